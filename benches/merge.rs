@@ -0,0 +1,56 @@
+//! Compares the in-memory and streaming merge strategies on generated packs of increasing size.
+//!
+//! Run with `cargo bench`. The crossover point is where the streaming strategy stops being
+//! slower than the in-memory one; use it to pick `MergeOptions::streaming_threshold_bytes`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use resource_merger::{merge_packs_to_file_with_options, MergeOptions, PackInput};
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+/// Create a directory pack with `files` files of `size` bytes each.
+fn make_pack(root: &Path, name: &str, files: usize, size: usize) -> PackInput {
+    let dir = root.join(name);
+    create_dir_all(dir.join("assets/bench/textures")).unwrap();
+    write(
+        dir.join("pack.mcmeta"),
+        r#"{"pack":{"pack_format":34,"description":"bench"}}"#,
+    )
+    .unwrap();
+    let body: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    for i in 0..files {
+        write(dir.join(format!("assets/bench/textures/{}.bin", i)), &body).unwrap();
+    }
+    PackInput::Dir(dir)
+}
+
+fn bench_strategies(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("merge_strategy");
+    group.sample_size(10);
+
+    for (files, size) in [(64, 4 * 1024), (256, 16 * 1024), (512, 64 * 1024)] {
+        let label = format!("{}x{}KiB", files, size / 1024);
+        let packs = vec![
+            make_pack(tmp.path(), &format!("{}_a", label), files, size),
+            make_pack(tmp.path(), &format!("{}_b", label), files, size),
+        ];
+        group.throughput(Throughput::Bytes((2 * files * size) as u64));
+        let out = tmp.path().join(format!("{}.zip", label));
+
+        for (name, threshold) in [("in_memory", None), ("streaming", Some(0))] {
+            let opts = MergeOptions {
+                streaming_threshold_bytes: threshold,
+                atomic: false,
+                ..Default::default()
+            };
+            group.bench_with_input(BenchmarkId::new(name, &label), &packs, |b, packs| {
+                b.iter(|| merge_packs_to_file_with_options(packs, &out, &opts).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_strategies);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+/// Pick the default pack.png embedded by the library: the file named by
+/// `RESOURCE_MERGER_DEFAULT_PNG` if set, otherwise the bundled `assets/default-pack-64.png`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=RESOURCE_MERGER_DEFAULT_PNG");
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let png = match std::env::var_os("RESOURCE_MERGER_DEFAULT_PNG") {
+        Some(p) if !p.is_empty() => {
+            let path = PathBuf::from(p);
+            let path = if path.is_absolute() {
+                path
+            } else {
+                manifest_dir.join(path)
+            };
+            let bytes = std::fs::read(&path).unwrap_or_else(|e| {
+                panic!(
+                    "RESOURCE_MERGER_DEFAULT_PNG: cannot read {}: {}",
+                    path.display(),
+                    e
+                )
+            });
+            if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+                panic!(
+                    "RESOURCE_MERGER_DEFAULT_PNG: {} is not a PNG file",
+                    path.display()
+                );
+            }
+            path
+        }
+        _ => manifest_dir.join("assets").join("default-pack-64.png"),
+    };
+    println!("cargo:rerun-if-changed={}", png.display());
+    println!(
+        "cargo:rustc-env=RESOURCE_MERGER_DEFAULT_PNG_PATH={}",
+        png.display()
+    );
+}
@@ -1,4 +1,4 @@
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, Parser, Subcommand};
 use std::path::PathBuf;
 
 /// Merge Minecraft resource packs into a single zip. Later inputs overwrite earlier ones.
@@ -9,9 +9,13 @@ use std::path::PathBuf;
     about,
     long_about = None,
     // If no args are provided, show help instead of silently failing
-    arg_required_else_help = true
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Output zip path
     #[arg(
         short,
@@ -27,13 +31,13 @@ struct Args {
         help = "Input packs (directories, zip files, or HTTP/HTTPS URLs). Order matters; later inputs override earlier ones."
     )]
     inputs: Vec<PathBuf>,
-    /// Read inputs from a config file (JSON); entries from the config will be used first
+    /// Read inputs from config files (JSON); entries from the configs will be used first
     #[arg(
         long,
         value_name = "PATH",
-        help = "Path to a JSON config file that mirrors CLI options. Values from the CLI override config."
+        help = "Path to a JSON config file that mirrors CLI options. Repeatable: later configs override earlier ones and inputs are concatenated. Values from the CLI override config."
     )]
-    config: Option<PathBuf>,
+    config: Vec<PathBuf>,
     /// Write output as a directory instead of a zip file
     #[arg(
         long,
@@ -94,6 +98,13 @@ struct Args {
         help = "Supported formats synthesis policy: one-to-highest|lowest-to-highest|one-to-latest."
     )]
     supported_formats: Option<String>,
+    /// Default output compression: stored, fast or best
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help = "Output compression: stored|fast|best (default: deflate at the default level). Overrides config.compression."
+    )]
+    compression: Option<String>,
 
     /// Optional pack description to include in generated pack.mcmeta (overrides config)
     #[arg(
@@ -108,6 +119,107 @@ struct Args {
         help = "Continue when input URLs fail to download or aren't valid zips (warn and skip)."
     )]
     tolerate_missing: bool,
+    /// Which URL download failures skip the input: skip-all, skip-transient, error-all
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "Which URL download failures skip the input: skip-all|skip-transient|error-all (default: error-all). skip-transient skips unreachable hosts, timeouts and HTTP 5xx but fails on e.g. a 404. Overrides config.url_failure_policy."
+    )]
+    url_failure_policy: Option<String>,
+    /// Keep only these namespaces under assets/ and data/ (repeatable)
+    #[arg(
+        long = "namespace",
+        value_name = "NS",
+        help = "Keep only assets/<NS>/ and data/<NS>/ entries for the given namespace. Repeatable; overrides config.namespaces."
+    )]
+    namespaces: Vec<String>,
+    /// Drop these namespaces under assets/ and data/ (repeatable)
+    #[arg(
+        long = "deny-namespace",
+        value_name = "NS",
+        help = "Drop assets/<NS>/ and data/<NS>/ entries for the given namespace, after --namespace filtering. Repeatable; overrides config.deny_namespaces."
+    )]
+    deny_namespaces: Vec<String>,
+    /// Union datapack tag values across inputs instead of overwriting
+    #[arg(
+        long,
+        help = "Union the values of data/<ns>/tags/**.json files across inputs instead of overwriting them."
+    )]
+    merge_tags: bool,
+    /// Concatenate font providers across inputs instead of overwriting
+    #[arg(
+        long,
+        help = "Concatenate the providers of assets/<ns>/font/**.json files across inputs instead of overwriting them."
+    )]
+    merge_fonts: bool,
+    /// Merge the JSON files Minecraft combines across packs
+    #[arg(
+        long,
+        help = "Merge tags, fonts, lang files, sounds.json and atlas sources across inputs instead of overwriting them."
+    )]
+    smart_json_merge: bool,
+    /// Write a SHA-256 sidecar next to the output
+    #[arg(
+        long,
+        help = "Write <out>.sha256 next to a zip output, or a SHA256SUMS file inside a directory output."
+    )]
+    checksum_sidecar: bool,
+    /// Print the resolved settings as JSON and exit without merging
+    #[arg(
+        long,
+        help = "Print the effective settings (after applying CLI-over-config precedence) as JSON and exit without merging."
+    )]
+    print_config: bool,
+    /// Only use config inputs whose for_formats range includes this format
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Target pack format: config inputs with a for_formats range that excludes N are skipped. Overrides config.target_format."
+    )]
+    target_format: Option<u32>,
+    /// Paths (globs) that may be provided by more than one input (repeatable)
+    #[arg(
+        long = "allow-conflict",
+        value_name = "GLOB",
+        help = "Paths matching GLOB may be provided by several inputs: exempt from --overwrite error and --fail-on-conflict. Repeatable; overrides config.allowed_conflicts."
+    )]
+    allowed_conflicts: Vec<String>,
+    /// Exit with code 3 if any path is provided by more than one input
+    #[arg(
+        long,
+        help = "Exit with code 3 and list the paths when any path (outside --allow-conflict) is provided by more than one input. Combine with --dry-run to check without writing."
+    )]
+    fail_on_conflict: bool,
+    /// Treat URL inputs ending in `/` as directory listings of zips
+    #[arg(
+        long,
+        help = "Treat URL inputs ending in / as HTML directory listings: every linked .zip is downloaded and merged in link order. Needs the dir-listing feature."
+    )]
+    expand_url_listings: bool,
+    /// Extra files to add to the output, as SRC:DEST (repeatable)
+    #[arg(
+        long = "add-file",
+        value_name = "SRC:DEST",
+        help = "Add the file SRC to the output at path DEST, after all inputs and following the overwrite policy. Repeatable; overrides config.add_files."
+    )]
+    add_files: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print an example JSON config with every option and its default value
+    Init,
+    /// Check the inputs without writing anything and print what was found. Exits with 3 if
+    /// there are conflicts, a too-wide format span, paths with characters Windows rejects or
+    /// missing model textures
+    Analyze {
+        /// Config files to take inputs and options from, as for a merge
+        #[arg(long, value_name = "PATH")]
+        config: Vec<PathBuf>,
+        /// Input packs, checked after the config's inputs
+        #[arg(value_name = "INPUTS")]
+        inputs: Vec<PathBuf>,
+    },
 }
 
 fn main() {
@@ -123,163 +235,306 @@ fn main() {
         }
     };
 
-    // Build input list from config (if any) and positional args.
-    let mut inputs: Vec<resource_merger::PackInput> = Vec::new();
-    let mut cfg_obj: Option<resource_merger::Config> = None;
-    if let Some(cfg_path) = &args.config {
-        match resource_merger::read_config_file(cfg_path) {
-            Ok(c) => cfg_obj = Some(c),
-            Err(e) => {
-                eprintln!("failed to read config {}: {}", cfg_path.display(), e);
-                std::process::exit(2);
-            }
+    match &args.command {
+        Some(Command::Init) => {
+            print!("{}", resource_merger::Config::example_json());
+            return;
         }
+        Some(Command::Analyze { config, inputs }) => analyze(config, inputs),
+        None => {}
     }
 
-    // If config has inputs, add them first
-    if let Some(cfg) = &cfg_obj {
-        if let Some(cfg_inputs) = &cfg.inputs {
-            for s in cfg_inputs {
-                inputs.push(resource_merger::PackInput::from(s.clone()));
-            }
+    // Build input list from config (if any) and positional args.
+    let cfg_obj = read_configs(&args.config);
+
+    // Flags are layered on top of the config files (CLI -> config -> default); the library then
+    // resolves the combined config into options
+    let flags = resource_merger::Config {
+        overwrite: args.overwrite.clone(),
+        dry_run: args.dry_run.then_some(true),
+        buffer_size: args.buffer_size,
+        // atomic: --atomic sets true, --no-atomic sets false, otherwise config or default true
+        atomic: if args.atomic {
+            Some(true)
+        } else if args.no_atomic {
+            Some(false)
+        } else {
+            None
+        },
+        preserve_timestamps: args.preserve_timestamps.then_some(true),
+        pack_format: args.pack_format,
+        supported_formats: args.supported_formats.clone(),
+        dir: args.dir.then_some(true),
+        description: args.description.clone(),
+        tolerate_missing_inputs: args.tolerate_missing.then_some(true),
+        url_failure_policy: args.url_failure_policy.clone(),
+        namespaces: (!args.namespaces.is_empty()).then(|| args.namespaces.clone()),
+        deny_namespaces: (!args.deny_namespaces.is_empty()).then(|| args.deny_namespaces.clone()),
+        merge_tags: args.merge_tags.then_some(true),
+        merge_fonts: args.merge_fonts.then_some(true),
+        smart_json_merge: args.smart_json_merge.then_some(true),
+        target_format: args.target_format,
+        allowed_conflicts: (!args.allowed_conflicts.is_empty())
+            .then(|| args.allowed_conflicts.clone()),
+        fail_on_conflict: args.fail_on_conflict.then_some(true),
+        expand_url_listings: args.expand_url_listings.then_some(true),
+        compression: args.compression.clone(),
+        add_files: (!args.add_files.is_empty()).then(|| args.add_files.clone()),
+        ..Default::default()
+    };
+    let cfg = cfg_obj.merge(flags);
+    let cli_inputs = positional_inputs(&args.inputs);
+
+    // Config inputs come first, then positional inputs, then config inputs marked force_top
+    let zip_passwords = cfg.zip_passwords(cli_inputs.len());
+    let inputs = match cfg.pack_inputs(cli_inputs) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
         }
-    }
+    };
 
-    // Add positional inputs
-    for p in &args.inputs {
-        if !p.exists() {
-            eprintln!("input path does not exist: {}", p.display());
+    // Determine output path: CLI `--out` takes precedence, otherwise try config `out`.
+    let out_path: PathBuf = match (&args.out, &cfg.out) {
+        (Some(o), _) => o.clone(),
+        (None, Some(co)) => PathBuf::from(co),
+        (None, None) => {
+            eprintln!("no output path provided; pass --out or add `out` to config");
             std::process::exit(2);
         }
-        inputs.push(p.clone().into());
-    }
+    };
+    let dir_flag = cfg.dir.unwrap_or(false);
+    let also_emit_zip = cfg.also_emit_zip.clone().map(PathBuf::from);
+    let fail_on_conflict = cfg.fail_on_conflict.unwrap_or(false);
+    // Only a config file can set the hook, so running it needs the same trust as the config
+    let post_merge_command = cfg.post_merge_command.clone();
 
-    // Build options with clear precedence: CLI (Some) -> config -> default
-    let overwrite = if let Some(s) = &args.overwrite {
-        match s.parse::<resource_merger::OverwritePolicy>() {
-            Ok(o) => o,
-            Err(e) => {
-                eprintln!("invalid overwrite value: {}", e);
-                std::process::exit(2);
-            }
+    let mut opts = match cfg.into_options() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
         }
-    } else if let Some(cfg) = &cfg_obj {
-        if let Some(s) = &cfg.overwrite {
-            match s.parse::<resource_merger::OverwritePolicy>() {
-                Ok(o) => o,
+    };
+    opts.write_checksum_sidecar = args.checksum_sidecar;
+    opts.zip_passwords = zip_passwords;
+    // Environment variables are the lowest-precedence source, below config and CLI
+    if opts.pack_format_override.is_none() {
+        if let Ok(s) = std::env::var("RESOURCE_MERGER_PACK_FORMAT") {
+            match s.trim().parse::<u32>() {
+                Ok(n) => opts.pack_format_override = Some(n),
                 Err(e) => {
-                    eprintln!("invalid overwrite value in config: {}", e);
+                    eprintln!("invalid RESOURCE_MERGER_PACK_FORMAT value {:?}: {}", s, e);
                     std::process::exit(2);
                 }
             }
-        } else {
-            resource_merger::OverwritePolicy::LastWins
         }
-    } else {
-        resource_merger::OverwritePolicy::LastWins
-    };
+    }
+    if opts.description_override.is_none() {
+        opts.description_override = std::env::var("RESOURCE_MERGER_DESCRIPTION").ok();
+    }
 
-    let dry_run = if args.dry_run {
-        true
-    } else {
-        cfg_obj.as_ref().and_then(|c| c.dry_run).unwrap_or(false)
+    let settings = resource_merger::Settings {
+        inputs,
+        out: out_path,
+        dir: dir_flag,
+        also_emit_zip,
+        options: opts,
     };
 
-    let buffer_size = args
-        .buffer_size
-        .or_else(|| cfg_obj.as_ref().and_then(|c| c.buffer_size))
-        .unwrap_or(32 * 1024);
+    if args.print_config {
+        match serde_json::to_string_pretty(&settings) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                eprintln!("failed to serialize settings: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    // atomic: CLI --atomic sets true; --no-atomic sets false; otherwise use config or default true
-    let atomic = if args.atomic {
-        true
-    } else if args.no_atomic {
-        false
-    } else {
-        cfg_obj.as_ref().and_then(|c| c.atomic).unwrap_or(true)
-    };
+    if fail_on_conflict {
+        match resource_merger::find_conflicts(&settings.inputs, &settings.options) {
+            Ok(conflicts) if !conflicts.is_empty() => {
+                eprintln!("paths provided by more than one input:");
+                for path in &conflicts {
+                    eprintln!("  {}", path);
+                }
+                std::process::exit(3);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("error checking conflicts: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    let preserve_timestamps = if args.preserve_timestamps {
-        true
-    } else {
-        cfg_obj
-            .as_ref()
-            .and_then(|c| c.preserve_timestamps)
-            .unwrap_or(false)
-    };
+    if let Err(e) = resource_merger::run_with_settings(&settings) {
+        eprintln!("error merging packs: {}", e);
+        std::process::exit(1);
+    }
 
-    let pack_format_override = args
-        .pack_format
-        .or_else(|| cfg_obj.as_ref().and_then(|c| c.pack_format));
+    println!("Wrote merged output to {}", settings.out.display());
 
-    let supported_formats_str: Option<String> = args
-        .supported_formats
-        .clone()
-        .or_else(|| cfg_obj.as_ref().and_then(|c| c.supported_formats.clone()));
+    if let (Some(template), false) = (post_merge_command, settings.options.dry_run) {
+        // `{out}` is substituted as one quoted word; the path is also in RESOURCE_MERGER_OUT
+        let out_word = shell_quote(&settings.out.display().to_string());
+        let command = template.replace("{out}", &out_word);
+        let status = shell_command(&command)
+            .env("RESOURCE_MERGER_OUT", &settings.out)
+            .status();
+        match status {
+            Ok(s) if s.success() => {}
+            Ok(s) => {
+                eprintln!("post-merge command failed ({}): {}", s, command);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("error running post-merge command: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
 
-    let supported_formats_policy = match supported_formats_str {
-        Some(s) => match s.parse::<resource_merger::SupportedFormatsPolicy>() {
-            Ok(p) => p,
+/// Read and combine the config files in order; later ones override earlier ones.
+fn read_configs(paths: &[PathBuf]) -> resource_merger::Config {
+    let mut cfg_obj: Option<resource_merger::Config> = None;
+    for cfg_path in paths {
+        match resource_merger::read_config_file(cfg_path) {
+            Ok(c) => {
+                cfg_obj = Some(match cfg_obj.take() {
+                    Some(prev) => prev.merge(c),
+                    None => c,
+                })
+            }
             Err(e) => {
-                eprintln!("invalid supported_formats value in config or args: {}", e);
+                eprintln!("failed to read config {}: {}", cfg_path.display(), e);
                 std::process::exit(2);
             }
-        },
-        None => resource_merger::SupportedFormatsPolicy::OneToHighest,
-    };
+        }
+    }
+    cfg_obj.unwrap_or_default()
+}
 
-    let opts = resource_merger::MergeOptions {
-        overwrite,
-        dry_run,
-        buffer_size,
-        atomic,
-        preserve_timestamps,
-        pack_format_override,
-        supported_formats_policy,
-        description_override: args
-            .description
-            .clone()
-            .or_else(|| cfg_obj.as_ref().and_then(|c| c.description.clone())),
-        tolerate_missing_inputs: if args.tolerate_missing {
-            true
-        } else {
-            cfg_obj
-                .as_ref()
-                .and_then(|c| c.tolerate_missing_inputs)
-                .unwrap_or(false)
-        },
+/// Positional inputs, exiting if one of them doesn't exist.
+fn positional_inputs(paths: &[PathBuf]) -> Vec<resource_merger::PackInput> {
+    let mut cli_inputs: Vec<resource_merger::PackInput> = Vec::new();
+    for p in paths {
+        if !p.exists() {
+            eprintln!("input path does not exist: {}", p.display());
+            std::process::exit(2);
+        }
+        cli_inputs.push(p.clone().into());
+    }
+    cli_inputs
+}
+
+/// Run `analyze` on the inputs, print the findings and exit.
+fn analyze(config: &[PathBuf], paths: &[PathBuf]) -> ! {
+    let cfg = read_configs(config);
+    let cli_inputs = positional_inputs(paths);
+    let zip_passwords = cfg.zip_passwords(cli_inputs.len());
+    let inputs = match cfg.pack_inputs(cli_inputs) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
     };
-    // Determine output path: CLI `--out` takes precedence, otherwise try config `out`.
-    let out_path: PathBuf = if let Some(o) = &args.out {
-        o.clone()
-    } else if let Some(cfg) = &cfg_obj {
-        if let Some(co) = &cfg.out {
-            PathBuf::from(co)
-        } else {
-            eprintln!("no output path provided; pass --out or add `out` to config");
+    let mut opts = match cfg.into_options() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
             std::process::exit(2);
         }
-    } else {
-        eprintln!("no output path provided; pass --out or add `out` to config");
-        std::process::exit(2);
     };
-
-    let dir_flag = if args.dir {
-        true
-    } else {
-        cfg_obj.as_ref().and_then(|c| c.dir).unwrap_or(false)
+    opts.zip_passwords = zip_passwords;
+    let analysis = match resource_merger::analyze(&inputs, &opts) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("error analyzing packs: {}", e);
+            std::process::exit(1);
+        }
     };
 
-    let res = if dir_flag {
-        resource_merger::merge_packs_to_dir(&inputs, &out_path, &opts)
-    } else {
-        resource_merger::merge_packs_to_file_with_options(&inputs, &out_path, &opts)
-    };
+    if !analysis.conflicts.is_empty() {
+        println!("paths provided by more than one input:");
+        for path in &analysis.conflicts {
+            println!("  {}", path);
+        }
+    }
+    if let Some(span) = &analysis.format_span {
+        println!("{}", span);
+    }
+    if !analysis.path_lints.is_empty() {
+        println!("problematic paths:");
+        for lint in &analysis.path_lints {
+            let reason = match lint.reason {
+                resource_merger::PathLintReason::Uppercase => "uppercase letters".to_string(),
+                resource_merger::PathLintReason::Whitespace => "whitespace".to_string(),
+                resource_merger::PathLintReason::IllegalCharacter(c) => {
+                    format!("illegal character {:?}", c)
+                }
+            };
+            println!("  {} ({})", lint.path, reason);
+        }
+    }
+    if !analysis.missing_textures.is_empty() {
+        println!("missing model textures:");
+        for m in &analysis.missing_textures {
+            println!("  {} needs {}", m.model, m.texture);
+        }
+    }
+    if !analysis.duplicate_contents.is_empty() {
+        println!("files with identical contents:");
+        for dup in &analysis.duplicate_contents {
+            println!("  {} ({} bytes)", dup.paths.join(", "), dup.size);
+        }
+    }
+    if !analysis.identical_overlay_files.is_empty() {
+        println!("identical overlay files:");
+        for file in &analysis.identical_overlay_files {
+            let base = if file.matches_base { ", base" } else { "" };
+            println!("  {} in {}{}", file.path, file.directories.join(", "), base);
+        }
+    }
 
-    if let Err(e) = res {
-        eprintln!("error merging packs: {}", e);
-        std::process::exit(1);
+    if analysis.has_errors() {
+        std::process::exit(3);
+    }
+    if analysis.duplicate_contents.is_empty() && analysis.identical_overlay_files.is_empty() {
+        println!("no problems found");
+    }
+    std::process::exit(0);
+}
+
+/// Quote `path` as a single word for the shell that runs `post_merge_command`.
+fn shell_quote(path: &str) -> String {
+    if cfg!(windows) {
+        // Windows paths can't contain `"`
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
     }
+}
+
+/// The platform shell invocation for `command`: `cmd /C` on Windows, `sh -c` elsewhere.
+#[cfg(windows)]
+fn shell_command(command: &str) -> std::process::Command {
+    use std::os::windows::process::CommandExt;
+    let mut cmd = std::process::Command::new("cmd");
+    // Passed verbatim: cmd doesn't understand the backslash escaping of regular args
+    cmd.arg("/C").raw_arg(command);
+    cmd
+}
 
-    println!("Wrote merged output to {}", out_path.display());
+/// The platform shell invocation for `command`: `cmd /C` on Windows, `sh -c` elsewhere.
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> std::process::Command {
+    let mut cmd = std::process::Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
 }
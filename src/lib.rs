@@ -3,10 +3,13 @@
 //! Exposes a small API to merge multiple resource packs (directories, zip bytes, or zip files)
 //! into a single zip where later packs overwrite earlier ones.
 
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use thiserror::Error;
 use walkdir::WalkDir;
 use zip::{ZipArchive, ZipWriter};
@@ -19,21 +22,64 @@ pub enum MergeError {
     Zip(#[from] zip::result::ZipError),
     #[error("invalid input: {0}")]
     InvalidInput(String),
+    #[error("merge was cancelled")]
+    Cancelled,
+    #[error("timed out reading input: {0}")]
+    Timeout(String),
+    #[error("merged output doesn't match the manifest:\n{0}")]
+    ManifestMismatch(String),
+    #[error("{0}")]
+    Unreachable(String),
+    #[error("GET {url} returned HTTP {status}")]
+    HttpStatus { url: String, status: u16 },
 }
 
 pub type Result<T> = std::result::Result<T, MergeError>;
 
 /// How to handle multiple inputs that contain the same internal path.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OverwritePolicy {
+    #[serde(rename = "last")]
     LastWins,
+    #[serde(rename = "first")]
     FirstWins,
+    #[serde(rename = "error")]
     ErrorIfConflict,
+    #[serde(rename = "skip")]
     SkipIfExists,
 }
 
+/// What to do when a URL input can't be downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UrlFailurePolicy {
+    /// Warn and skip the input on any failure
+    SkipAll,
+    /// Skip on failures that may go away on a retry (unreachable host, timeout, HTTP 5xx, 408
+    /// or 429), but fail on permanent ones such as a 404 or a response that isn't a zip
+    SkipTransientErrorPermanent,
+    /// Fail the merge on any failure
+    #[default]
+    ErrorAll,
+}
+
+impl std::str::FromStr for UrlFailurePolicy {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip-all" | "skip_all" | "skip" => Ok(UrlFailurePolicy::SkipAll),
+            "skip-transient" | "skip_transient" | "skip-transient-error-permanent" => {
+                Ok(UrlFailurePolicy::SkipTransientErrorPermanent)
+            }
+            "error-all" | "error_all" | "error" => Ok(UrlFailurePolicy::ErrorAll),
+            other => Err(format!("unknown URL failure policy: {}", other)),
+        }
+    }
+}
+
 /// How to synthesize the supported_formats array in pack.mcmeta
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum SupportedFormatsPolicy {
     /// [1, highest_found]
     OneToHighest,
@@ -59,6 +105,16 @@ impl std::str::FromStr for SupportedFormatsPolicy {
     }
 }
 
+impl std::fmt::Display for SupportedFormatsPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SupportedFormatsPolicy::OneToHighest => "one-to-highest",
+            SupportedFormatsPolicy::LowestToHighest => "lowest-to-highest",
+            SupportedFormatsPolicy::OneToLatest => "one-to-latest",
+        })
+    }
+}
+
 impl std::str::FromStr for OverwritePolicy {
     type Err = String;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
@@ -74,8 +130,176 @@ impl std::str::FromStr for OverwritePolicy {
     }
 }
 
+impl std::fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OverwritePolicy::LastWins => "last",
+            OverwritePolicy::FirstWins => "first",
+            OverwritePolicy::ErrorIfConflict => "error",
+            OverwritePolicy::SkipIfExists => "skip",
+        })
+    }
+}
+
+/// Compression to use for an output zip entry.
+///
+/// Serializes as `"stored"` or `{ "deflated": <level or null> }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionChoice {
+    /// Store the entry without compression (best for already-compressed PNG/OGG files)
+    Stored,
+    /// Deflate the entry, optionally at a specific level (0-9; None uses the default level)
+    Deflated(Option<i64>),
+}
+
+impl std::str::FromStr for CompressionChoice {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stored" | "store" | "none" => Ok(CompressionChoice::Stored),
+            "fast" | "fastest" => Ok(CompressionChoice::Deflated(Some(1))),
+            "best" => Ok(CompressionChoice::Deflated(Some(9))),
+            "deflated" | "default" => Ok(CompressionChoice::Deflated(None)),
+            other => Err(format!("unknown compression: {}", other)),
+        }
+    }
+}
+
+/// What to do when a single archive input contains two entries with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateEntryPolicy {
+    /// Keep the first entry and warn
+    KeepFirst,
+    /// Keep the last entry and warn
+    KeepLast,
+    /// Fail the merge with [`MergeError::InvalidInput`]
+    Error,
+}
+
+/// Where the output's pack.mcmeta, pack.png and README.md come from. README.md is carried
+/// from the last input even under `Synthesize` unless [`MergeOptions::generate_readme`] is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataSource {
+    /// Carry the first input's files (the base pack); synthesize the ones it doesn't provide
+    FirstInput,
+    /// Carry the last provided version of each file; synthesize the ones no input provides
+    LastInput,
+    /// Always synthesize the files from the merged inputs
+    Synthesize,
+}
+
+/// Signature of a per-file transform: receives the entry path and its bytes and returns the
+/// bytes to write instead.
+pub type TransformFn = dyn Fn(&str, Vec<u8>) -> Result<Vec<u8>> + Send + Sync;
+
+/// A transform applied to every output entry whose path matches `pattern`.
+///
+/// Patterns use `/` separators: `*` matches within one path segment, `**` matches across
+/// segments and `?` matches one character, e.g. `assets/**/*.json`.
+#[derive(Clone)]
+pub struct FileTransform {
+    pub pattern: String,
+    pub transform: Arc<TransformFn>,
+}
+
+impl FileTransform {
+    pub fn new(
+        pattern: impl Into<String>,
+        transform: impl Fn(&str, Vec<u8>) -> Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        FileTransform {
+            pattern: pattern.into(),
+            transform: Arc::new(transform),
+        }
+    }
+}
+
+impl std::fmt::Debug for FileTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileTransform")
+            .field("pattern", &self.pattern)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Order in which entries are written to the output zip. Only the order changes, never the
+/// contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntryOrder {
+    /// Merged entries in lexical order, then pack.mcmeta, pack.png and README.md
+    Lexical,
+    /// pack.mcmeta and pack.png first, then `assets/` entries, then everything else; each group
+    /// in lexical order
+    McmetaFirst,
+}
+
+/// How the output's pack.png is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackPngPolicy {
+    /// Use the embedded default icon, unless `metadata_from`, `keep_input_pack_png` or
+    /// `resize_png_to` carries an input's pack.png
+    Default,
+    /// Keep the input pack.png with the largest square dimensions; the default icon is used if
+    /// no input icon decodes as a square
+    HighestResolution,
+}
+
+/// Hook that rewrites the synthesized pack.mcmeta JSON right before it is serialized.
+#[derive(Clone)]
+pub struct McmetaPostProcess(pub Arc<dyn Fn(serde_json::Value) -> serde_json::Value + Send + Sync>);
+
+impl McmetaPostProcess {
+    pub fn new(f: impl Fn(serde_json::Value) -> serde_json::Value + Send + Sync + 'static) -> Self {
+        McmetaPostProcess(Arc::new(f))
+    }
+}
+
+impl std::fmt::Debug for McmetaPostProcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("McmetaPostProcess(..)")
+    }
+}
+
+/// A step of a merge, as passed to [`MergeOptions::progress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Input `index` (of `total`) is about to be read
+    InputStarted { index: usize, total: usize },
+    /// Output entry `index` (of `total`, counting the root files) has been written to the zip
+    EntryWritten {
+        index: usize,
+        total: usize,
+        path: String,
+    },
+}
+
+/// Callback receiving [`ProgressEvent`]s while a merge runs.
+#[derive(Clone)]
+pub struct ProgressHook(pub Arc<dyn Fn(ProgressEvent) + Send + Sync>);
+
+impl ProgressHook {
+    pub fn new(f: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        ProgressHook(Arc::new(f))
+    }
+}
+
+impl std::fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook(..)")
+    }
+}
+
 /// Options that control merge behavior. New fields can be added as the library expands.
-#[derive(Debug, Clone)]
+///
+/// Serializes with the policy enums in their canonical string form (e.g. `"last"`,
+/// `"one-to-highest"`). Missing fields deserialize to their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MergeOptions {
     pub overwrite: OverwritePolicy,
     pub dry_run: bool,
@@ -88,8 +312,210 @@ pub struct MergeOptions {
     pub supported_formats_policy: SupportedFormatsPolicy,
     /// Optional description to use in generated pack.mcmeta
     pub description_override: Option<String>,
-    /// If true, continue when input URLs fail to download or aren't valid zips (warn and skip)
+    /// If true, continue when input URLs fail to download or aren't valid zips (warn and skip).
+    /// Same as `url_failure_policy: SkipAll`, which it takes precedence over.
     pub tolerate_missing_inputs: bool,
+    /// Which URL download failures skip the input (with a warning) instead of failing the merge
+    pub url_failure_policy: UrlFailurePolicy,
+    /// Passwords for encrypted zip inputs, keyed by the input's position in the `packs` slice
+    /// given to the merge. Applies to zip files, zip bytes and downloaded URLs; other inputs
+    /// ignore it. Positions don't shift when inputs are skipped during the merge (e.g. by
+    /// `drop_inputs_below_format`). For inputs from a [`Config`], set `password` on the input
+    /// instead: [`Config::into_settings`] and the CLI map it to the input's position after
+    /// `force_top` and `for_formats` have been applied.
+    pub zip_passwords: HashMap<usize, String>,
+    /// If true, check that an input pack.png is square and at least 64x64 (warn only)
+    pub validate_png_dimensions: bool,
+    /// If set, rescale an input pack.png to an NxN square and carry it into the output
+    pub resize_png_to: Option<u32>,
+    /// If set, keep only `assets/<ns>/**` and `data/<ns>/**` entries for these namespaces
+    /// (plus root files such as pack.mcmeta)
+    pub namespaces: Option<Vec<String>>,
+    /// Drop `assets/<ns>/**` and `data/<ns>/**` entries (also inside overlay directories) for
+    /// these namespaces, e.g. to remove one mod's overrides. Applied after `namespaces`.
+    pub deny_namespaces: Vec<String>,
+    /// If true, union the `values` of `data/<ns>/tags/**.json` files across inputs instead of
+    /// overwriting them (a later tag with `"replace": true` still resets the list)
+    pub merge_tags: bool,
+    /// If true, concatenate the `providers` of `assets/<ns>/font/**.json` files across inputs
+    /// (later inputs appended, identical providers kept once) instead of overwriting them
+    pub merge_fonts: bool,
+    /// If true, merge the JSON files Minecraft itself combines across packs instead of
+    /// overwriting them: everything `merge_tags` and `merge_fonts` cover, plus lang files and
+    /// `sounds.json` (keys unioned, later inputs win per key) and atlas `sources` (appended)
+    pub smart_json_merge: bool,
+    /// If set, apply every overlay whose format range covers this format onto the base files,
+    /// then drop the overlay directories and the `overlays` section from pack.mcmeta
+    pub flatten_overlays_to: Option<u32>,
+    /// Cancel token checked between inputs and between file writes. When it becomes true the
+    /// merge stops with [`MergeError::Cancelled`].
+    #[serde(skip)]
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Called as inputs are read and as entries are written to an output zip
+    #[serde(skip)]
+    pub progress: Option<ProgressHook>,
+    /// Compression per file extension (lowercase, without the dot, e.g. `"png"`). Entries whose
+    /// extension isn't listed use `compression`.
+    pub per_extension_compression: HashMap<String, CompressionChoice>,
+    /// Default compression for output zip entries (None = deflate at the default level)
+    pub compression: Option<CompressionChoice>,
+    /// If set and the estimated total input size exceeds this many bytes, file and directory
+    /// output use the streaming strategy: on-disk entries are copied one at a time instead of
+    /// loading every input into memory first. Below the threshold the in-memory path is used.
+    pub streaming_threshold_bytes: Option<u64>,
+    /// If true, carry the last input's pack.mcmeta, pack.png and README.md into the output
+    /// instead of synthesizing them. Only the files no input provided are synthesized.
+    /// Shorthand for `metadata_from: LastInput`; ignored when `metadata_from` is set otherwise.
+    pub prefer_input_metadata: bool,
+    /// Directory output only: after writing, remove any empty directories left in the output
+    /// tree (e.g. from a previous run)
+    pub prune_empty_dirs: bool,
+    /// Directory output only: write a `.gitattributes` at the output root marking common
+    /// binary asset extensions as binary
+    pub write_gitattributes: bool,
+    /// If true, the generated README.md lists every file that was provided by more than one
+    /// input, together with the input whose version won. Implies `generate_readme`.
+    pub include_conflict_log: bool,
+    /// If true, always write a generated README.md listing the inputs, even when an input
+    /// ships its own. By default the last input's README.md is carried and one is generated
+    /// only if no input provides it.
+    pub generate_readme: bool,
+    /// How to handle a zip or tar input that contains the same entry name twice (after
+    /// normalizing separators). Entries with byte-identical names are collapsed by the zip
+    /// reader itself, so for those only the last copy is available; `KeepFirst` warns instead.
+    pub on_duplicate_in_archive: DuplicateEntryPolicy,
+    /// Zip file output only: if the merged zip would be larger than this many bytes, write it as
+    /// numbered parts (`merged.part1.zip`, `merged.part2.zip`, ...) that each stay under the cap.
+    /// Every part is a standalone pack carrying the same pack.mcmeta, pack.png and README.md.
+    /// Leftovers of an earlier run (a higher-numbered part or an unsplit `merged.zip`) are removed.
+    pub split_output_bytes: Option<u64>,
+    /// Where the output's pack.mcmeta, pack.png and README.md come from
+    pub metadata_from: MetadataSource,
+    /// Transforms run on matching output entries (including the synthesized root files) right
+    /// before they are written. Every matching transform runs, in order.
+    #[serde(skip)]
+    pub transforms: Vec<FileTransform>,
+    /// Archive-level comment for zip output (e.g. build version, date or commit). Minecraft
+    /// ignores it.
+    pub zip_comment: Option<String>,
+    /// Order in which entries are written to zip output
+    pub entry_order: EntryOrder,
+    /// If true, every zip entry gets the same fixed timestamp (1980-01-01) instead of the
+    /// current time, so identical inputs produce byte-identical output
+    pub fixed_timestamps: bool,
+    /// If set, bound how long reading any single input may take (URL downloads, directory and
+    /// zip file reads). On timeout the read fails with [`MergeError::Timeout`].
+    pub per_input_timeout: Option<std::time::Duration>,
+    /// Called on the synthesized pack.mcmeta value right before it is serialized, e.g. to add
+    /// a custom field. Not called when an input's pack.mcmeta is carried instead.
+    #[serde(skip)]
+    pub mcmeta_post_process: Option<McmetaPostProcess>,
+    /// How the output's pack.png is chosen when inputs provide one
+    pub pack_png_policy: PackPngPolicy,
+    /// If set, inputs whose detected pack_format is below this floor are skipped with a warning
+    /// instead of merged. Inputs without a readable pack.mcmeta are always merged.
+    pub drop_inputs_below_format: Option<u32>,
+    /// If true, drop overlay copies of a file when every overlay's copy is byte-identical to the
+    /// base file at the same path, since applying them changes nothing. See
+    /// [`find_identical_overlay_files`] for a report without changing the output.
+    pub coalesce_identical_overlay_files: bool,
+    /// If true (the default), the generated pack.mcmeta description includes the crate version.
+    /// Disable it for output that stays identical across crate releases.
+    pub include_version_in_description: bool,
+    /// Under [`OverwritePolicy::ErrorIfConflict`], paths matching any of these globs (same syntax
+    /// as [`FileTransform`] patterns) may be provided by several inputs; the last one wins.
+    pub allowed_conflicts: Vec<String>,
+    /// If true, carry the winning input's root pack.png into the output unchanged; the embedded
+    /// default icon is only used when no input provides one
+    pub keep_input_pack_png: bool,
+    /// If true, parse every merged `*.mcmeta` entry (including sidecars such as texture
+    /// animations) and fail with [`MergeError::InvalidInput`] listing each one that isn't valid
+    /// JSON, with its path
+    pub validate_all_mcmeta: bool,
+    /// If set, a vanilla reference (directory or zip, e.g. the extracted client assets): every
+    /// merged file whose bytes equal the reference's file at the same path is dropped. Root
+    /// metadata files are never dropped. The reference is opened once per merge with this
+    /// merge's `per_input_timeout`, `filename_encoding` and `on_duplicate_in_archive`, and only
+    /// its entries at merged paths are read.
+    pub strip_vanilla_identical: Option<PathBuf>,
+    /// If true, every entry of a zip input (file, bytes or URL) is checked against its stored
+    /// CRC-32 before the input is used, and a mismatch fails the merge with
+    /// [`MergeError::InvalidInput`] naming the archive and entry. Without it a corrupt entry
+    /// surfaces as a generic I/O error, and only once it is copied.
+    pub verify_input_crc: bool,
+    /// Per-path overwrite policies as `(glob, policy)` pairs. The first rule whose glob matches
+    /// a conflicting path decides it; paths no rule matches use `overwrite`.
+    pub overwrite_rules: Vec<(String, OverwritePolicy)>,
+    /// Directory for the temp files and directories of atomic writes. It must be on the same
+    /// filesystem as the output so the final rename stays atomic; otherwise temps go next to
+    /// the output as usual, with a warning. Scratch files (split and directory output) are
+    /// created here too.
+    pub temp_dir: Option<PathBuf>,
+    /// Treat URL inputs ending in `/` as HTML directory listings: every linked `.zip` is
+    /// downloaded and merged in link order (later links overwrite earlier ones) as that one
+    /// input. Needs the `dir-listing` feature.
+    pub expand_url_listings: bool,
+    /// Record in each merged entry's zip extra field which input it came from, as a
+    /// [`PROVENANCE_EXTRA_FIELD_ID`] field. Minecraft ignores it; tooling can read it back.
+    pub embed_provenance_extra: bool,
+    /// Copy entries of zip inputs into the output as they are stored (Stored or Deflated),
+    /// instead of decompressing and recompressing them. Entries that are encrypted, use
+    /// another method, or are rewritten by transforms, `per_extension_compression`,
+    /// `compression`, `fixed_timestamps` or `embed_provenance_extra` are recompressed as usual.
+    pub preserve_input_compression: bool,
+    /// Reject any input that has neither a pack.mcmeta nor an `assets/` or `data/` folder at
+    /// its root (see [`classify_input`]), instead of merging whatever it contains.
+    pub require_valid_pack: bool,
+    /// Union the `language` blocks of every input's pack.mcmeta by language code (later inputs
+    /// win per code) and write the result into the output pack.mcmeta.
+    pub merge_language_block: bool,
+    /// Files to add to the output as `(path, bytes)`, e.g. credits or build info. They are
+    /// applied after every input and follow the overwrite policy like one more input would.
+    pub extra_files: Vec<(String, Vec<u8>)>,
+    /// Globs of paths to delete from the merged result just before it is written, whichever
+    /// input (or `extra_files`, or overlay flattening) produced them, e.g. `debug/**`.
+    pub remove: Vec<String>,
+    /// Read URL inputs through HTTP range requests when the server supports them: only the
+    /// zip's central directory and the entries that end up in the output are downloaded, in
+    /// 64 KiB chunks. Servers without range support are downloaded whole as usual, as are
+    /// servers whose range requests fail after advertising support; a failure of that download
+    /// is handled by `url_failure_policy`.
+    pub ranged_url_reads: bool,
+    /// After writing a zip file, also write `<out>.sha256` with its SHA-256 in the
+    /// `sha256sum` format (`<hex>  <file name>`). Directory output gets a `SHA256SUMS` file
+    /// listing every file in the directory instead.
+    pub write_checksum_sidecar: bool,
+    /// Warn when the pack_format values found across inputs span more than this many formats
+    /// (highest minus lowest), e.g. 6 and 48: the merged range likely claims formats the assets
+    /// can't honor. The warning is printed to stderr only by calls that write output; the
+    /// read-only ones return it instead ([`MergePreview::warnings`], [`Analysis::format_span`])
+    /// or, like [`logical_hash`] and [`merge_packs_to_json`], stay quiet
+    pub warn_on_wide_format_span: Option<u32>,
+    /// Fail with [`MergeError::InvalidInput`] instead of warning when
+    /// `warn_on_wide_format_span` is exceeded
+    pub strict_format_span: bool,
+    /// Fail with [`MergeError::InvalidInput`] when inputs declare the same overlay `directory`
+    /// with format ranges that neither overlap nor touch (e.g. `[10, 12]` and `[20, 22]`),
+    /// instead of silently keeping the later entry's range
+    pub strict_overlays: bool,
+    /// Write the synthesized pack.mcmeta as indented JSON (2 spaces) instead of a single line,
+    /// which is easier to read when debugging a pack by hand. Minecraft accepts both.
+    pub pretty_mcmeta: bool,
+    /// Check that an input pack.png carried into the output starts with the PNG signature, and
+    /// write the default icon (with a warning) if it doesn't. Turn off to force arbitrary icon
+    /// bytes, e.g. odd images Minecraft still loads; this also silences `validate_png_dimensions`.
+    pub validate_pack_png: bool,
+    /// Encoding of zip entry names that aren't flagged as UTF-8, e.g. `encoding_rs::SHIFT_JIS`
+    /// for some Japanese community packs. By default such names are decoded as CP437, as the
+    /// zip format specifies, which garbles names written in another code page. Serializes as
+    /// the encoding's name (e.g. `"Shift_JIS"`).
+    #[serde(with = "encoding_label")]
+    pub filename_encoding: NameEncoding,
+    /// Shrink text assets: `.json` and `.mcmeta` files are reserialized compactly and trailing
+    /// whitespace is trimmed from the lines of `.properties` and `.lang` files. Files that don't
+    /// parse, and binary files, are written unchanged. `pretty_mcmeta` still wins for the root
+    /// pack.mcmeta.
+    pub minify_text: bool,
 }
 
 impl Default for MergeOptions {
@@ -104,17 +530,192 @@ impl Default for MergeOptions {
             supported_formats_policy: SupportedFormatsPolicy::OneToHighest,
             description_override: None,
             tolerate_missing_inputs: false,
+            url_failure_policy: UrlFailurePolicy::ErrorAll,
+            zip_passwords: HashMap::new(),
+            validate_png_dimensions: false,
+            resize_png_to: None,
+            namespaces: None,
+            deny_namespaces: Vec::new(),
+            merge_tags: false,
+            merge_fonts: false,
+            smart_json_merge: false,
+            flatten_overlays_to: None,
+            cancel: None,
+            progress: None,
+            per_extension_compression: HashMap::new(),
+            compression: None,
+            streaming_threshold_bytes: None,
+            prefer_input_metadata: false,
+            prune_empty_dirs: false,
+            write_gitattributes: false,
+            include_conflict_log: false,
+            generate_readme: false,
+            on_duplicate_in_archive: DuplicateEntryPolicy::KeepLast,
+            split_output_bytes: None,
+            metadata_from: MetadataSource::Synthesize,
+            transforms: Vec::new(),
+            zip_comment: None,
+            entry_order: EntryOrder::Lexical,
+            fixed_timestamps: false,
+            per_input_timeout: None,
+            mcmeta_post_process: None,
+            pack_png_policy: PackPngPolicy::Default,
+            drop_inputs_below_format: None,
+            coalesce_identical_overlay_files: false,
+            include_version_in_description: true,
+            allowed_conflicts: Vec::new(),
+            keep_input_pack_png: false,
+            validate_all_mcmeta: false,
+            strip_vanilla_identical: None,
+            verify_input_crc: false,
+            overwrite_rules: Vec::new(),
+            temp_dir: None,
+            expand_url_listings: false,
+            embed_provenance_extra: false,
+            preserve_input_compression: false,
+            require_valid_pack: false,
+            merge_language_block: false,
+            extra_files: Vec::new(),
+            remove: Vec::new(),
+            ranged_url_reads: false,
+            write_checksum_sidecar: false,
+            warn_on_wide_format_span: None,
+            strict_format_span: false,
+            strict_overlays: false,
+            pretty_mcmeta: false,
+            validate_pack_png: true,
+            filename_encoding: None,
+            minify_text: false,
         }
     }
 }
 
-/// Represents an input pack. It can be a directory on disk, a zip file on disk, or raw zip bytes.
-#[derive(Debug, Clone)]
+impl MergeOptions {
+    /// The effective metadata source, honoring the `prefer_input_metadata` shorthand.
+    fn metadata_source(&self) -> MetadataSource {
+        match self.metadata_from {
+            MetadataSource::Synthesize if self.prefer_input_metadata => MetadataSource::LastInput,
+            source => source,
+        }
+    }
+
+    /// Whether a failed URL download `e` should skip the input rather than fail the merge.
+    fn skips_url_failure(&self, e: &MergeError) -> bool {
+        let policy = match self.tolerate_missing_inputs {
+            true => UrlFailurePolicy::SkipAll,
+            false => self.url_failure_policy,
+        };
+        match policy {
+            UrlFailurePolicy::SkipAll => true,
+            UrlFailurePolicy::SkipTransientErrorPermanent => match e {
+                MergeError::Timeout(_) | MergeError::Unreachable(_) => true,
+                MergeError::HttpStatus { status, .. } => {
+                    *status >= 500 || *status == 408 || *status == 429
+                }
+                _ => false,
+            },
+            UrlFailurePolicy::ErrorAll => false,
+        }
+    }
+
+    /// The overwrite policy for `path`: the first matching `overwrite_rules` entry, else
+    /// `overwrite`.
+    fn overwrite_policy_for(&self, path: &str) -> OverwritePolicy {
+        self.overwrite_rules
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, path))
+            .map_or(self.overwrite, |(_, policy)| *policy)
+    }
+
+    /// Whether the output bytes at `path` may differ from the input's: a transform matches it
+    /// or `minify_text` applies.
+    fn rewrites(&self, path: &str) -> bool {
+        self.transforms.iter().any(|t| glob_match(&t.pattern, path))
+            || (self.minify_text && minified_kind(path).is_some())
+    }
+
+    /// Apply `minify_text`, then run every transform whose pattern matches `path` over `bytes`,
+    /// in order.
+    fn apply_transforms(&self, path: &str, mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if self.minify_text && !(self.pretty_mcmeta && path == "pack.mcmeta") {
+            bytes = minify_text(path, bytes);
+        }
+        for t in self
+            .transforms
+            .iter()
+            .filter(|t| glob_match(&t.pattern, path))
+        {
+            bytes = (t.transform)(path, bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Pass `event` to the progress hook, if any.
+    fn report_progress(&self, event: ProgressEvent) {
+        if let Some(hook) = &self.progress {
+            (hook.0)(event);
+        }
+    }
+
+    /// Return [`MergeError::Cancelled`] if the cancel token has been set.
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(MergeError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Represents an input pack. It can be a directory on disk, a zip file on disk, raw zip bytes,
+/// raw (uncompressed) tar bytes, or a URL to a zip.
+///
+/// Serializes as `{ "type": "dir", "value": "..." }`; in-memory bytes are base64 encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
 pub enum PackInput {
     Dir(PathBuf),
     ZipFile(PathBuf),
-    ZipBytes(Vec<u8>),
+    ZipBytes(#[serde(with = "base64_bytes")] Vec<u8>),
     Url(String),
+    TarBytes(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+/// Serde helpers encoding in-memory archive bytes as a base64 string.
+mod base64_bytes {
+    use base64::Engine;
+
+    pub fn serialize<S: serde::Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(d)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serde helpers for [`MergeOptions::filename_encoding`]: an encoding name or label such as
+/// `"shift_jis"`, or null.
+mod encoding_label {
+    use super::NameEncoding;
+
+    pub fn serialize<S: serde::Serializer>(enc: &NameEncoding, s: S) -> Result<S::Ok, S::Error> {
+        match enc {
+            Some(enc) => s.serialize_some(enc.name()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<NameEncoding, D::Error> {
+        let Some(label) = <Option<String> as serde::Deserialize>::deserialize(d)? else {
+            return Ok(None);
+        };
+        encoding_rs::Encoding::for_label(label.as_bytes())
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown encoding: {}", label)))
+    }
 }
 
 impl From<PathBuf> for PackInput {
@@ -135,25 +736,59 @@ impl From<Vec<u8>> for PackInput {
 
 impl From<String> for PackInput {
     fn from(s: String) -> Self {
-        // treat http/https as urls, otherwise as path
+        // treat http/https as urls, otherwise as path; going through `From<PathBuf>` lets a
+        // directory given as a string (e.g. a config's `./base`) load as `Dir`, not a zip
         if s.starts_with("http://") || s.starts_with("https://") {
             PackInput::Url(s)
         } else {
-            PackInput::ZipFile(PathBuf::from(s))
+            PathBuf::from(s).into()
         }
     }
 }
 
 /// Download a URL and return bytes (blocking reqwest). Caller should handle large bodies.
 fn fetch_url_bytes(url: &str) -> Result<Vec<u8>> {
-    let resp = reqwest::blocking::get(url)
-        .map_err(|e| MergeError::InvalidInput(format!("failed to GET {}: {}", url, e)))?;
+    fetch_url_bytes_with_timeout(url, None)
+}
+
+/// Like [`fetch_url_bytes`], but with an overall request `timeout` (reqwest's default applies
+/// when None).
+fn fetch_url_bytes_with_timeout(
+    url: &str,
+    timeout: Option<std::time::Duration>,
+) -> Result<Vec<u8>> {
+    let (bytes, ct_header) = fetch_url_body(url, timeout)?;
+    check_zip_download(url, bytes, ct_header.as_deref())
+}
+
+/// GET `url` and return its body along with the content-type header, without checking what
+/// the body is.
+fn fetch_url_body(
+    url: &str,
+    timeout: Option<std::time::Duration>,
+) -> Result<(Vec<u8>, Option<String>)> {
+    let http_err = |what: &str, e: reqwest::Error| {
+        if e.is_timeout() {
+            MergeError::Timeout(url.to_string())
+        } else if e.is_connect() || e.is_body() {
+            MergeError::Unreachable(format!("{} {}: {}", what, url, e))
+        } else {
+            MergeError::InvalidInput(format!("{} {}: {}", what, url, e))
+        }
+    };
+    let resp = match timeout {
+        Some(t) => reqwest::blocking::Client::builder()
+            .timeout(t)
+            .build()
+            .and_then(|c| c.get(url).send()),
+        None => reqwest::blocking::get(url),
+    }
+    .map_err(|e| http_err("failed to GET", e))?;
     if !resp.status().is_success() {
-        return Err(MergeError::InvalidInput(format!(
-            "GET {} returned {}",
-            url,
-            resp.status()
-        )));
+        return Err(MergeError::HttpStatus {
+            url: url.to_string(),
+            status: resp.status().as_u16(),
+        });
     }
     // Capture content-type header before consuming the response
     let ct_header = resp
@@ -162,22 +797,121 @@ fn fetch_url_bytes(url: &str) -> Result<Vec<u8>> {
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    let bytes = resp
-        .bytes()
-        .map_err(|e| MergeError::InvalidInput(format!("read {} body: {}", url, e)))?;
-    let b = bytes.to_vec();
-    // Quick sanity check: ensure the bytes look like a ZIP file (start with PK signature).
-    // Many servers may return HTML error pages or other content; detect that early.
+    let bytes = resp.bytes().map_err(|e| http_err("read body of", e))?;
+    Ok((bytes.to_vec(), ct_header))
+}
+
+/// Download every `.zip` linked from the HTML directory listing at `url` into `map`, in link
+/// order so later links overwrite earlier ones.
+#[cfg(feature = "dir-listing")]
+fn read_url_listing_into_map(
+    url: &str,
+    map: &mut HashMap<String, Vec<u8>>,
+    opts: &MergeOptions,
+    password: Option<&str>,
+) -> Result<()> {
+    let (body, _) = fetch_url_body(url, opts.per_input_timeout)?;
+    let links = listing_zip_links(url, &String::from_utf8_lossy(&body))?;
+    if links.is_empty() {
+        return Err(MergeError::InvalidInput(format!(
+            "directory listing {} links no .zip files",
+            url
+        )));
+    }
+    for link in links {
+        let bytes = fetch_url_bytes_with_timeout(&link, opts.per_input_timeout)?;
+        if opts.verify_input_crc {
+            verify_zip_crcs(&mut ZipArchive::new(Cursor::new(&bytes))?, password, &link)?;
+        }
+        let (encoding, dup) = (opts.filename_encoding, opts.on_duplicate_in_archive);
+        read_zipbytes_into_map(&bytes, map, password, encoding, dup, &link)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "dir-listing"))]
+fn read_url_listing_into_map(
+    url: &str,
+    _map: &mut HashMap<String, Vec<u8>>,
+    _opts: &MergeOptions,
+    _password: Option<&str>,
+) -> Result<()> {
+    Err(MergeError::InvalidInput(format!(
+        "{} is a directory listing, but resource_merger was built without the `dir-listing` feature",
+        url
+    )))
+}
+
+/// Absolute URLs of the `.zip` files linked (`href`) from the listing page `html`, in page
+/// order without duplicates. Relative links resolve against `base`.
+#[cfg(feature = "dir-listing")]
+fn listing_zip_links(base: &str, html: &str) -> Result<Vec<String>> {
+    let base = reqwest::Url::parse(base)
+        .map_err(|e| MergeError::InvalidInput(format!("invalid URL {}: {}", base, e)))?;
+    let href = regex::Regex::new(r#"(?i)href\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#)
+        .expect("valid href pattern");
+    let mut links = Vec::new();
+    for cap in href.captures_iter(html) {
+        let Some(raw) = cap.get(1).or(cap.get(2)).or(cap.get(3)) else {
+            continue;
+        };
+        let Ok(link) = base.join(&raw.as_str().replace("&amp;", "&")) else {
+            continue;
+        };
+        let is_zip = link.path().to_ascii_lowercase().ends_with(".zip");
+        if is_zip && !links.contains(&link.to_string()) {
+            links.push(link.to_string());
+        }
+    }
+    Ok(links)
+}
+
+/// Quick sanity check: ensure downloaded bytes look like a ZIP file (start with PK signature).
+/// Many servers may return HTML error pages or login redirects instead; the error then includes
+/// the content type and a short preview of the body.
+fn check_zip_download(url: &str, b: Vec<u8>, content_type: Option<&str>) -> Result<Vec<u8>> {
     if b.len() >= 2 && &b[0..2] == b"PK" {
-        Ok(b)
-    } else {
-        // Try to include content-type header for better debugging
-        let ct = ct_header.as_deref().unwrap_or("<unknown>");
-        Err(MergeError::InvalidInput(format!(
-            "GET {} did not return a zip file (content-type: {}).",
-            url, ct
-        )))
+        return Ok(b);
+    }
+    let ct = content_type.unwrap_or("<unknown>");
+    let preview = match body_preview(&b) {
+        Some(p) => format!(" Response starts with: \"{}\"", p),
+        None => String::new(),
+    };
+    Err(MergeError::InvalidInput(format!(
+        "GET {} did not return a zip file (content-type: {}).{}",
+        url, ct, preview
+    )))
+}
+
+/// Short single-line preview of a text response body: the HTML `<title>` if there is one,
+/// otherwise the first 200 characters. Control characters are dropped and whitespace is
+/// collapsed. Returns None for empty or non-text bodies.
+fn body_preview(body: &[u8]) -> Option<String> {
+    const MAX_CHARS: usize = 200;
+    let text = std::str::from_utf8(&body[..body.len().min(64 * 1024)])
+        .or_else(|e| std::str::from_utf8(&body[..e.valid_up_to()]))
+        .ok()?;
+    let lower = text.to_ascii_lowercase();
+    let title = lower.find("<title").and_then(|start| {
+        let open_end = start + lower[start..].find('>')? + 1;
+        let close = open_end + lower[open_end..].find("</title")?;
+        Some(&text[open_end..close])
+    });
+    let raw = title.unwrap_or(text);
+    let cleaned = raw
+        .split_whitespace()
+        .map(|w| w.chars().filter(|c| !c.is_control()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if cleaned.is_empty() {
+        return None;
+    }
+    let mut preview: String = cleaned.chars().take(MAX_CHARS).collect();
+    if cleaned.chars().count() > MAX_CHARS {
+        preview.push_str("...");
     }
+    Some(preview)
 }
 
 /// Merge multiple packs into a single zip archive (returned as Vec<u8>).
@@ -193,782 +927,8383 @@ pub fn merge_packs_to_bytes_with_options(
     packs: &[PackInput],
     opts: &MergeOptions,
 ) -> Result<Vec<u8>> {
-    // We'll maintain a map of path -> file bytes. Later packs overwrite earlier ones.
-    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
-    // Track pack_format and max_format numbers found in inputs
-    let mut found_formats: Vec<u32> = Vec::new();
-    let mut found_max_formats: Vec<u32> = Vec::new();
-    // Collect overlays from all packs (later packs overwrite earlier ones)
-    let mut overlays_values: Vec<serde_json::Value> = Vec::new();
+    let mut plan = match plan_single_zip_pass_through(packs, opts)? {
+        Some(plan) => plan,
+        None => plan_merge(None, packs, opts, false)?,
+    };
+    let writer = write_merged_zip(
+        &mut plan,
+        &input_labels(packs),
+        opts,
+        Cursor::new(Vec::new()),
+    )?;
+    Ok(writer.into_inner())
+}
 
-    // First, inspect each input for pack.mcmeta to collect pack_format values across all inputs.
-    // We do a best-effort peek so we can choose the HIGHEST pack_format observed, independent
-    // of later overwrites.
-    for pack in packs {
-        match pack {
-            PackInput::Dir(p) => {
-                if let Some((pf, mf, overlays)) = peek_pack_format_from_dir(p) {
-                    found_formats.push(pf);
-                    if let Some(max) = mf {
-                        found_max_formats.push(max);
-                    }
-                    if let Some(ov) = overlays {
-                        overlays_values.push(ov);
-                    }
+/// Fast path for normalizing a single zip input: its entries are listed instead of read and
+/// later copied into the output as stored, without decompressing and recompressing them. Only
+/// stored and deflated entries are copied; other methods (bzip2, zstd, ...) are recompressed so
+/// Minecraft can read them. Root metadata is still carried or synthesized as usual.
+///
+/// Returns `None` (use the regular plan) for other inputs, or when `opts` needs every entry
+/// decoded or rewritten: transforms, compression settings, fixed timestamps, passwords,
+/// CRC checks or provenance fields, or when the zip itself has encrypted entries. Also returns
+/// `None` with a `per_input_timeout`, so the regular plan opens the zip under its watchdog.
+fn plan_single_zip_pass_through(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<Option<MergePlan>> {
+    let [pack] = packs else {
+        return Ok(None);
+    };
+    if !opts.transforms.is_empty()
+        || opts.minify_text
+        || !opts.per_extension_compression.is_empty()
+        || opts.fixed_timestamps
+        || !opts.zip_passwords.is_empty()
+        || opts.verify_input_crc
+        || opts.embed_provenance_extra
+        || opts.compression.is_some()
+        || opts.per_input_timeout.is_some()
+    {
+        return Ok(None);
+    }
+    let reader = match pack {
+        PackInput::ZipFile(p) if custom_reader_for(p).is_none() => ZipReader::File(File::open(p)?),
+        PackInput::ZipBytes(b) => ZipReader::Bytes(Cursor::new(b.clone())),
+        _ => return Ok(None),
+    };
+    let label = describe_input(pack);
+    let dup = opts.on_duplicate_in_archive;
+    let mut archive = open_zip_checked(reader, dup, &label)?;
+    for i in 0..archive.len() {
+        if archive.by_index_raw(i)?.encrypted() {
+            return Ok(None);
+        }
+    }
+    plan_zip_pass_through(archive, opts, &label).map(Some)
+}
+
+/// Plan `archive` as the only input, with its entries listed and marked for raw copying.
+fn plan_zip_pass_through(
+    archive: ZipArchive<ZipReader>,
+    opts: &MergeOptions,
+    label: &str,
+) -> Result<MergePlan> {
+    let mut archive = Some(archive);
+    let mut plan = plan_merge_with(None, &[label.to_string()], opts, |_, zips| {
+        let mut archive = archive.take().expect("single input is loaded once");
+        let mut files = HashMap::new();
+        list_zip_entries(
+            &mut archive,
+            zips.len(),
+            &mut files,
+            opts.filename_encoding,
+            opts.on_duplicate_in_archive,
+            label,
+        )?;
+        let peeked = peek_pack_format_from_archive(&mut archive, None, opts.filename_encoding);
+        zips.push((archive, None));
+        Ok(LoadedInput { files, peeked })
+    })?;
+    plan.raw_copy = true;
+    Ok(plan)
+}
+
+/// Rewrite only the metadata of an already merged pack zip, e.g. to bump its pack_format or
+/// description without reading the original inputs again.
+///
+/// pack.mcmeta, pack.png and README.md are regenerated from `opts` and the formats and overlays
+/// declared by the base's pack.mcmeta; every other stored or deflated entry is copied as
+/// stored, without decompressing it (entries using other methods are recompressed). Options that rewrite entries (transforms, compression, timestamps) only
+/// affect the regenerated files.
+pub fn remeta(base_zip: &[u8], opts: &MergeOptions) -> Result<Vec<u8>> {
+    let label = "base zip";
+    let reader = ZipReader::Bytes(Cursor::new(base_zip.to_vec()));
+    let archive = open_zip_checked(reader, opts.on_duplicate_in_archive, label)?;
+    let mut plan = plan_zip_pass_through(archive, opts, label)?;
+    let writer = write_merged_zip(
+        &mut plan,
+        &[label.to_string()],
+        opts,
+        Cursor::new(Vec::new()),
+    )?;
+    Ok(writer.into_inner())
+}
+
+/// Apply `patches` on top of an already merged pack zip, as if the inputs that produced
+/// `base_zip` were listed before `patches`.
+///
+/// The base's pack.mcmeta, pack.png and README.md are not carried over; they are synthesized
+/// again from the base's declared formats plus the patches. `opts.zip_passwords` indexes refer
+/// to `patches`.
+pub fn merge_onto(base_zip: &[u8], patches: &[PackInput], opts: &MergeOptions) -> Result<Vec<u8>> {
+    let mut plan = plan_merge(Some(base_zip), patches, opts, false)?;
+    let writer = write_merged_zip(
+        &mut plan,
+        &input_labels(patches),
+        opts,
+        Cursor::new(Vec::new()),
+    )?;
+    Ok(writer.into_inner())
+}
+
+/// Merge like [`merge_packs_to_bytes_with_options`], reading inputs concurrently.
+///
+/// Inputs are read on up to `available_parallelism` worker threads, but they are folded strictly
+/// in input order and written with sorted keys and fixed timestamps, so the output is
+/// byte-identical to a sequential merge with `fixed_timestamps` set.
+pub fn merge_packs_to_bytes_parallel_deterministic(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<Vec<u8>> {
+    let opts = &MergeOptions {
+        fixed_timestamps: true,
+        ..opts.clone()
+    };
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(packs.len())
+        .max(1);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let slots: Vec<std::sync::Mutex<Option<Result<LoadedInput>>>> =
+        packs.iter().map(|_| std::sync::Mutex::new(None)).collect();
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(pack) = packs.get(idx) else { break };
+                if opts.check_cancelled().is_err() {
+                    break;
                 }
-                read_dir_into_map(p, &mut files)?;
-            }
-            PackInput::ZipFile(p) => {
-                if let Some((pf, mf, overlays)) = peek_pack_format_from_zipfile(p) {
-                    found_formats.push(pf);
-                    if let Some(max) = mf {
-                        found_max_formats.push(max);
-                    }
-                    if let Some(ov) = overlays {
-                        overlays_values.push(ov);
-                    }
+                // Not streaming, so no archives are kept open and the zip list stays empty
+                let loaded = load_input_with_timeout(idx, pack, opts, false, &mut Vec::new());
+                *slots[idx].lock().unwrap() = Some(loaded);
+            });
+        }
+    });
+
+    // Inputs skipped by a cancelled worker surface as Cancelled when the fold reaches them
+    let labels = input_labels(packs);
+    let mut plan = plan_merge_with(None, &labels, opts, |idx, _| {
+        slots[idx]
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or(Err(MergeError::Cancelled))
+    })?;
+    let writer = write_merged_zip(&mut plan, &labels, opts, Cursor::new(Vec::new()))?;
+    Ok(writer.into_inner())
+}
+
+/// Merge zip archives the caller already has open, without reopening them.
+///
+/// Archives are merged in order like [`PackInput`]s given to
+/// [`merge_packs_to_bytes_with_options`]; `opts.zip_passwords` indexes refer to `archives`.
+/// Entries are read into memory, so the handles can keep being used afterwards. The archives
+/// are already open, so `opts.per_input_timeout` doesn't apply.
+pub fn merge_zip_archives_to_bytes<R: Read + Seek>(
+    archives: &mut [&mut ZipArchive<R>],
+    opts: &MergeOptions,
+) -> Result<Vec<u8>> {
+    let labels: Vec<String> = (0..archives.len())
+        .map(|idx| format!("ZipArchive: <open archive {}>", idx))
+        .collect();
+    let mut plan = plan_merge_with(None, &labels, opts, |idx, _| {
+        let password = opts.zip_passwords.get(&idx).map(|s| s.as_str());
+        let mut mem = HashMap::new();
+        let dup = opts.on_duplicate_in_archive;
+        let encoding = opts.filename_encoding;
+        read_archive_into_map(
+            archives[idx],
+            &mut mem,
+            password,
+            encoding,
+            dup,
+            &labels[idx],
+        )?;
+        let peeked = mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m));
+        Ok(LoadedInput {
+            files: mem
+                .into_iter()
+                .map(|(k, v)| (k, EntrySource::Memory(v)))
+                .collect(),
+            peeked,
+        })
+    })?;
+    let writer = write_merged_zip(&mut plan, &labels, opts, Cursor::new(Vec::new()))?;
+    Ok(writer.into_inner())
+}
+
+/// Read a single input into a map of sanitized entry path -> contents, without merging.
+///
+/// URLs are downloaded and archives decoded exactly as during a merge; `opts.zip_passwords`
+/// entry `0` is used for an encrypted zip. No files are added or filtered.
+pub fn read_pack(input: &PackInput, opts: &MergeOptions) -> Result<HashMap<String, Vec<u8>>> {
+    let loaded = load_input_with_timeout(0, input, opts, false, &mut Vec::new())?;
+    let mut zips = Vec::new();
+    loaded
+        .files
+        .into_iter()
+        .map(|(k, src)| Ok((k, read_source(&src, &mut zips)?)))
+        .collect()
+}
+
+/// Every `assets/<ns>` and `data/<ns>` namespace present in the inputs (also inside overlay
+/// directories), mapped to the indices of the inputs that provide files in it, in input order.
+///
+/// Only entry paths are inspected; file contents of directory and zip inputs are not read.
+/// Inputs are opened as for a merge (`zip_passwords`, `filename_encoding` and the like apply),
+/// but nothing is filtered: `namespaces`/`deny_namespaces` are ignored.
+pub fn list_namespaces(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<HashMap<String, Vec<usize>>> {
+    let mut namespaces: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, pack) in packs.iter().enumerate() {
+        let loaded = load_input_with_timeout(idx, pack, opts, true, &mut Vec::new())?;
+        for key in loaded.files.keys() {
+            if let Some(ns) = path_namespace(key) {
+                let inputs = namespaces.entry(ns.to_string()).or_default();
+                if inputs.last() != Some(&idx) {
+                    inputs.push(idx);
                 }
-                read_zipfile_into_map(p, &mut files)?;
             }
-            PackInput::ZipBytes(b) => {
-                if let Some((pf, mf, overlays)) = peek_pack_format_from_zipbytes(b) {
-                    found_formats.push(pf);
-                    if let Some(max) = mf {
-                        found_max_formats.push(max);
-                    }
-                    if let Some(ov) = overlays {
-                        overlays_values.push(ov);
-                    }
-                }
-                read_zipbytes_into_map(b, &mut files)?;
-            }
-            PackInput::Url(u) => match fetch_url_bytes(u) {
-                Ok(bytes) => {
-                    if let Some((pf, mf, overlays)) = peek_pack_format_from_zipbytes(&bytes) {
-                        found_formats.push(pf);
-                        if let Some(max) = mf {
-                            found_max_formats.push(max);
-                        }
-                        if let Some(ov) = overlays {
-                            overlays_values.push(ov);
-                        }
-                    }
-                    read_zipbytes_into_map(&bytes, &mut files)?;
-                }
-                Err(e) => {
-                    if opts.tolerate_missing_inputs {
-                        eprintln!("warning: skipping input {}: {}", u, e);
-                    } else {
-                        return Err(e);
-                    }
-                }
-            },
         }
     }
+    Ok(namespaces)
+}
 
-    // Inspect any pack.mcmeta files found and collect pack_format values
-    // (overlays are now collected during the peek phase above)
-    for (k, v) in &files {
-        if k == "pack.mcmeta" || k.ends_with("/pack.mcmeta") {
-            if let Ok(s) = std::str::from_utf8(v) {
-                if let Ok((pf, mf)) = extract_pack_format_from_mcmeta(s) {
-                    found_formats.push(pf);
-                    if let Some(max) = mf {
-                        found_max_formats.push(max);
-                    }
-                }
-            }
+/// The manifest of a merged zip: every file path mapped to its CRC-32 as 8 lowercase hex digits,
+/// in the JSON shape [`assert_against_manifest`] reads (`{ "pack.mcmeta": "1c291ca3", ... }`).
+pub fn zip_manifest(zip: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut archive = ZipArchive::new(Cursor::new(zip))?;
+    let mut manifest = BTreeMap::new();
+    for i in 0..archive.len() {
+        let f = archive.by_index_raw(i)?;
+        if !f.is_dir() {
+            manifest.insert(f.name().to_string(), format!("{:08x}", f.crc32()));
         }
     }
+    Ok(manifest)
+}
 
-    // Write map into an in-memory zip
-    let buffer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-    let mut zip = ZipWriter::new(buffer);
-    let options: zip::write::FileOptions<'_, zip::write::ExtendedFileOptions> =
-        zip::write::FileOptions::default().unix_permissions(0o644);
+/// Merged path -> contents, plus the `warn_on_wide_format_span` warning, if any.
+type MergedFiles = (BTreeMap<String, Vec<u8>>, Option<String>);
 
-    // Ensure deterministic order by sorting keys
-    // We'll skip certain auto-generated names when emitting from the map so we can synthesize them
-    let mut keys: Vec<&String> = files
-        .keys()
-        .filter(|k| {
-            let kk = k.as_str();
-            kk != "pack.mcmeta" && kk != "pack.png" && kk != "README.md"
+/// Every file a merge of `packs` would write, including the generated root files, with
+/// transforms applied. The span warning is left to callers that write the files.
+fn merged_files(packs: &[PackInput], opts: &MergeOptions) -> Result<MergedFiles> {
+    let mut plan = plan_merge(None, packs, opts, true)?;
+    let roots = build_root_files(&mut plan, &input_labels(packs), opts)?;
+    let mut files = BTreeMap::new();
+    for (key, src) in &plan.files {
+        if !SYNTHESIZED_ROOT_FILES.contains(&key.as_str()) {
+            let bytes = opts.apply_transforms(key, read_source(src, &mut plan.zips)?)?;
+            files.insert(key.clone(), bytes);
+        }
+    }
+    files.extend(roots.into_iter().map(|(k, v)| (k.to_string(), v)));
+    Ok((files, format_span_warning(&plan.found_formats, opts)))
+}
+
+/// Merge `packs` into a single JSON document instead of a zip, e.g. to serve over HTTP:
+/// `{ "files": { "<path>": "<base64 bytes>", ... }, "mcmeta": { ... } }`.
+///
+/// `files` holds every output file (including pack.mcmeta, pack.png and README.md) with
+/// standard base64 encoding; `mcmeta` is the generated pack.mcmeta, parsed.
+pub fn merge_packs_to_json(packs: &[PackInput], opts: &MergeOptions) -> Result<serde_json::Value> {
+    use base64::Engine;
+
+    let (files, _) = merged_files(packs, opts)?;
+    let mcmeta: serde_json::Value = serde_json::from_slice(&files["pack.mcmeta"])
+        .map_err(|e| MergeError::InvalidInput(format!("pack.mcmeta: {}", e)))?;
+    let encoded: serde_json::Map<String, serde_json::Value> = files
+        .into_iter()
+        .map(|(path, bytes)| {
+            let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+            (path, serde_json::Value::String(b64))
         })
         .collect();
-    keys.sort();
+    Ok(serde_json::json!({ "files": encoded, "mcmeta": mcmeta }))
+}
 
-    for key in keys {
-        let data = &files[key];
-        zip.start_file(key, options.clone())?;
-        zip.write_all(data)?;
-    }
+/// Merge `packs` and ship the result as two zips: a resource pack with the `assets/` content
+/// and a data pack with the `data/` content, as `(resource, data)`. Overlay directories are
+/// split the same way; other files (e.g. a LICENSE) go into both.
+///
+/// Each zip gets its own pack.mcmeta, synthesized (or carried) only from the inputs that
+/// contribute to it, so a data pack's pack_format doesn't leak into the resource pack. An input
+/// mixing both kinds counts towards both.
+pub fn merge_and_split(packs: &[PackInput], opts: &MergeOptions) -> Result<(Vec<u8>, Vec<u8>)> {
+    let half = |kind: &str| -> Result<Vec<u8>> {
+        let keep = |key: &str| content_folder(key).is_none_or(|f| f == kind);
+        let mut plan = plan_merge_with(None, &input_labels(packs), opts, |idx, zips| {
+            let mut loaded = load_input_with_timeout(idx, &packs[idx], opts, false, zips)?;
+            if loaded.files.keys().any(|k| content_folder(k) == Some(kind)) {
+                loaded.files.retain(|k, _| keep(k));
+            } else {
+                loaded.files.clear();
+                loaded.peeked = None;
+            }
+            Ok(loaded)
+        })?;
+        plan.files.retain(|k, _| keep(k));
+        let writer = write_merged_zip(
+            &mut plan,
+            &input_labels(packs),
+            opts,
+            Cursor::new(Vec::new()),
+        )?;
+        Ok(writer.into_inner())
+    };
+    Ok((half("assets")?, half("data")?))
+}
 
-    // Determine final pack_format: override via opts if present, otherwise highest found or 1
-    let final_pack_fmt = if let Some(ov) = opts.pack_format_override {
-        ov
-    } else if found_formats.is_empty() {
-        1u32
+/// `assets` or `data` if a normalized path lies in that top-level folder, also inside an
+/// overlay directory (`<overlay>/data/**`).
+fn content_folder(path: &str) -> Option<&str> {
+    let comps: Vec<&str> = path.split('/').collect();
+    let is_content = |c: &str| c == "assets" || c == "data";
+    if comps.len() > 1 && is_content(comps[0]) {
+        Some(comps[0])
+    } else if comps.len() > 2 && is_content(comps[1]) {
+        Some(comps[1])
     } else {
-        *found_formats.iter().max().unwrap_or(&1u32)
-    };
+        None
+    }
+}
 
-    // Compute supported_formats vector based on policy.
-    // For user-friendly pack.mcmeta we emit only the endpoint values (lowest/highest)
-    // instead of every integer in the inclusive range. Examples:
-    // - OneToHighest => [1, high]
-    // - LowestToHighest => [low, high]
-    // If low == high we emit a single-element array [low].
-    let supported_formats: Vec<u32> = match opts.supported_formats_policy {
-        SupportedFormatsPolicy::OneToHighest => {
-            let high = if found_formats.is_empty() {
-                final_pack_fmt
-            } else {
-                *found_formats.iter().max().unwrap_or(&final_pack_fmt)
-            };
-            if high <= 1 {
-                vec![1u32]
-            } else {
-                vec![1u32, high]
-            }
-        }
-        SupportedFormatsPolicy::LowestToHighest => {
-            if found_formats.is_empty() {
-                vec![final_pack_fmt]
-            } else {
-                let low = *found_formats.iter().min().unwrap_or(&final_pack_fmt);
-                let high = *found_formats.iter().max().unwrap_or(&final_pack_fmt);
-                if low == high {
-                    vec![low]
-                } else {
-                    vec![low, high]
-                }
-            }
-        }
-        SupportedFormatsPolicy::OneToLatest => {
-            // Not implemented: fall back to OneToHighest for now
-            let high = if found_formats.is_empty() {
-                final_pack_fmt
-            } else {
-                *found_formats.iter().max().unwrap_or(&final_pack_fmt)
-            };
-            if high <= 1 {
-                vec![1u32]
-            } else {
-                vec![1u32, high]
-            }
-        }
-    };
+/// A SHA-256 hex digest of what merging `packs` would produce, independent of zip framing,
+/// compression and timestamps, for use as a cache key.
+///
+/// The hash covers the sorted `(path, sha256(bytes))` pairs of the merged files, including the
+/// generated pack.mcmeta and pack.png but not README.md, which lists the inputs. Inputs that
+/// produce the same files in a different order therefore hash the same. Nothing is written.
+pub fn logical_hash(packs: &[PackInput], opts: &MergeOptions) -> Result<String> {
+    use sha2::{Digest, Sha256};
 
-    // Determine actual max format from all sources
-    let actual_max_format = if found_max_formats.is_empty() {
-        *supported_formats.last().unwrap_or(&final_pack_fmt)
-    } else {
-        *found_max_formats.iter().max().unwrap_or(&final_pack_fmt)
-    };
+    let mut hasher = Sha256::new();
+    for (path, bytes) in merged_files(packs, opts)?.0 {
+        if path == "README.md" {
+            continue;
+        }
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(Sha256::digest(&bytes));
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
 
-    // Merge overlays: later ones overwrite earlier, keyed by directory name
-    let merged_overlays = merge_overlays(&overlays_values);
+/// Merge `packs` and compare the output against the JSON manifest at `manifest_path` (see
+/// [`zip_manifest`]). Any missing, extra or changed file fails with
+/// [`MergeError::ManifestMismatch`] listing every difference, one per line. Nothing is written.
+pub fn assert_against_manifest<P: AsRef<Path>>(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+    manifest_path: P,
+) -> Result<()> {
+    let path = manifest_path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let mut expected: BTreeMap<String, String> = serde_json::from_str(&text)
+        .map_err(|e| MergeError::InvalidInput(format!("manifest {}: {}", path.display(), e)))?;
+    let actual = zip_manifest(&merge_packs_to_bytes_with_options(packs, opts)?)?;
 
-    // Ensure pack.mcmeta exists with an appropriate pack_format & supported_formats
-    let mcmeta = make_pack_mcmeta(
-        final_pack_fmt,
-        &supported_formats,
-        opts.description_override.as_deref(),
-        actual_max_format,
-        merged_overlays.as_ref(),
+    let mut diff = Vec::new();
+    for (file, crc) in actual {
+        match expected.remove(&file) {
+            None => diff.push(format!("extra: {}", file)),
+            Some(want) if !want.eq_ignore_ascii_case(&crc) => diff.push(format!(
+                "changed: {} (expected {}, got {})",
+                file, want, crc
+            )),
+            Some(_) => {}
+        }
+    }
+    diff.extend(
+        expected
+            .into_keys()
+            .map(|file| format!("missing: {}", file)),
     );
-    zip.start_file("pack.mcmeta", options.clone())?;
-    zip.write_all(mcmeta.as_bytes())?;
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        diff.sort();
+        Err(MergeError::ManifestMismatch(diff.join("\n")))
+    }
+}
 
-    // Ensure pack.png exists (small default) if missing
-    // Always write our embedded default pack.png into the merged zip as pack.png.
-    // This ensures a consistent default image regardless of input packs.
-    let png = default_pack_png_bytes();
-    zip.start_file("pack.png", options.clone())?;
-    zip.write_all(&png)?;
+/// Where the contents of one merged entry come from.
+///
+/// The in-memory strategy loads every entry up front (`Memory`). The streaming strategy keeps
+/// entries of on-disk inputs as references and copies them into the output one at a time.
+#[derive(Debug, Clone)]
+enum EntrySource {
+    Memory(Vec<u8>),
+    File(PathBuf),
+    /// Entry `index` of `MergePlan::zips[archive]`
+    ZipEntry {
+        archive: usize,
+        index: usize,
+    },
+}
 
-    // Ensure README.md exists with simple generation notes
-    if !files.contains_key("README.md") {
-        let readme = make_readme(packs);
-        zip.start_file("README.md", options.clone())?;
-        zip.write_all(readme.as_bytes())?;
-    }
+/// Zip archives kept open by a streaming plan, with the password used to read their entries.
+type OpenZips = Vec<(ZipArchive<ZipReader>, Option<String>)>;
 
-    let writer = zip.finish()?;
-    // writer is Cursor<Vec<u8>>
-    let mut inner = writer.into_inner();
-    // ensure start at 0
-    let _ = Cursor::new(&mut inner).seek(SeekFrom::Start(0));
-    Ok(inner)
+/// Backing storage of a zip archive kept open by a plan.
+enum ZipReader {
+    File(File),
+    Bytes(Cursor<Vec<u8>>),
+    Http(HttpRangeReader),
 }
 
-/// Merge packs and write resulting zip to a file path.
-pub fn merge_packs_to_file<P: AsRef<Path>>(packs: &[PackInput], out: P) -> Result<()> {
-    let bytes = merge_packs_to_bytes(packs)?;
-    std::fs::write(out, bytes)?;
-    Ok(())
+impl Read for ZipReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ZipReader::File(f) => f.read(buf),
+            ZipReader::Bytes(c) => c.read(buf),
+            ZipReader::Http(h) => h.read(buf),
+        }
+    }
 }
 
-/// Merge with options and write to file. Currently uses the in-memory path when appropriate.
-pub fn merge_packs_to_file_with_options<P: AsRef<Path>>(
-    packs: &[PackInput],
-    out: P,
-    opts: &MergeOptions,
-) -> Result<()> {
-    // For now, if dry_run just compute plan via merge_packs_to_bytes read-only scan
-    if opts.dry_run {
-        // perform a simple scan to validate inputs and return early (no writes)
-        let _ = merge_packs_to_bytes_with_options(packs, opts)?;
-        return Ok(());
+impl Seek for ZipReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            ZipReader::File(f) => f.seek(pos),
+            ZipReader::Bytes(c) => c.seek(pos),
+            ZipReader::Http(h) => h.seek(pos),
+        }
     }
+}
 
-    // For small inputs we keep using the in-memory path. We'll add streaming dir-based merging later.
-    let bytes = merge_packs_to_bytes_with_options(packs, opts)?;
-    std::fs::write(out, bytes)?;
-    Ok(())
+/// A remote file read through HTTP range requests, one chunk at a time, so a zip's central
+/// directory and selected entries can be read without downloading the whole archive.
+struct HttpRangeReader {
+    client: reqwest::blocking::Client,
+    url: String,
+    timeout: Option<std::time::Duration>,
+    len: u64,
+    pos: u64,
+    /// The most recently fetched chunk and its offset in the file
+    chunk: Vec<u8>,
+    chunk_start: u64,
 }
 
-/// Streaming merge into a directory. This is a placeholder that currently falls back to in-memory behavior
-/// for backwards compatibility. Later this should stream per-file into `out_dir` following `opts`.
-pub fn merge_packs_to_dir<P: AsRef<Path>>(
-    packs: &[PackInput],
-    out_dir: P,
-    opts: &MergeOptions,
-) -> Result<()> {
-    // TODO: implement streaming plan+execute.
-    if opts.dry_run {
-        // validate by scanning using existing in-memory method
-        let _ = merge_packs_to_bytes_with_options(packs, opts)?;
-        return Ok(());
-    }
+impl HttpRangeReader {
+    /// Bytes requested per range request
+    const CHUNK_SIZE: u64 = 64 * 1024;
 
-    // Fallback: unzip the in-memory merged zip into out_dir.
-    let bytes = merge_packs_to_bytes_with_options(packs, opts)?;
-    let cursor = Cursor::new(bytes);
-    let mut archive = ZipArchive::new(cursor)?;
-    let out_path = out_dir.as_ref();
-    std::fs::create_dir_all(out_path)?;
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        if file.is_dir() {
-            continue;
+    /// Probe `url` with a HEAD request. Returns None when the server doesn't advertise
+    /// `Accept-Ranges: bytes` with a `Content-Length`, or the probe fails; the caller then
+    /// downloads the file whole (which reports any error).
+    fn open(url: &str, timeout: Option<std::time::Duration>) -> Option<HttpRangeReader> {
+        let mut builder = reqwest::blocking::Client::builder();
+        if let Some(t) = timeout {
+            builder = builder.timeout(t);
         }
-        let raw_name = file.name().to_string();
-        let name = match sanitize_zip_entry_name(&raw_name) {
-            Some(n) => n,
-            None => continue,
-        };
-        // Build a destination path from the sanitized components to ensure correct
-        // OS-specific separators and avoid zip-slip.
-        let dest = {
-            let mut p = out_path.to_path_buf();
-            for comp in name.split('/') {
-                p.push(comp);
+        let client = builder.build().ok()?;
+        let resp = client.head(url).send().ok()?;
+        let headers = resp.headers();
+        let ranges = headers
+            .get(reqwest::header::ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok());
+        let len = headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        match (resp.status().is_success(), ranges, len) {
+            (true, Some("bytes"), Some(len)) => Some(HttpRangeReader {
+                client,
+                url: url.to_string(),
+                timeout,
+                len,
+                pos: 0,
+                chunk: Vec::new(),
+                chunk_start: 0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Fetch the chunk at `pos`. If the server doesn't honour the range request after all,
+    /// the whole file is downloaded once and served from memory from then on.
+    fn fetch_chunk(&mut self) -> std::io::Result<()> {
+        match self.fetch_range() {
+            Ok(chunk) => {
+                self.chunk = chunk;
+                self.chunk_start = self.pos;
+            }
+            Err(e) => {
+                eprintln!("warning: {}; downloading {} whole instead", e, self.url);
+                let bytes = fetch_url_bytes_with_timeout(&self.url, self.timeout)
+                    .map_err(std::io::Error::other)?;
+                self.len = bytes.len() as u64;
+                self.chunk = bytes;
+                self.chunk_start = 0;
             }
-            p
-        };
-        if let Some(parent) = dest.parent() {
-            std::fs::create_dir_all(parent)?;
         }
-        let mut outfile = std::fs::File::create(dest)?;
-        std::io::copy(&mut file, &mut outfile)?;
+        Ok(())
     }
-    Ok(())
-}
 
-/// Given a directory which contains multiple resourcepack folders or zip files, merge them all in
-/// lexical order. Useful when users supply a single "resourcepacks" folder.
-pub fn merge_all_packs_in_folder(folder: &Path) -> Result<Vec<u8>> {
-    if !folder.is_dir() {
-        return Err(MergeError::InvalidInput(format!(
-            "{} is not a dir",
-            folder.display()
-        )));
+    fn fetch_range(&self) -> std::result::Result<Vec<u8>, String> {
+        let end = (self.pos + Self::CHUNK_SIZE).min(self.len) - 1;
+        let resp = self
+            .client
+            .get(&self.url)
+            .header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", self.pos, end),
+            )
+            .send()
+            .map_err(|e| format!("range request to {} failed: {}", self.url, e))?;
+        if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(format!(
+                "range request to {} returned {}",
+                self.url,
+                resp.status()
+            ));
+        }
+        resp.bytes()
+            .map(|b| b.to_vec())
+            .map_err(|e| format!("range request to {} failed: {}", self.url, e))
     }
-    let mut entries: Vec<PathBuf> = std::fs::read_dir(folder)?
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .collect();
-    entries.sort();
-    let packs: Vec<PackInput> = entries.into_iter().map(|p| p.into()).collect();
-    merge_packs_to_bytes(&packs)
 }
 
-/// Settings that represent the full runtime configuration for a merge run.
-/// This mirrors the CLI args/config file and is the single object used to execute a merge.
-#[derive(Debug, Clone)]
-pub struct Settings {
-    /// Ordered list of inputs (directories, zip files, or URLs). These are applied in order.
-    pub inputs: Vec<PackInput>,
-    /// Output path (file or directory) - resolved by the caller
-    pub out: PathBuf,
-    /// If true, write to a directory instead of a zip file
-    pub dir: bool,
-    /// Merge behavior options
-    pub options: MergeOptions,
+impl Read for HttpRangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk_end = self.chunk_start + self.chunk.len() as u64;
+        if self.pos < self.chunk_start || self.pos >= chunk_end {
+            self.fetch_chunk()?;
+        }
+        let offset = (self.pos - self.chunk_start) as usize;
+        let n = buf.len().min(self.chunk.len() - offset);
+        buf[..n].copy_from_slice(&self.chunk[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
 }
 
-/// Execute a merge according to `Settings`.
-/// This is the single entrypoint consumers (like the CLI) should call.
-pub fn run_with_settings(settings: &Settings) -> Result<()> {
-    if settings.dir {
-        merge_packs_to_dir(&settings.inputs, &settings.out, &settings.options)
-    } else {
-        merge_packs_to_file_with_options(&settings.inputs, &settings.out, &settings.options)
+impl Seek for HttpRangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = target.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+        Ok(self.pos)
     }
 }
 
-/// Read a simple config file (one URL or path per line, comments start with #) and return PackInput list
-use serde::Deserialize;
-
-/// Configuration structure for JSON config files.
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    /// Ordered list of inputs (directories, zip files, or URLs). These are applied first.
-    pub inputs: Option<Vec<String>>,
-    /// Overwrite policy: last, first, error, skip
-    pub overwrite: Option<String>,
-    /// Dry run
-    pub dry_run: Option<bool>,
-    /// Buffer size
-    pub buffer_size: Option<usize>,
-    /// Atomic write
-    pub atomic: Option<bool>,
-    /// Preserve timestamps
-    pub preserve_timestamps: Option<bool>,
-    /// Force pack_format
-    pub pack_format: Option<u32>,
-    /// Supported formats policy: one-to-highest, lowest-to-highest, one-to-latest
-    pub supported_formats: Option<String>,
-    /// Optional output path if you want the config to specify a default output file
-    pub out: Option<String>,
-    /// If true, write output as a directory instead of a zip file
-    pub dir: Option<bool>,
-    /// Optional description to use for generated pack.mcmeta
-    pub description: Option<String>,
-    /// If true, continue when input URLs fail to download or aren't valid zips
-    pub tolerate_missing_inputs: Option<bool>,
+/// The merged file set and detected metadata, before anything is written.
+struct MergePlan {
+    /// Output path -> winning entry. Later packs overwrite earlier ones.
+    files: HashMap<String, EntrySource>,
+    zips: OpenZips,
+    /// pack_format and max_format numbers found in inputs
+    found_formats: Vec<u32>,
+    found_max_formats: Vec<u32>,
+    /// Overlays merged from all inputs (later packs overwrite earlier ones)
+    overlays: Option<serde_json::Value>,
+    /// Paths that more than one input provided -> index of the input whose version won
+    overridden: BTreeMap<String, usize>,
+    /// The first (not dropped) input's own root metadata files, for `MetadataSource::FirstInput`
+    first_metadata: HashMap<String, EntrySource>,
+    /// Every input's pack.png in input order, for `PackPngPolicy::HighestResolution`
+    png_candidates: Vec<EntrySource>,
+    /// Copy stored and deflated `ZipEntry` sources into the output as they are, without
+    /// decompressing
+    raw_copy: bool,
+    /// Output path -> index of the input its kept version came from (for merged tag and font
+    /// files, the last contributing input)
+    sources: HashMap<String, usize>,
+    /// Union of the inputs' pack.mcmeta `language` blocks, for `merge_language_block`
+    language: Option<serde_json::Value>,
+    /// Indices of inputs skipped by `drop_inputs_below_format`, left out of the README
+    dropped: BTreeSet<usize>,
 }
 
-/// Read a JSON config file and return a Config structure.
-pub fn read_config_file(path: &Path) -> Result<Config> {
-    let s = std::fs::read_to_string(path)?;
-    let cfg: Config = serde_json::from_str(&s).map_err(|e| {
-        MergeError::InvalidInput(format!(
-            "failed to parse JSON config {}: {}",
-            path.display(),
-            e
-        ))
-    })?;
-    Ok(cfg)
+/// Apply the overwrite policy to an entry at `key` coming from input `idx` (described by
+/// `who` in errors). Returns whether it should replace what the plan holds; conflicts that
+/// replace an entry are recorded in `plan.overridden`.
+fn place_entry(
+    plan: &mut MergePlan,
+    key: &str,
+    idx: usize,
+    opts: &MergeOptions,
+    who: impl FnOnce() -> String,
+) -> Result<bool> {
+    if !plan.files.contains_key(key) {
+        return Ok(true);
+    }
+    match opts.overwrite_policy_for(key) {
+        OverwritePolicy::ErrorIfConflict
+            if !SYNTHESIZED_ROOT_FILES.contains(&key)
+                && !opts.allowed_conflicts.iter().any(|p| glob_match(p, key)) =>
+        {
+            return Err(MergeError::InvalidInput(format!(
+                "{} conflicts with an earlier input: {}",
+                who(),
+                key
+            )));
+        }
+        OverwritePolicy::FirstWins | OverwritePolicy::SkipIfExists => return Ok(false),
+        _ => {}
+    }
+    plan.overridden.insert(key.to_string(), idx);
+    Ok(true)
 }
 
-fn read_dir_into_map(dir: &Path, map: &mut HashMap<String, Vec<u8>>) -> Result<()> {
-    if !dir.is_dir() {
-        return Err(MergeError::InvalidInput(format!(
-            "{} is not a directory",
-            dir.display()
-        )));
-    }
+/// Label of the input at `idx` in conflict reports; indexes past the inputs are
+/// [`MergeOptions::extra_files`].
+fn winner_label(labels: &[String], idx: usize) -> &str {
+    labels.get(idx).map_or("extra files", |l| l.as_str())
+}
 
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() {
-            let rel = path.strip_prefix(dir).unwrap();
-            // Use forward slashes as zip paths
-            let key = rel
-                .iter()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join("/");
-            let mut f = File::open(path)?;
-            let mut buf = Vec::new();
-            f.read_to_end(&mut buf)?;
-            map.insert(key, buf);
+/// Copy the contents of an entry source into `w`.
+fn copy_source<W: Write>(
+    src: &EntrySource,
+    zips: &mut OpenZips,
+    w: &mut W,
+    buffer_size: usize,
+) -> Result<()> {
+    match src {
+        EntrySource::Memory(b) => w.write_all(b)?,
+        EntrySource::File(p) => {
+            let mut f = std::io::BufReader::with_capacity(buffer_size.max(1), File::open(p)?);
+            std::io::copy(&mut f, w)?;
+        }
+        EntrySource::ZipEntry { archive, index } => {
+            let (zip, password) = &mut zips[*archive];
+            let mut f = open_zip_entry(zip, *index, password.as_deref())?;
+            std::io::copy(&mut f, w)?;
         }
     }
     Ok(())
 }
 
-fn read_zipfile_into_map(path: &Path, map: &mut HashMap<String, Vec<u8>>) -> Result<()> {
-    let f = File::open(path)?;
-    let mut archive = ZipArchive::new(f)?;
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        if file.is_dir() {
-            continue;
-        }
-        let name = file.name().to_string();
-        // Sanitize zip entry name to a normalized forward-slash form and skip unsafe entries
-        let name = match sanitize_zip_entry_name(&name) {
-            Some(n) => n,
-            None => continue,
-        };
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        map.insert(name, buf);
+/// Read the full contents of an entry source.
+fn read_source(src: &EntrySource, zips: &mut OpenZips) -> Result<Vec<u8>> {
+    if let EntrySource::Memory(b) = src {
+        return Ok(b.clone());
     }
-    Ok(())
+    let mut buf = Vec::new();
+    copy_source(src, zips, &mut buf, 8 * 1024)?;
+    Ok(buf)
 }
 
-fn read_zipbytes_into_map(bytes: &[u8], map: &mut HashMap<String, Vec<u8>>) -> Result<()> {
-    let cursor = Cursor::new(bytes);
-    let mut archive = ZipArchive::new(cursor)?;
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        if file.is_dir() {
-            continue;
-        }
-        let name = file.name().to_string();
-        let name = match sanitize_zip_entry_name(&name) {
-            Some(n) => n,
-            None => continue,
-        };
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        map.insert(name, buf);
+/// pack_format, max_format and overlays read from an input's pack.mcmeta
+type PeekedFormats = (u32, Option<u32>, Option<serde_json::Value>);
+
+/// One input's entries and its peeked pack.mcmeta (pack_format, max_format, overlays), before
+/// they are folded into a [`MergePlan`].
+struct LoadedInput {
+    files: HashMap<String, EntrySource>,
+    peeked: Option<PeekedFormats>,
+}
+
+/// Read input `idx`. With `streaming` set, zip file inputs are opened into `zips` and only
+/// referenced; with `opts.preserve_input_compression`, so are zip bytes and URL inputs.
+/// Otherwise `zips` is left untouched.
+fn load_input(
+    idx: usize,
+    pack: &PackInput,
+    opts: &MergeOptions,
+    streaming: bool,
+    zips: &mut OpenZips,
+) -> Result<LoadedInput> {
+    let password = opts.zip_passwords.get(&idx).map(|s| s.as_str());
+    let mut pack_files: HashMap<String, EntrySource> = HashMap::new();
+    let mut mem: HashMap<String, Vec<u8>> = HashMap::new();
+    let label = describe_input(pack);
+    let dup = opts.on_duplicate_in_archive;
+    let encoding = opts.filename_encoding;
+    let peeked = match pack {
+        PackInput::Dir(p) => {
+            if streaming {
+                list_dir_entries(p, &mut pack_files)?;
+            } else {
+                read_dir_into_map(p, &mut mem)?;
+            }
+            peek_pack_format_from_dir(p)
+        }
+        PackInput::ZipFile(p) if custom_reader_for(p).is_some() => {
+            read_custom_into_map(p, &mut mem)?;
+            mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+        }
+        // Zip inputs are opened once: formats come from the pack.mcmeta found while reading
+        PackInput::ZipFile(p) => {
+            if opts.verify_input_crc {
+                verify_zip_crcs(&mut ZipArchive::new(File::open(p)?)?, password, &label)?;
+            }
+            if streaming || opts.preserve_input_compression {
+                let reader = ZipReader::File(File::open(p)?);
+                open_listed_zip(
+                    reader,
+                    &mut pack_files,
+                    zips,
+                    password,
+                    encoding,
+                    dup,
+                    &label,
+                )?
+            } else {
+                read_zipfile_into_map(p, &mut mem, password, encoding, dup)?;
+                mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+            }
+        }
+        PackInput::ZipBytes(b) => {
+            if opts.verify_input_crc {
+                verify_zip_crcs(&mut ZipArchive::new(Cursor::new(b))?, password, &label)?;
+            }
+            if opts.preserve_input_compression {
+                let reader = ZipReader::Bytes(Cursor::new(b.clone()));
+                open_listed_zip(
+                    reader,
+                    &mut pack_files,
+                    zips,
+                    password,
+                    encoding,
+                    dup,
+                    &label,
+                )?
+            } else {
+                read_zipbytes_into_map(b, &mut mem, password, encoding, dup, &label)?;
+                mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+            }
+        }
+        PackInput::Url(u) if opts.expand_url_listings && u.ends_with('/') => {
+            read_url_listing_into_map(u, &mut mem, opts, password)?;
+            mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+        }
+        PackInput::Url(u) => {
+            // CRC checks read every entry anyway, so ranged reads would only add requests
+            let ranged = match opts.ranged_url_reads && !opts.verify_input_crc {
+                true => HttpRangeReader::open(u, opts.per_input_timeout),
+                false => None,
+            };
+            // A ranged read that fails once the probe succeeded falls back to the full download,
+            // so its errors go through `url_failure_policy` like any other download's
+            let listed = ranged.and_then(|reader| {
+                let reader = ZipReader::Http(reader);
+                match open_listed_zip(
+                    reader,
+                    &mut pack_files,
+                    zips,
+                    password,
+                    encoding,
+                    dup,
+                    &label,
+                ) {
+                    Ok(peeked) => Some(peeked),
+                    Err(e) => {
+                        eprintln!("warning: ranged read of {} failed: {}", u, e);
+                        pack_files.clear();
+                        None
+                    }
+                }
+            });
+            match listed {
+                Some(peeked) => peeked,
+                None => match fetch_url_bytes_with_timeout(u, opts.per_input_timeout) {
+                    Ok(bytes) => {
+                        if opts.verify_input_crc {
+                            verify_zip_crcs(
+                                &mut ZipArchive::new(Cursor::new(&bytes))?,
+                                password,
+                                &label,
+                            )?;
+                        }
+                        if opts.preserve_input_compression {
+                            let reader = ZipReader::Bytes(Cursor::new(bytes));
+                            open_listed_zip(
+                                reader,
+                                &mut pack_files,
+                                zips,
+                                password,
+                                encoding,
+                                dup,
+                                &label,
+                            )?
+                        } else {
+                            read_zipbytes_into_map(
+                                &bytes, &mut mem, password, encoding, dup, &label,
+                            )?;
+                            mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+                        }
+                    }
+                    Err(e) => {
+                        if opts.skips_url_failure(&e) {
+                            eprintln!("warning: skipping input {}: {}", u, e);
+                            None
+                        } else {
+                            return Err(e);
+                        }
+                    }
+                },
+            }
+        }
+        PackInput::TarBytes(b) => {
+            // Tar archives aren't indexed, so read everything and peek from the map
+            read_tar_into_map(Cursor::new(b), &mut mem, dup, &label)?;
+            mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+        }
+    };
+    pack_files.extend(mem.into_iter().map(|(k, v)| (k, EntrySource::Memory(v))));
+    Ok(LoadedInput {
+        files: pack_files,
+        peeked,
+    })
+}
+
+/// Reads an input file of a custom format (e.g. `.7z`) into a path -> bytes map, as registered
+/// with [`register_reader`].
+pub type CustomReader = dyn Fn(&Path) -> Result<HashMap<String, Vec<u8>>> + Send + Sync;
+
+/// Readers registered with [`register_reader`], by lowercase extension.
+fn custom_readers() -> &'static std::sync::RwLock<HashMap<String, Arc<CustomReader>>> {
+    static READERS: std::sync::OnceLock<std::sync::RwLock<HashMap<String, Arc<CustomReader>>>> =
+        std::sync::OnceLock::new();
+    READERS.get_or_init(Default::default)
+}
+
+/// Register `reader` for file inputs with the extension `ext` (without the dot, matched
+/// case-insensitively), e.g. to merge `.rar` or `.7z` packs. Registered readers are consulted
+/// before the built-in zip reader whenever a merge or a read-only helper (e.g. [`read_mcmeta`]
+/// or [`classify_input`]) opens a [`PackInput::ZipFile`], process-wide; registering an
+/// extension again replaces its reader.
+///
+/// Paths returned by the reader are normalized like zip entry names; unsafe ones are skipped.
+pub fn register_reader(ext: &str, reader: Box<CustomReader>) {
+    let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+    custom_readers()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(ext, Arc::from(reader));
+}
+
+/// The registered reader for `path`'s extension, if any.
+fn custom_reader_for(path: &Path) -> Option<Arc<CustomReader>> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    custom_readers()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&ext)
+        .cloned()
+}
+
+/// Read a file input through its registered reader into `map`.
+fn read_custom_into_map(path: &Path, map: &mut HashMap<String, Vec<u8>>) -> Result<()> {
+    let Some(reader) = custom_reader_for(path) else {
+        return Err(MergeError::InvalidInput(format!(
+            "no reader registered for {}",
+            path.display()
+        )));
+    };
+    for (name, bytes) in reader(path)? {
+        if let Some(key) = sanitize_zip_entry_name(&name) {
+            map.insert(key, bytes);
+        }
     }
     Ok(())
 }
 
-/// Normalize a zip entry name into a safe forward-slash form suitable for
-/// using as a zip path and for converting into OS paths when extracting.
-/// Returns None for absolute paths or entries that attempt to traverse up
-/// the filesystem ("..").
-fn sanitize_zip_entry_name(name: &str) -> Option<String> {
-    // Convert any backslashes to forward slashes (some zip writers use them)
-    let n = name.replace('\\', "/");
-    // Reject absolute paths
-    if n.starts_with('/') || n.starts_with("\\") {
-        return None;
+/// Open a zip input into `zips` and list its entries into `files` as `ZipEntry` sources,
+/// returning the formats peeked from its pack.mcmeta.
+fn open_listed_zip(
+    reader: ZipReader,
+    files: &mut HashMap<String, EntrySource>,
+    zips: &mut OpenZips,
+    password: Option<&str>,
+    encoding: NameEncoding,
+    dup: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<Option<PeekedFormats>> {
+    let mut archive = open_zip_checked(reader, dup, label)?;
+    list_zip_entries(&mut archive, zips.len(), files, encoding, dup, label)?;
+    let peeked = peek_pack_format_from_archive(&mut archive, password, encoding);
+    zips.push((archive, password.map(|s| s.to_string())));
+    Ok(peeked)
+}
+
+/// [`load_input`] bounded by `opts.per_input_timeout`. Directory and zip file inputs are read on
+/// a watchdog thread; other inputs are in memory or (for URLs) use the request timeout.
+fn load_input_with_timeout(
+    idx: usize,
+    pack: &PackInput,
+    opts: &MergeOptions,
+    streaming: bool,
+    zips: &mut OpenZips,
+) -> Result<LoadedInput> {
+    let timeout = match (opts.per_input_timeout, pack) {
+        (Some(t), PackInput::Dir(_) | PackInput::ZipFile(_)) => t,
+        _ => return load_input(idx, pack, opts, streaming, zips),
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (thread_pack, thread_opts) = (pack.clone(), opts.clone());
+    std::thread::spawn(move || {
+        let mut own_zips = Vec::new();
+        let loaded = load_input(idx, &thread_pack, &thread_opts, streaming, &mut own_zips);
+        let _ = tx.send(loaded.map(|l| (l, own_zips)));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(loaded) => {
+            let (mut loaded, own_zips) = loaded?;
+            // The thread numbered its archives from zero; shift them behind the caller's
+            for src in loaded.files.values_mut() {
+                if let EntrySource::ZipEntry { archive, .. } = src {
+                    *archive += zips.len();
+                }
+            }
+            zips.extend(own_zips);
+            Ok(loaded)
+        }
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+            Err(MergeError::Timeout(describe_input(pack)))
+        }
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Err(MergeError::InvalidInput(
+            format!("reading {} failed unexpectedly", describe_input(pack)),
+        )),
     }
-    // Split and remove any empty components (caused by leading/trailing slashes)
-    let comps: Vec<&str> = n.split('/').filter(|s| !s.is_empty()).collect();
-    // Reject parent-traversal components for safety (zip-slip)
-    if comps.contains(&"..") {
-        return None;
+}
+
+/// Read all inputs and resolve which entry wins for every output path.
+///
+/// With `streaming` set, entries of directory and zip file inputs are only referenced, not read;
+/// in-memory inputs (zip/tar bytes and downloaded URLs) are always held in memory.
+///
+/// `base` is an optional previously merged zip used to seed the plan before `packs` are
+/// applied. Its synthesized root files are skipped so they get recomputed, but the formats and
+/// overlays declared by its pack.mcmeta still count.
+fn plan_merge(
+    base: Option<&[u8]>,
+    packs: &[PackInput],
+    opts: &MergeOptions,
+    streaming: bool,
+) -> Result<MergePlan> {
+    plan_merge_with(base, &input_labels(packs), opts, |idx, zips| {
+        load_input_with_timeout(idx, &packs[idx], opts, streaming, zips)
+    })
+}
+
+/// Like [`plan_merge`], but the inputs described by `labels` are obtained through `load`, which
+/// is called once per input index strictly in input order.
+fn plan_merge_with(
+    base: Option<&[u8]>,
+    labels: &[String],
+    opts: &MergeOptions,
+    mut load: impl FnMut(usize, &mut OpenZips) -> Result<LoadedInput>,
+) -> Result<MergePlan> {
+    let count = labels.len();
+    let mut plan = MergePlan {
+        files: HashMap::new(),
+        zips: Vec::new(),
+        found_formats: Vec::new(),
+        found_max_formats: Vec::new(),
+        overlays: None,
+        overridden: BTreeMap::new(),
+        first_metadata: HashMap::new(),
+        png_candidates: Vec::new(),
+        raw_copy: false,
+        sources: HashMap::new(),
+        language: None,
+        dropped: BTreeSet::new(),
+    };
+    let mut overlays_values: Vec<serde_json::Value> = Vec::new();
+
+    if let Some(base) = base {
+        let mut mem: HashMap<String, Vec<u8>> = HashMap::new();
+        read_zipbytes_into_map(
+            base,
+            &mut mem,
+            None,
+            None,
+            opts.on_duplicate_in_archive,
+            "base zip",
+        )?;
+        if let Some((pf, mf, overlays)) =
+            mem.get("pack.mcmeta").and_then(|m| parse_peeked_mcmeta(m))
+        {
+            plan.found_formats.push(pf);
+            plan.found_max_formats.extend(mf);
+            overlays_values.extend(overlays);
+        }
+        plan.files.extend(
+            mem.into_iter()
+                .filter(|(k, _)| !SYNTHESIZED_ROOT_FILES.contains(&k.as_str()))
+                .map(|(k, v)| (k, EntrySource::Memory(v))),
+        );
     }
-    if comps.is_empty() {
-        return None;
+
+    // Inspect each input for pack.mcmeta to collect pack_format values across all inputs.
+    // We do a best-effort peek so we can choose the HIGHEST pack_format observed, independent
+    // of later overwrites.
+    let mut first_kept = true;
+    for (idx, label) in labels.iter().enumerate() {
+        opts.check_cancelled()?;
+        opts.report_progress(ProgressEvent::InputStarted {
+            index: idx,
+            total: count,
+        });
+        // Read each input into its own map first so entries can be combined with what earlier
+        // inputs produced (e.g. tag unions) instead of blindly overwriting.
+        let LoadedInput {
+            files: pack_files,
+            peeked,
+        } = load(idx, &mut plan.zips)?;
+        // Empty inputs (e.g. skipped downloads) add nothing, so only judge those with files
+        if opts.require_valid_pack
+            && !pack_files.is_empty()
+            && classify_names(pack_files.keys().map(|k| k.as_str())) == PackClassification::NotAPack
+        {
+            return Err(MergeError::InvalidInput(format!(
+                "{}: not a resource or data pack: it has no pack.mcmeta and no assets/ or data/ folder at its root (was the wrong folder zipped?)",
+                label
+            )));
+        }
+        if let (None, Some(src)) = (&peeked, pack_files.get("pack.mcmeta")) {
+            check_root_mcmeta_encoding(idx, &read_source(src, &mut plan.zips)?)?;
+        }
+        if let (Some(floor), Some((pf, _, _))) = (opts.drop_inputs_below_format, &peeked) {
+            if *pf < floor {
+                eprintln!(
+                    "warning: skipping input {}: pack_format {} is below {}",
+                    idx + 1,
+                    pf,
+                    floor
+                );
+                plan.dropped.insert(idx);
+                continue;
+            }
+        }
+        if let Some((pf, mf, overlays)) = peeked {
+            plan.found_formats.push(pf);
+            if let Some(max) = mf {
+                plan.found_max_formats.push(max);
+            }
+            if let Some(ov) = overlays {
+                overlays_values.push(ov);
+            }
+        }
+        if std::mem::take(&mut first_kept) {
+            plan.first_metadata = pack_files
+                .iter()
+                .filter(|(k, _)| SYNTHESIZED_ROOT_FILES.contains(&k.as_str()))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+        }
+        if opts.pack_png_policy == PackPngPolicy::HighestResolution {
+            plan.png_candidates
+                .extend(pack_files.get("pack.png").cloned());
+        }
+        if let (true, Some(src)) = (opts.merge_language_block, pack_files.get("pack.mcmeta")) {
+            let mcmeta = read_source(src, &mut plan.zips)?;
+            merge_language_block(&mut plan.language, &mcmeta);
+        }
+
+        for (key, src) in pack_files {
+            if let Some(merge_json) = json_merge_for(&key, opts) {
+                if let Some(prev) = plan.files.get(&key) {
+                    let prev = read_source(prev, &mut plan.zips)?;
+                    let next = read_source(&src, &mut plan.zips)?;
+                    if let Some(merged) = merge_json(&prev, &next) {
+                        plan.sources.insert(key.clone(), idx);
+                        plan.files.insert(key, EntrySource::Memory(merged));
+                        continue;
+                    }
+                }
+            }
+            if place_entry(&mut plan, &key, idx, opts, || format!("input {}", idx + 1))? {
+                plan.sources.insert(key.clone(), idx);
+                plan.files.insert(key, src);
+            }
+        }
     }
-    Some(comps.join("/"))
-}
 
-// Peek functions: try to locate pack.mcmeta and extract pack_format without reading all files.
-// Returns (pack_format, max_format_option, overlays_option)
-fn peek_pack_format_from_zipbytes(
-    bytes: &[u8],
-) -> Option<(u32, Option<u32>, Option<serde_json::Value>)> {
-    let cursor = Cursor::new(bytes);
-    if let Ok(mut archive) = ZipArchive::new(cursor) {
-        if let Ok(mut file) = archive.by_name("pack.mcmeta") {
-            let mut buf = String::new();
-            if file.read_to_string(&mut buf).is_ok() {
-                if let Ok(formats) = extract_pack_format_from_mcmeta(&buf) {
-                    let overlays = extract_overlays_from_mcmeta(&buf);
-                    return Some((formats.0, formats.1, overlays));
+    // Restrict to the requested namespaces, if any
+    if let Some(ns) = &opts.namespaces {
+        plan.files.retain(|k, _| path_in_namespaces(k, ns));
+    }
+    if !opts.deny_namespaces.is_empty() {
+        plan.files.retain(|k, _| {
+            path_namespace(k).is_none_or(|ns| !opts.deny_namespaces.iter().any(|d| d == ns))
+        });
+    }
+
+    if let Some(reference) = &opts.strip_vanilla_identical {
+        // The reference is only listed; just the entries the merge also has are read
+        let reference_opts = MergeOptions {
+            per_input_timeout: opts.per_input_timeout,
+            filename_encoding: opts.filename_encoding,
+            on_duplicate_in_archive: opts.on_duplicate_in_archive,
+            ..MergeOptions::default()
+        };
+        let mut reference_zips = Vec::new();
+        let vanilla = load_input_with_timeout(
+            0,
+            &PackInput::from(reference.clone()),
+            &reference_opts,
+            true,
+            &mut reference_zips,
+        )?;
+        let mut identical = Vec::new();
+        for (path, src) in &plan.files {
+            if SYNTHESIZED_ROOT_FILES.contains(&path.as_str()) {
+                continue;
+            }
+            if let Some(vanilla_src) = vanilla.files.get(path) {
+                if read_source(src, &mut plan.zips)?
+                    == read_source(vanilla_src, &mut reference_zips)?
+                {
+                    identical.push(path.clone());
                 }
             }
         }
+        for path in identical {
+            plan.files.remove(&path);
+        }
     }
-    None
-}
 
-fn peek_pack_format_from_zipfile(
-    path: &Path,
-) -> Option<(u32, Option<u32>, Option<serde_json::Value>)> {
-    if let Ok(f) = File::open(path) {
-        if let Ok(mut archive) = ZipArchive::new(f) {
-            if let Ok(mut file) = archive.by_name("pack.mcmeta") {
-                let mut buf = String::new();
-                if file.read_to_string(&mut buf).is_ok() {
-                    if let Ok(formats) = extract_pack_format_from_mcmeta(&buf) {
-                        let overlays = extract_overlays_from_mcmeta(&buf);
-                        return Some((formats.0, formats.1, overlays));
+    // Merge overlays: later ones overwrite earlier, keyed by directory name
+    if let (true, Some(msg)) = (
+        opts.strict_overlays,
+        overlay_range_conflict(&overlays_values),
+    ) {
+        return Err(MergeError::InvalidInput(msg));
+    }
+    plan.overlays = merge_overlays(&overlays_values);
+
+    // Optionally resolve overlays for a single target format and drop the overlay directories
+    if let Some(target) = opts.flatten_overlays_to {
+        if let Some(ov) = plan.overlays.take() {
+            flatten_overlays(&mut plan.files, &ov, target);
+            flatten_overlays(&mut plan.sources, &ov, target);
+        }
+    }
+
+    // Overlay copies are only redundant if every overlay agrees with the base; otherwise the
+    // order in which overlays apply could change which version the game sees
+    if opts.coalesce_identical_overlay_files {
+        for (path, copies) in overlay_copies(&mut plan)? {
+            let base = match plan.files.get(&path) {
+                Some(src) => read_source(src, &mut plan.zips)?,
+                None => continue,
+            };
+            if copies.iter().all(|(_, bytes)| *bytes == base) {
+                for (dir, _) in copies {
+                    plan.files.remove(&format!("{}/{}", dir, path));
+                }
+            }
+        }
+    }
+
+    // Injected files come after every input, overlay flattening and coalescing, and follow the
+    // same overwrite policy. They carry no provenance, even where they replace an input's file.
+    for (name, bytes) in &opts.extra_files {
+        let key = sanitize_zip_entry_name(name).ok_or_else(|| {
+            MergeError::InvalidInput(format!("invalid extra file path: {}", name))
+        })?;
+        if place_entry(&mut plan, &key, count, opts, || "extra file".to_string())? {
+            plan.sources.remove(&key);
+            plan.files.insert(key, EntrySource::Memory(bytes.clone()));
+        }
+    }
+
+    // Drop paths from the final result, whichever stage produced them. Root files are
+    // synthesized separately and can't be removed this way.
+    if !opts.remove.is_empty() {
+        plan.files.retain(|k, _| {
+            SYNTHESIZED_ROOT_FILES.contains(&k.as_str())
+                || !opts.remove.iter().any(|p| glob_match(p, k))
+        });
+    }
+
+    // Inspect any pack.mcmeta files found and collect pack_format values
+    // (overlays are collected during the peek phase above)
+    let mcmeta_keys: Vec<String> = plan
+        .files
+        .keys()
+        .filter(|k| *k == "pack.mcmeta" || k.ends_with("/pack.mcmeta"))
+        .cloned()
+        .collect();
+    for k in mcmeta_keys {
+        if let Ok(v) = read_source(&plan.files[&k], &mut plan.zips) {
+            if let Ok(s) = decode_json_text(&v) {
+                if let Ok((pf, mf)) = extract_pack_format_from_mcmeta(s) {
+                    plan.found_formats.push(pf);
+                    if let Some(max) = mf {
+                        plan.found_max_formats.push(max);
                     }
                 }
             }
         }
     }
-    None
-}
 
-fn peek_pack_format_from_dir(dir: &Path) -> Option<(u32, Option<u32>, Option<serde_json::Value>)> {
-    let p = dir.join("pack.mcmeta");
-    if p.is_file() {
-        if let Ok(s) = std::fs::read_to_string(p) {
-            if let Ok(formats) = extract_pack_format_from_mcmeta(&s) {
-                let overlays = extract_overlays_from_mcmeta(&s);
-                return Some((formats.0, formats.1, overlays));
+    if opts.validate_all_mcmeta {
+        let mut keys: Vec<String> = plan
+            .files
+            .keys()
+            .filter(|k| k.ends_with(".mcmeta"))
+            .cloned()
+            .collect();
+        keys.sort();
+        let mut broken = Vec::new();
+        for k in keys {
+            let bytes = read_source(&plan.files[&k], &mut plan.zips)?;
+            let parsed = decode_json_text(&bytes).and_then(|s| {
+                serde_json::from_str::<serde_json::Value>(s).map_err(|e| e.to_string())
+            });
+            if let Err(e) = parsed {
+                broken.push(format!("{}: {}", k, e));
             }
         }
+        if !broken.is_empty() {
+            return Err(MergeError::InvalidInput(format!(
+                "invalid mcmeta files: {}",
+                broken.join("; ")
+            )));
+        }
     }
-    None
+
+    // Optionally sanity-check the icon carried by the inputs. This only warns; the written
+    // pack.png is not affected.
+    if opts.validate_png_dimensions && opts.validate_pack_png {
+        if let Some(src) = plan.files.get("pack.png") {
+            if let Ok(png) = read_source(src, &mut plan.zips) {
+                if let Err(e) = check_pack_png_dimensions(&png) {
+                    eprintln!("warning: pack.png: {}", e);
+                }
+            }
+        }
+    }
+
+    Ok(plan)
 }
 
-/// Extract overlays section from a pack.mcmeta JSON string.
-fn extract_overlays_from_mcmeta(s: &str) -> Option<serde_json::Value> {
-    if let Ok(json) = serde_json::from_str::<serde_json::Value>(s) {
-        if let Some(overlays) = json.get("overlays") {
-            return Some(overlays.clone());
+/// Write a planned merge as a zip into `writer`, adding the synthesized pack.mcmeta, pack.png
+/// and README.md, and return the writer.
+fn write_merged_zip<W: Write + Seek>(
+    plan: &mut MergePlan,
+    inputs: &[String],
+    opts: &MergeOptions,
+    writer: W,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let mut options: zip::write::FileOptions<'_, zip::write::ExtendedFileOptions> =
+        zip::write::FileOptions::default().unix_permissions(0o644);
+    if opts.fixed_timestamps {
+        options = options.last_modified_time(zip::DateTime::default());
+    }
+    if let Some(choice) = opts.compression {
+        options = with_compression(options, choice);
+    }
+
+    // Root metadata files are carried or synthesized separately from the merged entries
+    let mut roots = build_root_files(plan, inputs, opts)?;
+    if let Some(msg) = format_span_warning(&plan.found_formats, opts) {
+        eprintln!("warning: {}", msg);
+    }
+
+    // Ensure deterministic order by sorting keys
+    let mut keys: Vec<&str> = plan
+        .files
+        .keys()
+        .map(|k| k.as_str())
+        .filter(|k| !SYNTHESIZED_ROOT_FILES.contains(k))
+        .collect();
+    keys.sort();
+    let order: Vec<&str> = match opts.entry_order {
+        EntryOrder::Lexical => keys.into_iter().chain(SYNTHESIZED_ROOT_FILES).collect(),
+        EntryOrder::McmetaFirst => {
+            let (assets, mut rest): (Vec<&str>, Vec<&str>) =
+                keys.into_iter().partition(|k| k.starts_with("assets/"));
+            rest.push("README.md");
+            rest.sort();
+            ["pack.mcmeta", "pack.png"]
+                .into_iter()
+                .chain(assets)
+                .chain(rest)
+                .collect()
+        }
+    };
+
+    let total = order.len();
+    let written = |index: usize, key: &str| ProgressEvent::EntryWritten {
+        index,
+        total,
+        path: key.to_string(),
+    };
+    for (i, key) in order.into_iter().enumerate() {
+        opts.check_cancelled()?;
+        let raw_candidate = !roots.contains_key(key)
+            && (plan.raw_copy || (opts.preserve_input_compression && keeps_entry_bytes(key, opts)));
+        if let (true, Some(&EntrySource::ZipEntry { archive, index })) =
+            (raw_candidate, plan.files.get(key))
+        {
+            let entry = plan.zips[archive].0.by_index_raw(index)?;
+            let copyable = (plan.raw_copy || !entry.encrypted())
+                && matches!(
+                    entry.compression(),
+                    zip::CompressionMethod::Stored | zip::CompressionMethod::Deflated
+                );
+            if copyable {
+                zip.raw_copy_file_rename(entry, key)?;
+                opts.report_progress(written(i, key));
+                continue;
+            }
         }
+        let mut entry_options = entry_file_options(key, &options, opts);
+        if let (true, Some(idx)) = (opts.embed_provenance_extra, plan.sources.get(key)) {
+            let data = (*idx as u32).to_le_bytes();
+            entry_options.add_extra_data(PROVENANCE_EXTRA_FIELD_ID, Box::new(data), false)?;
+        }
+        zip.start_file(key, entry_options)?;
+        if let Some(bytes) = roots.remove(key) {
+            zip.write_all(&bytes)?;
+        } else if opts.rewrites(key) {
+            let bytes = read_source(&plan.files[key], &mut plan.zips)?;
+            zip.write_all(&opts.apply_transforms(key, bytes)?)?;
+        } else {
+            copy_source(&plan.files[key], &mut plan.zips, &mut zip, opts.buffer_size)?;
+        }
+        opts.report_progress(written(i, key));
     }
-    None
+
+    if let Some(comment) = &opts.zip_comment {
+        zip.set_comment(comment.as_str());
+    }
+    Ok(zip.finish()?)
 }
 
-/// Merge overlays from multiple pack.mcmeta files.
-/// Later overlays overwrite earlier ones based on directory name.
-fn merge_overlays(overlays_list: &[serde_json::Value]) -> Option<serde_json::Value> {
-    if overlays_list.is_empty() {
-        return None;
+/// Produce the output's pack.mcmeta, pack.png and README.md, carried from the inputs or
+/// synthesized according to `opts`, with transforms applied.
+fn build_root_files(
+    plan: &mut MergePlan,
+    inputs: &[String],
+    opts: &MergeOptions,
+) -> Result<HashMap<&'static str, Vec<u8>>> {
+    // Only written outputs print the warning; read-only callers report it themselves
+    if let (true, Some(msg)) = (
+        opts.strict_format_span,
+        format_span_warning(&plan.found_formats, opts),
+    ) {
+        return Err(MergeError::InvalidInput(msg));
     }
 
-    // Collect all overlay entries, keyed by directory name (later overwrites earlier)
-    let mut merged_entries: HashMap<String, serde_json::Value> = HashMap::new();
+    // Ensure pack.mcmeta exists with an appropriate pack_format & supported_formats
+    let source = opts.metadata_source();
+    let mcmeta = match carried_metadata(&plan.files, &plan.first_metadata, source, "pack.mcmeta") {
+        Some(src) => read_source(src, &mut plan.zips)?,
+        None => synthesize_pack_mcmeta(
+            &plan.found_formats,
+            &plan.found_max_formats,
+            plan.overlays.as_ref(),
+            opts,
+        )
+        .into_bytes(),
+    };
+    let mcmeta = match &plan.language {
+        Some(language) => with_language_block(&mcmeta, language, opts.pretty_mcmeta)?,
+        None => mcmeta,
+    };
+    let mcmeta = opts.apply_transforms("pack.mcmeta", mcmeta)?;
+
+    // Ensure pack.png exists (small default) if missing
+    // Unless resizing is requested or input metadata or icons are kept, always write our embedded
+    // default pack.png into the merged zip as pack.png. This ensures a consistent default image
+    // regardless of input packs.
+    let carried_png = carried_metadata(&plan.files, &plan.first_metadata, source, "pack.png");
+    let input_png = match (carried_png, plan.files.get("pack.png")) {
+        _ if opts.pack_png_policy == PackPngPolicy::HighestResolution => {
+            highest_resolution_png(&plan.png_candidates, &mut plan.zips)?
+        }
+        (Some(src), _) => Some(read_source(src, &mut plan.zips)?),
+        (None, Some(src)) if opts.resize_png_to.is_some() || opts.keep_input_pack_png => {
+            Some(read_source(src, &mut plan.zips)?)
+        }
+        _ => None,
+    };
+    let png = match (opts.resize_png_to, input_png) {
+        (Some(size), Some(src)) => resize_pack_png(&src, size).unwrap_or_else(|e| {
+            eprintln!("warning: pack.png: {}; using default icon", e);
+            default_pack_png_bytes()
+        }),
+        (None, Some(src)) if opts.validate_pack_png && !src.starts_with(PNG_SIGNATURE) => {
+            eprintln!("warning: pack.png is not a PNG file; using default icon");
+            default_pack_png_bytes()
+        }
+        (None, Some(src)) => src,
+        _ => default_pack_png_bytes(),
+    };
+    let png = opts.apply_transforms("pack.png", png)?;
+
+    // Ensure README.md exists; an input's copy wins unless a generated one is asked for
+    let readme_source = if opts.generate_readme || opts.include_conflict_log {
+        MetadataSource::Synthesize
+    } else if source == MetadataSource::Synthesize {
+        MetadataSource::LastInput
+    } else {
+        source
+    };
+    let readme = match carried_metadata(
+        &plan.files,
+        &plan.first_metadata,
+        readme_source,
+        "README.md",
+    ) {
+        Some(src) => read_source(src, &mut plan.zips)?,
+        None => {
+            let overridden: Vec<(&str, usize)> = plan
+                .overridden
+                .iter()
+                .filter(|(k, _)| plan.files.contains_key(*k))
+                .map(|(k, i)| (k.as_str(), *i))
+                .collect();
+            let conflicts = opts.include_conflict_log.then_some(overridden.as_slice());
+            make_readme(inputs, &plan.dropped, conflicts).into_bytes()
+        }
+    };
+    let readme = opts.apply_transforms("README.md", readme)?;
+
+    Ok(HashMap::from([
+        ("pack.mcmeta", mcmeta),
+        ("pack.png", png),
+        ("README.md", readme),
+    ]))
+}
+
+/// The input version of root metadata file `name` to carry into the output under `source`, if
+/// any. `None` means the file is synthesized.
+fn carried_metadata<'a>(
+    files: &'a HashMap<String, EntrySource>,
+    first_metadata: &'a HashMap<String, EntrySource>,
+    source: MetadataSource,
+    name: &str,
+) -> Option<&'a EntrySource> {
+    match source {
+        MetadataSource::FirstInput => first_metadata.get(name),
+        MetadataSource::LastInput => files.get(name),
+        MetadataSource::Synthesize => None,
+    }
+}
+
+/// Message for `opts.warn_on_wide_format_span`, if the found pack_format values exceed it.
+fn format_span_warning(found_formats: &[u32], opts: &MergeOptions) -> Option<String> {
+    let limit = opts.warn_on_wide_format_span?;
+    let low = *found_formats.iter().min()?;
+    let high = *found_formats.iter().max()?;
+    (high - low > limit).then(|| {
+        format!(
+            "inputs span pack_format {} to {}, more than {} apart; they are likely incompatible",
+            low, high, limit
+        )
+    })
+}
+
+/// Build the merged pack.mcmeta text from the formats and overlays detected across inputs.
+fn synthesize_pack_mcmeta(
+    found_formats: &[u32],
+    found_max_formats: &[u32],
+    overlays: Option<&serde_json::Value>,
+    opts: &MergeOptions,
+) -> String {
+    // Determine final pack_format: override via opts if present, otherwise highest found or 1
+    let final_pack_fmt = if let Some(ov) = opts.pack_format_override {
+        ov
+    } else if found_formats.is_empty() {
+        1u32
+    } else {
+        *found_formats.iter().max().unwrap_or(&1u32)
+    };
+
+    // Compute supported_formats vector based on policy.
+    // For user-friendly pack.mcmeta we emit only the endpoint values (lowest/highest)
+    // instead of every integer in the inclusive range. Examples:
+    // - OneToHighest => [1, high]
+    // - LowestToHighest => [low, high]
+    // If low == high we emit a single-element array [low].
+    let supported_formats: Vec<u32> = match opts.supported_formats_policy {
+        SupportedFormatsPolicy::OneToHighest => {
+            let high = if found_formats.is_empty() {
+                final_pack_fmt
+            } else {
+                *found_formats.iter().max().unwrap_or(&final_pack_fmt)
+            };
+            if high <= 1 {
+                vec![1u32]
+            } else {
+                vec![1u32, high]
+            }
+        }
+        SupportedFormatsPolicy::LowestToHighest => {
+            if found_formats.is_empty() {
+                vec![final_pack_fmt]
+            } else {
+                let low = *found_formats.iter().min().unwrap_or(&final_pack_fmt);
+                let high = *found_formats.iter().max().unwrap_or(&final_pack_fmt);
+                if low == high {
+                    vec![low]
+                } else {
+                    vec![low, high]
+                }
+            }
+        }
+        SupportedFormatsPolicy::OneToLatest => {
+            // Not implemented: fall back to OneToHighest for now
+            let high = if found_formats.is_empty() {
+                final_pack_fmt
+            } else {
+                *found_formats.iter().max().unwrap_or(&final_pack_fmt)
+            };
+            if high <= 1 {
+                vec![1u32]
+            } else {
+                vec![1u32, high]
+            }
+        }
+    };
+
+    // Determine actual max format from all sources
+    let actual_max_format = if found_max_formats.is_empty() {
+        *supported_formats.last().unwrap_or(&final_pack_fmt)
+    } else {
+        *found_max_formats.iter().max().unwrap_or(&final_pack_fmt)
+    };
+
+    let description = opts.description_override.clone().unwrap_or_else(|| {
+        if opts.include_version_in_description {
+            format!(
+                "Made with Rust API: resource_merger:{}",
+                env!("CARGO_PKG_VERSION")
+            )
+        } else {
+            "Made with Rust API: resource_merger".to_string()
+        }
+    });
+    make_pack_mcmeta(
+        final_pack_fmt,
+        &supported_formats,
+        &description,
+        actual_max_format,
+        overlays,
+        opts.mcmeta_post_process.as_ref(),
+        opts.pretty_mcmeta,
+    )
+}
+
+/// Root files that are written separately from the merged entries, synthesized unless
+/// [`MergeOptions::prefer_input_metadata`] carries an input's version (README.md is carried
+/// by default, see [`MergeOptions::generate_readme`]).
+const SYNTHESIZED_ROOT_FILES: [&str; 3] = ["pack.mcmeta", "pack.png", "README.md"];
+
+/// Header ID of the zip extra field written by [`MergeOptions::embed_provenance_extra`]. Its
+/// data is the zero-based index of the source input as a little-endian `u32`. Root files don't
+/// get one, nor do [`MergeOptions::extra_files`] or entries of the base zip given to
+/// [`merge_onto`].
+pub const PROVENANCE_EXTRA_FIELD_ID: u16 = 0x524d;
+
+/// Whether the entry written at `key` is left exactly as an input stored it: no transform,
+/// compression setting, fixed timestamp or provenance field applies to it.
+fn keeps_entry_bytes(key: &str, opts: &MergeOptions) -> bool {
+    let ext = Path::new(key)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    !opts.fixed_timestamps
+        && opts.compression.is_none()
+        && !opts.embed_provenance_extra
+        && !opts.rewrites(key)
+        && !ext.is_some_and(|e| opts.per_extension_compression.contains_key(&e))
+}
+
+/// Build the zip options for one output entry, applying `per_extension_compression`.
+fn entry_file_options<'a>(
+    name: &str,
+    base: &zip::write::FileOptions<'a, zip::write::ExtendedFileOptions>,
+    opts: &MergeOptions,
+) -> zip::write::FileOptions<'a, zip::write::ExtendedFileOptions> {
+    let ext = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match ext.and_then(|e| opts.per_extension_compression.get(&e)) {
+        Some(choice) => with_compression(base.clone(), *choice),
+        None => base.clone(),
+    }
+}
+
+/// Set the compression method and level of `options` from `choice`.
+fn with_compression<'a>(
+    options: zip::write::FileOptions<'a, zip::write::ExtendedFileOptions>,
+    choice: CompressionChoice,
+) -> zip::write::FileOptions<'a, zip::write::ExtendedFileOptions> {
+    match choice {
+        CompressionChoice::Stored => options.compression_method(zip::CompressionMethod::Stored),
+        CompressionChoice::Deflated(level) => options
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(level),
+    }
+}
+
+/// Merge packs and write resulting zip to a file path.
+pub fn merge_packs_to_file<P: AsRef<Path>>(packs: &[PackInput], out: P) -> Result<()> {
+    let bytes = merge_packs_to_bytes(packs)?;
+    std::fs::write(out, bytes)?;
+    Ok(())
+}
+
+/// Merge with options and write to file. Uses the in-memory path unless the inputs exceed
+/// `opts.streaming_threshold_bytes`, in which case entries are streamed into the output file.
+pub fn merge_packs_to_file_with_options<P: AsRef<Path>>(
+    packs: &[PackInput],
+    out: P,
+    opts: &MergeOptions,
+) -> Result<()> {
+    // For now, if dry_run just compute plan via merge_packs_to_bytes read-only scan
+    if opts.dry_run {
+        // perform a simple scan to validate inputs and return early (no writes)
+        let _ = merge_packs_to_bytes_with_options(packs, opts)?;
+        return Ok(());
+    }
+
+    // A single zip input is copied through without decoding its entries. Otherwise small
+    // inputs are read into memory, but the zip itself is written straight to the file instead
+    // of being assembled in a buffer first
+    let mut plan = match plan_single_zip_pass_through(packs, opts)? {
+        Some(plan) => plan,
+        None => plan_merge(None, packs, opts, use_streaming(packs, opts))?,
+    };
+
+    if let Some(cap) = opts.split_output_bytes {
+        let tmp = write_merged_zip(&mut plan, &input_labels(packs), opts, scratch_file(opts)?)?;
+        return write_split_output(out.as_ref(), tmp, cap, opts);
+    }
+
+    write_output_file_with(out.as_ref(), opts, |f| {
+        write_merged_zip(&mut plan, &input_labels(packs), opts, f)?;
+        Ok(())
+    })
+}
+
+/// Summary of a merge run through [`merge_with_controls`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Number of inputs merged
+    pub inputs: usize,
+    /// Number of entries written to the output zip, including pack.mcmeta, pack.png and README.md
+    pub entries: usize,
+    /// Size of the file at `out` afterwards, or the total size of its parts for split output
+    /// (0 if none was written, e.g. for a dry run)
+    pub bytes_written: u64,
+}
+
+/// Merge into the file `out` with live progress and cancellation, for interactive frontends.
+///
+/// `progress` is called on the calling thread for every [`ProgressEvent`] while the merge runs
+/// on a worker thread, so it doesn't need to be `Send`. Setting `cancel` stops the merge with
+/// [`MergeError::Cancelled`]. `opts.progress` and `opts.cancel` are replaced for this call.
+pub fn merge_with_controls<P: AsRef<Path>>(
+    packs: &[PackInput],
+    out: P,
+    opts: &MergeOptions,
+    mut progress: impl FnMut(ProgressEvent),
+    cancel: &AtomicBool,
+) -> Result<MergeReport> {
+    let out = out.as_ref();
+    let flag = Arc::new(AtomicBool::new(cancel.load(Ordering::Relaxed)));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let opts = MergeOptions {
+        cancel: Some(flag.clone()),
+        progress: Some(ProgressHook::new(move |event| {
+            let _ = tx.send(event);
+        })),
+        ..opts.clone()
+    };
+    let mut entries = 0;
+    std::thread::scope(|s| {
+        // The worker owns the only sender, so the channel disconnects once the merge returns
+        let worker = s.spawn(move || merge_packs_to_file_with_options(packs, out, &opts));
+        loop {
+            match rx.recv_timeout(std::time::Duration::from_millis(20)) {
+                Ok(event) => {
+                    if matches!(event, ProgressEvent::EntryWritten { .. }) {
+                        entries += 1;
+                    }
+                    progress(event);
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if cancel.load(Ordering::Relaxed) {
+                flag.store(true, Ordering::Relaxed);
+            }
+        }
+        worker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })?;
+    Ok(MergeReport {
+        inputs: packs.len(),
+        entries,
+        bytes_written: written_output_size(out),
+    })
+}
+
+/// Write a merged zip to `out`, or split it into numbered parts next to `out` when it is larger
+/// than `cap` bytes. Entries are copied without recompression and distributed first-fit by
+/// size; the synthesized root files are repeated in every part.
+fn write_split_output<R: Read + Seek>(
+    out: &Path,
+    mut merged: R,
+    cap: u64,
+    opts: &MergeOptions,
+) -> Result<()> {
+    let total = merged.seek(SeekFrom::End(0))?;
+    merged.seek(SeekFrom::Start(0))?;
+    if total <= cap {
+        write_output_file_with(out, opts, |f| {
+            std::io::copy(&mut merged, f)?;
+            Ok(())
+        })?;
+        return remove_stale_parts(out, 1);
+    }
+
+    // Upper bound of an entry's footprint: data plus local and central headers (name twice,
+    // extra fields assumed under 32 bytes each)
+    let entry_cost = |name: &str, compressed: u64| compressed + 2 * (name.len() as u64 + 32) + 76;
+    let mut archive = ZipArchive::new(merged)?;
+    let mut shared_cost = 22; // end of central directory record
+    let mut entries: Vec<(String, u64)> = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let cost = entry_cost(file.name(), file.compressed_size());
+        if SYNTHESIZED_ROOT_FILES.contains(&file.name()) {
+            shared_cost += cost;
+        } else {
+            entries.push((file.name().to_string(), cost));
+        }
+    }
+    let capacity = cap.saturating_sub(shared_cost);
+    if let Some((name, cost)) = entries.iter().find(|(_, c)| *c > capacity) {
+        return Err(MergeError::InvalidInput(format!(
+            "{} needs about {} bytes, which doesn't fit in a {} byte part",
+            name,
+            cost + shared_cost,
+            cap
+        )));
+    }
+
+    // First-fit decreasing bin packing
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut parts: Vec<(u64, Vec<String>)> = Vec::new();
+    for (name, cost) in entries {
+        match parts.iter_mut().find(|(used, _)| used + cost <= capacity) {
+            Some((used, names)) => {
+                *used += cost;
+                names.push(name);
+            }
+            None => parts.push((cost, vec![name])),
+        }
+    }
+
+    let part_count = parts.len();
+    for (n, (_, mut names)) in parts.into_iter().enumerate() {
+        opts.check_cancelled()?;
+        names.sort();
+        names.extend(SYNTHESIZED_ROOT_FILES.iter().map(|s| s.to_string()));
+        write_output_file_with(&split_part_path(out, n + 1), opts, |f| {
+            let mut zip = ZipWriter::new(f);
+            for name in &names {
+                // The other names were listed from this archive, so only a root file can be absent
+                if let Some(index) = archive.index_for_name(name) {
+                    zip.raw_copy_file(archive.by_index_raw(index)?)?;
+                }
+            }
+            if let Some(comment) = &opts.zip_comment {
+                zip.set_comment(comment.as_str());
+            }
+            zip.finish()?;
+            Ok(())
+        })?;
+    }
+    remove_stale_parts(out, part_count + 1)?;
+    remove_output_file(out)
+}
+
+/// Remove the parts numbered `first` and up that an earlier, larger split left next to `out`.
+fn remove_stale_parts(out: &Path, first: usize) -> Result<()> {
+    let mut n = first;
+    while split_part_path(out, n).is_file() {
+        remove_output_file(&split_part_path(out, n))?;
+        n += 1;
+    }
+    Ok(())
+}
+
+/// Remove an output file written by an earlier run, with its checksum sidecar, if present.
+fn remove_output_file(path: &Path) -> Result<()> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    for file in [path, Path::new(&sidecar)] {
+        if file.is_file() {
+            std::fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Total size of the output written to `out`: the file itself or, for split output, its parts.
+fn written_output_size(out: &Path) -> u64 {
+    let size = |p: &Path| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    let mut total = size(out);
+    let mut n = 1;
+    while split_part_path(out, n).is_file() {
+        total += size(&split_part_path(out, n));
+        n += 1;
+    }
+    total
+}
+
+/// Path of part `n` of a split output: `merged.zip` becomes `merged.part<n>.zip`.
+fn split_part_path(out: &Path, n: usize) -> PathBuf {
+    let stem = out.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match out.extension() {
+        Some(ext) => format!("{}.part{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.part{}", stem, n),
+    };
+    out.with_file_name(name)
+}
+
+/// Download a canonical base pack from `url` and layer the local edits in `overrides_dir` on
+/// top, writing the merged zip to `out`. Equivalent to merging `[Url(url), Dir(overrides_dir)]`.
+pub fn merge_base_url_with_dir<P: AsRef<Path>>(
+    url: &str,
+    overrides_dir: &Path,
+    out: P,
+    opts: &MergeOptions,
+) -> Result<()> {
+    let packs = [
+        PackInput::Url(url.to_string()),
+        PackInput::Dir(overrides_dir.to_path_buf()),
+    ];
+    merge_packs_to_file_with_options(&packs, out, opts)
+}
+
+/// Cheap estimate of the total input size in bytes, used to pick a merge strategy.
+/// URL inputs aren't downloaded for this and count as zero.
+fn estimate_input_size(packs: &[PackInput]) -> u64 {
+    packs
+        .iter()
+        .map(|p| match p {
+            PackInput::Dir(d) => WalkDir::new(d)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum(),
+            PackInput::ZipFile(f) => std::fs::metadata(f).map(|m| m.len()).unwrap_or(0),
+            PackInput::ZipBytes(b) | PackInput::TarBytes(b) => b.len() as u64,
+            PackInput::Url(_) => 0,
+        })
+        .sum()
+}
+
+/// Whether `opts.streaming_threshold_bytes` selects the streaming strategy for these inputs.
+fn use_streaming(packs: &[PackInput], opts: &MergeOptions) -> bool {
+    opts.streaming_threshold_bytes
+        .map(|t| estimate_input_size(packs) > t)
+        .unwrap_or(false)
+}
+
+/// Directory used for temp files/dirs next to `out`, so the final rename stays on one filesystem.
+fn output_parent(out: &Path) -> &Path {
+    match out.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+/// Directory for the temp files of an atomic write to `out`: `opts.temp_dir` if a file can be
+/// renamed from it to next to `out`, which proves both are on one filesystem, else
+/// [`output_parent`].
+fn temp_parent(out: &Path, opts: &MergeOptions) -> PathBuf {
+    let parent = output_parent(out);
+    let Some(dir) = &opts.temp_dir else {
+        return parent.to_path_buf();
+    };
+    let probe = || -> std::io::Result<()> {
+        let tmp = tempfile::Builder::new()
+            .prefix(".resource_merger-")
+            .tempfile_in(dir)?;
+        let moved = tempfile::Builder::new()
+            .prefix(".resource_merger-")
+            .make_in(parent, |p| std::fs::rename(tmp.path(), p))?;
+        drop(moved);
+        Ok(())
+    };
+    match probe() {
+        Ok(()) => dir.clone(),
+        Err(e) => {
+            eprintln!(
+                "warning: temp_dir {} can't be used for {} ({}); using the output directory",
+                dir.display(),
+                out.display(),
+                e
+            );
+            parent.to_path_buf()
+        }
+    }
+}
+
+/// Anonymous scratch file for a merged zip that is processed further before being written out,
+/// in `opts.temp_dir` if set. It is deleted once closed.
+fn scratch_file(opts: &MergeOptions) -> Result<File> {
+    Ok(match &opts.temp_dir {
+        Some(dir) => tempfile::tempfile_in(dir)?,
+        None => tempfile::tempfile()?,
+    })
+}
+
+/// Write the merged zip to `out` by letting `write` produce it directly into the file. With
+/// `opts.atomic` it goes to a temp file next to `out` which is renamed into place once complete;
+/// on error or cancellation the temp file is removed.
+fn write_output_file_with(
+    out: &Path,
+    opts: &MergeOptions,
+    write: impl FnOnce(&mut File) -> Result<()>,
+) -> Result<()> {
+    if !opts.atomic {
+        write(&mut File::create(out)?)?;
+    } else {
+        let mut tmp = tempfile::Builder::new()
+            .prefix(".resource_merger-")
+            .suffix(".tmp")
+            .tempfile_in(temp_parent(out, opts))?;
+        write(tmp.as_file_mut())?;
+        tmp.as_file().sync_all()?;
+        // Dropping `tmp` on the error path deletes the temp file
+        opts.check_cancelled()?;
+        tmp.persist(out).map_err(|e| MergeError::Io(e.error))?;
+    }
+    if opts.write_checksum_sidecar {
+        let name = out.file_name().unwrap_or_default().to_string_lossy();
+        let mut sidecar = out.as_os_str().to_owned();
+        sidecar.push(".sha256");
+        std::fs::write(sidecar, format!("{}  {}\n", sha256_file(out)?, name))?;
+    }
+    Ok(())
+}
+
+/// Hex SHA-256 of the file at `path`, read in chunks.
+fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Merge packs and extract the result into the directory `out_dir`. Above
+/// `opts.streaming_threshold_bytes` (and for a single zip input) the merged zip is streamed
+/// through a scratch file instead of being built in memory.
+pub fn merge_packs_to_dir<P: AsRef<Path>>(
+    packs: &[PackInput],
+    out_dir: P,
+    opts: &MergeOptions,
+) -> Result<()> {
+    if opts.dry_run {
+        // validate by scanning using existing in-memory method
+        let _ = merge_packs_to_bytes_with_options(packs, opts)?;
+        return Ok(());
+    }
+
+    // Stream the merged zip into an anonymous temp file and extract from there; a single zip
+    // input is copied through without decoding its entries
+    let pass_through = plan_single_zip_pass_through(packs, opts)?;
+    if pass_through.is_some() || use_streaming(packs, opts) {
+        let mut plan = match pass_through {
+            Some(plan) => plan,
+            None => plan_merge(None, packs, opts, true)?,
+        };
+        let mut tmp = write_merged_zip(&mut plan, &input_labels(packs), opts, scratch_file(opts)?)?;
+        tmp.seek(SeekFrom::Start(0))?;
+        return write_output_dir(out_dir.as_ref(), tmp, opts);
+    }
+
+    // Fallback: unzip the in-memory merged zip into out_dir.
+    let bytes = merge_packs_to_bytes_with_options(packs, opts)?;
+    write_output_dir(out_dir.as_ref(), Cursor::new(bytes), opts)
+}
+
+/// Merge `packs` and write only the delta against a previous merged zip into `out_dir`: every
+/// file that is new or whose contents changed, plus a `deleted.txt` listing (one per line, sorted)
+/// the paths that exist in `prev_zip` but not in the new output.
+///
+/// Files are compared by CRC-32 and size as recorded in the two archives. The directory is
+/// written like [`merge_packs_to_dir`] output, honoring `opts.atomic` and the other directory
+/// options. A merged pack with its own root `deleted.txt` can't be diffed, as the listing
+/// would overwrite it; that fails with [`MergeError::InvalidInput`].
+pub fn diff_to_dir<P: AsRef<Path>>(
+    prev_zip: &[u8],
+    packs: &[PackInput],
+    out_dir: P,
+    opts: &MergeOptions,
+) -> Result<()> {
+    let merged = merge_packs_to_bytes_with_options(packs, opts)?;
+    let mut next = ZipArchive::new(Cursor::new(merged))?;
+    if next.index_for_name("deleted.txt").is_some() {
+        return Err(MergeError::InvalidInput(
+            "the merged pack has its own deleted.txt, which diff_to_dir reserves for the list of deleted paths; rename or remove it (e.g. with `remove`)".to_string(),
+        ));
+    }
+    if opts.dry_run {
+        return Ok(());
+    }
+    let mut prev = ZipArchive::new(Cursor::new(prev_zip))?;
+    let mut prev_entries: HashMap<String, (u32, u64)> = HashMap::new();
+    for i in 0..prev.len() {
+        let f = prev.by_index_raw(i)?;
+        if !f.is_dir() {
+            prev_entries.insert(f.name().to_string(), (f.crc32(), f.size()));
+        }
+    }
+
+    let mut delta = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..next.len() {
+        opts.check_cancelled()?;
+        let f = next.by_index_raw(i)?;
+        let unchanged = prev_entries.remove(f.name()) == Some((f.crc32(), f.size()));
+        if !unchanged {
+            delta.raw_copy_file(f)?;
+        }
+    }
+    let mut deleted: Vec<String> = prev_entries.into_keys().collect();
+    deleted.sort();
+    let mut listing = deleted.join("\n");
+    if !listing.is_empty() {
+        listing.push('\n');
+    }
+    delta.start_file::<_, ()>("deleted.txt", zip::write::FileOptions::default())?;
+    delta.write_all(listing.as_bytes())?;
+    let delta = delta.finish()?;
+    write_output_dir(out_dir.as_ref(), Cursor::new(delta.into_inner()), opts)
+}
+
+/// Merge `packs` and update the existing directory `out_dir` in place to match, touching as
+/// little as possible: files whose contents changed are overwritten, new files are added and
+/// files an earlier sync wrote that the merge no longer produces are deleted (along with
+/// directories that become empty). Unchanged files are left alone, timestamps included.
+///
+/// The paths written are recorded in a `.resource_merger-sync` manifest in `out_dir`, and only
+/// paths listed there are ever deleted, so other files in the directory (e.g. a server's own
+/// config) are kept. Files left by a merge that didn't use `sync_to_dir` are not removed. A
+/// merged pack containing that manifest name fails with [`MergeError::InvalidInput`].
+///
+/// Unlike [`merge_packs_to_dir`] this is not atomic, so a live server keeps seeing the files it
+/// already loaded. `.gitattributes` and `SHA256SUMS` are kept and rewritten when
+/// `opts.write_gitattributes` or `opts.write_checksum_sidecar` ask for them.
+pub fn sync_to_dir<P: AsRef<Path>>(
+    packs: &[PackInput],
+    out_dir: P,
+    opts: &MergeOptions,
+) -> Result<()> {
+    let out = out_dir.as_ref();
+    let (files, span_warning) = merged_files(packs, opts)?;
+    if let Some(msg) = span_warning {
+        eprintln!("warning: {}", msg);
+    }
+    if files.contains_key(SYNC_MANIFEST) {
+        return Err(MergeError::InvalidInput(format!(
+            "the merged pack has its own {}, which sync_to_dir reserves for its list of written paths",
+            SYNC_MANIFEST
+        )));
+    }
+    if opts.dry_run {
+        return Ok(());
+    }
+    let dest = |key: &str| key.split('/').fold(out.to_path_buf(), |p, c| p.join(c));
+
+    for (key, bytes) in &files {
+        opts.check_cancelled()?;
+        let path = dest(key);
+        let unchanged = std::fs::metadata(&path).is_ok_and(|m| m.len() == bytes.len() as u64)
+            && std::fs::read(&path)? == *bytes;
+        if !unchanged {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, bytes)?;
+        }
+    }
+
+    // Only paths the previous sync wrote are candidates for deletion
+    let manifest = out.join(SYNC_MANIFEST);
+    let previous = match std::fs::read_to_string(&manifest) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let stale: Vec<PathBuf> = previous
+        .lines()
+        .filter_map(sanitize_zip_entry_name)
+        .filter(|key| !files.contains_key(key))
+        .map(|key| dest(&key))
+        .filter(|path| path.is_file())
+        .collect();
+    for path in stale {
+        std::fs::remove_file(&path)?;
+        // Drop directories the deletion emptied, up to (not including) `out`
+        let mut dir = path.parent();
+        while let Some(d) = dir.filter(|d| *d != out) {
+            if std::fs::read_dir(d)?.next().is_some() {
+                break;
+            }
+            std::fs::remove_dir(d)?;
+            dir = d.parent();
+        }
+    }
+    let listing: String = files.keys().map(|k| format!("{}\n", k)).collect();
+    std::fs::write(&manifest, listing)?;
+    tidy_output_dir(out, opts)
+}
+
+/// File in a [`sync_to_dir`] output listing the paths the last sync wrote.
+const SYNC_MANIFEST: &str = ".resource_merger-sync";
+
+/// Extract the merged zip into `out`. With `opts.atomic` the complete tree is first built in a
+/// temp directory next to `out` and then swapped in, so an error or cancellation never leaves a
+/// half-populated tree behind and `out` is always either fully old or fully new. The swap
+/// replaces the whole directory: files from an earlier run that aren't in the new output go away.
+fn write_output_dir<R: Read + Seek>(out: &Path, zip: R, opts: &MergeOptions) -> Result<()> {
+    if !opts.atomic {
+        std::fs::create_dir_all(out)?;
+        extract_zip_to_dir(zip, out, opts)?;
+        return tidy_output_dir(out, opts);
+    }
+    std::fs::create_dir_all(output_parent(out))?;
+    // Dropping `staging` on the error path removes the temp directory and its contents
+    let staging = tempfile::Builder::new()
+        .prefix(".resource_merger-")
+        .tempdir_in(temp_parent(out, opts))?;
+    extract_zip_to_dir(zip, staging.path(), opts)?;
+    tidy_output_dir(staging.path(), opts)?;
+    opts.check_cancelled()?;
+    swap_output_dir(staging.path(), out)
+}
+
+/// Move the finished `staging` tree to `out`. An existing `out` is first moved into a fresh
+/// temp directory beside it, so no other path is touched, and restored if the final rename
+/// fails; the backup is removed once `staging` is in place.
+fn swap_output_dir(staging: &Path, out: &Path) -> Result<()> {
+    if !out.exists() {
+        std::fs::rename(staging, out)?;
+        return Ok(());
+    }
+    let holder = tempfile::Builder::new()
+        .prefix(".resource_merger-bak")
+        .tempdir_in(output_parent(out))?;
+    let backup = holder.path().join("old");
+    std::fs::rename(out, &backup)?;
+    if let Err(e) = std::fs::rename(staging, out) {
+        std::fs::rename(&backup, out)?;
+        return Err(e.into());
+    }
+    holder.close()?;
+    Ok(())
+}
+
+/// File extensions marked as binary in a generated `.gitattributes`.
+const BINARY_EXTENSIONS: [&str; 10] = [
+    "png", "jpg", "jpeg", "gif", "ogg", "wav", "ttf", "otf", "nbt", "zip",
+];
+
+/// Apply the directory-output finishing options (`prune_empty_dirs`, `write_gitattributes`,
+/// `write_checksum_sidecar`).
+fn tidy_output_dir(out: &Path, opts: &MergeOptions) -> Result<()> {
+    if opts.prune_empty_dirs {
+        // Children come before their parents, so nested empty directories collapse fully
+        for entry in WalkDir::new(out)
+            .min_depth(1)
+            .contents_first(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if entry.file_type().is_dir() && std::fs::read_dir(entry.path())?.next().is_none() {
+                std::fs::remove_dir(entry.path())?;
+            }
+        }
+    }
+    if opts.write_gitattributes {
+        let mut attrs = String::new();
+        for ext in BINARY_EXTENSIONS {
+            attrs.push_str(&format!("*.{} binary\n", ext));
+        }
+        std::fs::write(out.join(".gitattributes"), attrs)?;
+    }
+    if opts.write_checksum_sidecar {
+        // Sorted `/`-separated relative paths, so `sha256sum -c SHA256SUMS` works from `out`
+        let mut sums = BTreeMap::new();
+        for entry in WalkDir::new(out).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let rel = entry.path().strip_prefix(out).unwrap_or(entry.path());
+            let rel = rel.to_string_lossy().replace('\\', "/");
+            if rel != "SHA256SUMS" && rel != SYNC_MANIFEST {
+                sums.insert(rel, sha256_file(entry.path())?);
+            }
+        }
+        let listing: String = sums
+            .iter()
+            .map(|(path, hash)| format!("{}  {}\n", hash, path))
+            .collect();
+        std::fs::write(out.join("SHA256SUMS"), listing)?;
+    }
+    Ok(())
+}
+
+fn extract_zip_to_dir<R: Read + Seek>(zip: R, out_path: &Path, opts: &MergeOptions) -> Result<()> {
+    let mut archive = ZipArchive::new(zip)?;
+    // Write entries in sorted name order so the resulting tree is built deterministically
+    let mut order: Vec<(String, usize)> = archive
+        .file_names()
+        .map(|n| n.to_string())
+        .zip(0..)
+        .collect();
+    order.sort();
+    for (_, i) in order {
+        opts.check_cancelled()?;
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let raw_name = file.name().to_string();
+        let name = match sanitize_zip_entry_name(&raw_name) {
+            Some(n) => n,
+            None => continue,
+        };
+        // Build a destination path from the sanitized components to ensure correct
+        // OS-specific separators and avoid zip-slip.
+        let dest = {
+            let mut p = out_path.to_path_buf();
+            for comp in name.split('/') {
+                p.push(comp);
+            }
+            p
+        };
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut outfile = std::fs::File::create(dest)?;
+        std::io::copy(&mut file, &mut outfile)?;
+    }
+    Ok(())
+}
+
+/// Given a directory which contains multiple resourcepack folders or zip files, merge them all in
+/// lexical order. Useful when users supply a single "resourcepacks" folder.
+pub fn merge_all_packs_in_folder(folder: &Path) -> Result<Vec<u8>> {
+    merge_packs_in_folder(folder, false)
+}
+
+/// Like [`merge_all_packs_in_folder`], but with `recursive` set nested folders are searched too.
+///
+/// In recursive mode a directory containing a `pack.mcmeta` is treated as a pack, zip files are
+/// treated as packs, and any other directory is descended into. Packs are ordered by a
+/// depth-first lexical walk.
+pub fn merge_packs_in_folder(folder: &Path, recursive: bool) -> Result<Vec<u8>> {
+    if !folder.is_dir() {
+        return Err(MergeError::InvalidInput(format!(
+            "{} is not a dir",
+            folder.display()
+        )));
+    }
+    let mut packs: Vec<PackInput> = Vec::new();
+    collect_packs_in_folder(folder, recursive, &mut packs)?;
+    merge_packs_to_bytes(&packs)
+}
+
+fn collect_packs_in_folder(
+    folder: &Path,
+    recursive: bool,
+    packs: &mut Vec<PackInput>,
+) -> Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(folder)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for p in entries {
+        if recursive && p.is_dir() && !p.join("pack.mcmeta").is_file() {
+            collect_packs_in_folder(&p, recursive, packs)?;
+        } else if !recursive || p.is_dir() || is_zip_path(&p) {
+            packs.push(p.into());
+        }
+    }
+    Ok(())
+}
+
+fn is_zip_path(p: &Path) -> bool {
+    p.extension()
+        .map(|e| e.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false)
+}
+
+/// Formats detected in a single input's pack.mcmeta, as reported by [`scan_formats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFormat {
+    /// Human-readable description of the input (path, URL, or `<in-memory>`)
+    pub input: String,
+    /// pack_format declared by the input, if a readable pack.mcmeta was found
+    pub pack_format: Option<u32>,
+    /// max_format (or supported_formats max_inclusive) declared by the input
+    pub max_format: Option<u32>,
+    /// Number of overlay entries declared in the input's pack.mcmeta
+    pub overlay_count: usize,
+}
+
+/// Inspect each input's pack.mcmeta and report its declared formats without merging.
+///
+/// Only pack.mcmeta is read from each input (URLs are still downloaded). Inputs without a
+/// readable pack.mcmeta are reported with `None` formats rather than failing the scan. Reads
+/// aren't time-bounded.
+pub fn scan_formats(packs: &[PackInput]) -> Vec<InputFormat> {
+    scan_formats_with_options(packs, &MergeOptions::default())
+}
+
+/// Like [`scan_formats`], opening encrypted zip inputs with `opts.zip_passwords` and bounding
+/// URL downloads by `opts.per_input_timeout` (directory and zip file reads aren't bounded).
+pub fn scan_formats_with_options(packs: &[PackInput], opts: &MergeOptions) -> Vec<InputFormat> {
+    packs
+        .iter()
+        .enumerate()
+        .map(|(idx, pack)| {
+            let input = match pack {
+                PackInput::Dir(p) | PackInput::ZipFile(p) => p.display().to_string(),
+                PackInput::ZipBytes(_) => "<in-memory>".to_string(),
+                PackInput::Url(u) => u.clone(),
+                PackInput::TarBytes(_) => "<in-memory tar>".to_string(),
+            };
+            let peeked = read_root_mcmeta(idx, pack, opts)
+                .ok()
+                .flatten()
+                .and_then(|b| parse_peeked_mcmeta(&b));
+            match peeked {
+                Some((pf, mf, overlays)) => InputFormat {
+                    input,
+                    pack_format: Some(pf),
+                    max_format: mf,
+                    overlay_count: overlays
+                        .as_ref()
+                        .and_then(|o| o.get("entries"))
+                        .and_then(|e| e.as_array())
+                        .map(|a| a.len())
+                        .unwrap_or(0),
+                },
+                None => InputFormat {
+                    input,
+                    pack_format: None,
+                    max_format: None,
+                    overlay_count: 0,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Compute the pack.mcmeta a merge of `packs` would produce, without reading or writing any
+/// other files.
+///
+/// Only each input's root pack.mcmeta is read (URLs are still downloaded), opened as a merge
+/// with `opts` opens it; formats declared by nested pack.mcmeta files are not considered.
+/// Unreadable inputs are errors, except URL failures `url_failure_policy` skips. The result is
+/// the same JSON a full merge would synthesize, including merged overlays.
+/// `opts.per_input_timeout` bounds only URL downloads.
+pub fn compute_merged_mcmeta(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<serde_json::Value> {
+    let mut found_formats = Vec::new();
+    let mut found_max_formats = Vec::new();
+    let mut overlays_values = Vec::new();
+    let mut language = None;
+    for (idx, pack) in packs.iter().enumerate() {
+        opts.check_cancelled()?;
+        let mcmeta = match (read_root_mcmeta(idx, pack, opts), pack) {
+            (Ok(mcmeta), _) => mcmeta,
+            (Err(e), PackInput::Url(u)) if opts.skips_url_failure(&e) => {
+                eprintln!("warning: skipping input {}: {}", u, e);
+                None
+            }
+            (Err(e), _) => return Err(e),
+        };
+        let Some(mcmeta) = mcmeta else {
+            continue;
+        };
+        check_root_mcmeta_encoding(idx, &mcmeta)?;
+        if let Some((pf, mf, overlays)) = parse_peeked_mcmeta(&mcmeta) {
+            if opts
+                .drop_inputs_below_format
+                .is_some_and(|floor| pf < floor)
+            {
+                continue;
+            }
+            found_formats.push(pf);
+            found_max_formats.extend(mf);
+            overlays_values.extend(overlays);
+            if opts.merge_language_block {
+                merge_language_block(&mut language, &mcmeta);
+            }
+        }
+    }
+
+    if let (true, Some(msg)) = (
+        opts.strict_overlays,
+        overlay_range_conflict(&overlays_values),
+    ) {
+        return Err(MergeError::InvalidInput(msg));
+    }
+    // Flattening resolves overlays into the base files, so the merged pack.mcmeta has none
+    let overlays = match opts.flatten_overlays_to {
+        Some(_) => None,
+        None => merge_overlays(&overlays_values),
+    };
+    let mut mcmeta =
+        synthesize_pack_mcmeta(&found_formats, &found_max_formats, overlays.as_ref(), opts)
+            .into_bytes();
+    if let Some(language) = &language {
+        mcmeta = with_language_block(&mcmeta, language, opts.pretty_mcmeta)?;
+    }
+    serde_json::from_slice(&mcmeta)
+        .map_err(|e| MergeError::InvalidInput(format!("synthesized pack.mcmeta: {}", e)))
+}
+
+/// A file whose overlay copies are byte-identical, as reported by
+/// [`find_identical_overlay_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdenticalOverlayFile {
+    /// Path relative to the overlay directories, e.g. `assets/minecraft/textures/block/a.png`
+    pub path: String,
+    /// Overlay directories holding the same bytes at `path`, sorted
+    pub directories: Vec<String>,
+    /// Whether the base pack holds the same bytes at `path` too, making these copies redundant
+    pub matches_base: bool,
+}
+
+/// Report files that the merged output stores with identical contents in several overlay
+/// directories, or in an overlay directory and the base.
+///
+/// Overlay directories are the ones listed in the merged pack.mcmeta `overlays` entries. This
+/// only reports; `MergeOptions::coalesce_identical_overlay_files` drops the copies that are safe
+/// to drop.
+pub fn find_identical_overlay_files(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<Vec<IdenticalOverlayFile>> {
+    let opts = MergeOptions {
+        coalesce_identical_overlay_files: false,
+        ..opts.clone()
+    };
+    let mut plan = plan_merge(None, packs, &opts, true)?;
+    identical_overlay_files_in(&mut plan)
+}
+
+/// The report of [`find_identical_overlay_files`] for an already planned merge.
+fn identical_overlay_files_in(plan: &mut MergePlan) -> Result<Vec<IdenticalOverlayFile>> {
+    let mut report = Vec::new();
+    for (path, copies) in overlay_copies(plan)? {
+        let base = match plan.files.get(&path) {
+            Some(src) => Some(read_source(src, &mut plan.zips)?),
+            None => None,
+        };
+        let mut groups: BTreeMap<Vec<u8>, Vec<String>> = BTreeMap::new();
+        for (dir, bytes) in copies {
+            groups.entry(bytes).or_default().push(dir);
+        }
+        for (bytes, directories) in groups {
+            let matches_base = base.as_ref() == Some(&bytes);
+            if directories.len() > 1 || matches_base {
+                report.push(IdenticalOverlayFile {
+                    path: path.clone(),
+                    directories,
+                    matches_base,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Path relative to an overlay directory -> (directory, contents) of each copy.
+type OverlayCopies = BTreeMap<String, Vec<(String, Vec<u8>)>>;
+
+/// The plan's overlay directories, as listed in its merged `overlays` entries, sorted.
+fn overlay_dirs(plan: &MergePlan) -> Vec<String> {
+    let mut dirs: Vec<String> = plan
+        .overlays
+        .as_ref()
+        .and_then(|ov| ov.get("entries"))
+        .and_then(|e| e.as_array())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|e| e.get("directory").and_then(|d| d.as_str()))
+                .map(|d| d.trim_matches('/').to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Every file stored under one of the plan's overlay directories, with its copies in directory
+/// order.
+fn overlay_copies(plan: &mut MergePlan) -> Result<OverlayCopies> {
+    let dirs = overlay_dirs(plan);
+    let mut keys: Vec<String> = plan.files.keys().cloned().collect();
+    keys.sort();
+    let mut copies = OverlayCopies::new();
+    for dir in &dirs {
+        let prefix = format!("{}/", dir);
+        for key in &keys {
+            if let Some(rel) = key.strip_prefix(&prefix) {
+                let bytes = read_source(&plan.files[key], &mut plan.zips)?;
+                copies
+                    .entry(rel.to_string())
+                    .or_default()
+                    .push((dir.clone(), bytes));
+            }
+        }
+    }
+    Ok(copies)
+}
+
+/// Why a path was flagged by [`lint_paths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathLintReason {
+    /// Contains uppercase letters; the game only loads lowercase resource locations
+    Uppercase,
+    /// Contains spaces or other whitespace
+    Whitespace,
+    /// Contains a character that is illegal in Windows file names
+    IllegalCharacter(char),
+}
+
+/// A merged path that may cause problems on some platforms, as reported by [`lint_paths`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathLint {
+    pub path: String,
+    pub reason: PathLintReason,
+}
+
+/// Characters that can't appear in Windows file names.
+const WINDOWS_ILLEGAL_CHARS: [char; 7] = [':', '*', '?', '"', '<', '>', '|'];
+
+/// One file of the merged output, as listed by [`preview_merge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedEntry {
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u64,
+}
+
+/// What a merge would produce, without the zip bytes. See [`preview_merge`].
+#[derive(Debug, Clone)]
+pub struct MergePreview {
+    /// The pack.mcmeta the output would contain
+    pub mcmeta: serde_json::Value,
+    /// Every output file, including pack.mcmeta, pack.png and README.md, sorted by path
+    pub entries: Vec<MergedEntry>,
+    /// Things worth a look before writing: overridden paths, pack.png problems and a
+    /// `warn_on_wide_format_span` violation
+    pub warnings: Vec<String>,
+}
+
+/// Plan a merge and report its pack.mcmeta and file listing without writing a zip.
+///
+/// Entries of directory and zip file inputs are listed rather than read, except for the root
+/// files the output is built from.
+pub fn preview_merge(packs: &[PackInput], opts: &MergeOptions) -> Result<MergePreview> {
+    let mut plan = plan_merge(None, packs, opts, true)?;
+    let labels = input_labels(packs);
+    let roots = build_root_files(&mut plan, &labels, opts)?;
+    let mcmeta = serde_json::from_slice(&roots["pack.mcmeta"])
+        .map_err(|e| MergeError::InvalidInput(format!("pack.mcmeta: {}", e)))?;
+
+    let mut warnings: Vec<String> = plan
+        .overridden
+        .iter()
+        .filter(|(k, _)| plan.files.contains_key(*k))
+        .filter(|(k, _)| !SYNTHESIZED_ROOT_FILES.contains(&k.as_str()))
+        .map(|(k, idx)| {
+            format!(
+                "{} is provided by more than one input; {} wins",
+                k,
+                winner_label(&labels, *idx)
+            )
+        })
+        .collect();
+    if plan.files.contains_key("pack.png") && opts.validate_pack_png {
+        if let Err(e) = check_pack_png_dimensions(&roots["pack.png"]) {
+            warnings.push(format!("pack.png: {}", e));
+        }
+    }
+    warnings.extend(format_span_warning(&plan.found_formats, opts));
+
+    let mut entries: Vec<MergedEntry> = roots
+        .iter()
+        .map(|(path, bytes)| MergedEntry {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+        })
+        .collect();
+    for (path, src) in &plan.files {
+        if SYNTHESIZED_ROOT_FILES.contains(&path.as_str()) {
+            continue;
+        }
+        let size = match src {
+            EntrySource::Memory(b) => b.len() as u64,
+            EntrySource::File(p) => std::fs::metadata(p)?.len(),
+            EntrySource::ZipEntry { archive, index } => {
+                plan.zips[*archive].0.by_index_raw(*index)?.size()
+            }
+        };
+        entries.push(MergedEntry {
+            path: path.clone(),
+            size,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(MergePreview {
+        mcmeta,
+        entries,
+        warnings,
+    })
+}
+
+/// Paths that more than one input provides, sorted, as they would be resolved by a merge with
+/// `opts`. The generated pack.mcmeta, pack.png and README.md and paths matching
+/// `opts.allowed_conflicts` are not reported.
+///
+/// Like [`lint_paths`], this is read-only and doesn't fail under
+/// [`OverwritePolicy::ErrorIfConflict`].
+pub fn find_conflicts(packs: &[PackInput], opts: &MergeOptions) -> Result<Vec<String>> {
+    let opts = MergeOptions {
+        overwrite: OverwritePolicy::LastWins,
+        overwrite_rules: Vec::new(),
+        ..opts.clone()
+    };
+    let plan = plan_merge(None, packs, &opts, true)?;
+    Ok(conflicts_in(&plan, &opts))
+}
+
+/// The report of [`find_conflicts`] for an already planned merge.
+fn conflicts_in(plan: &MergePlan, opts: &MergeOptions) -> Vec<String> {
+    plan.overridden
+        .keys()
+        .filter(|k| plan.files.contains_key(*k))
+        .filter(|k| !SYNTHESIZED_ROOT_FILES.contains(&k.as_str()))
+        .filter(|k| !opts.allowed_conflicts.iter().any(|p| glob_match(p, k)))
+        .cloned()
+        .collect()
+}
+
+/// Report merged paths that would be problematic across platforms: uppercase letters,
+/// whitespace, or characters illegal on Windows. A path is reported once per reason.
+///
+/// This is read-only: entries of directory and zip file inputs are listed but not read, and
+/// nothing is written. The paths checked are the final merged ones, after `opts` filters apply.
+pub fn lint_paths(packs: &[PackInput], opts: &MergeOptions) -> Result<Vec<PathLint>> {
+    let plan = plan_merge(None, packs, opts, true)?;
+    Ok(lints_in(&plan))
+}
+
+/// The report of [`lint_paths`] for an already planned merge.
+fn lints_in(plan: &MergePlan) -> Vec<PathLint> {
+    let mut paths: Vec<&String> = plan.files.keys().collect();
+    paths.sort();
+    let mut lints = Vec::new();
+    for path in paths {
+        let mut reasons = Vec::new();
+        if path.chars().any(|c| c.is_uppercase()) {
+            reasons.push(PathLintReason::Uppercase);
+        }
+        if path.chars().any(|c| c.is_whitespace()) {
+            reasons.push(PathLintReason::Whitespace);
+        }
+        if let Some(c) = path.chars().find(|c| WINDOWS_ILLEGAL_CHARS.contains(c)) {
+            reasons.push(PathLintReason::IllegalCharacter(c));
+        }
+        lints.extend(reasons.into_iter().map(|reason| PathLint {
+            path: path.clone(),
+            reason,
+        }));
+    }
+    lints
+}
+
+/// A texture a merged model refers to but the merged pack doesn't contain, as reported by
+/// [`find_missing_textures`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MissingTexture {
+    /// The model, e.g. `assets/mymod/models/item/wand.json`
+    pub model: String,
+    /// The file it needs, e.g. `assets/mymod/textures/item/wand.png`
+    pub texture: String,
+}
+
+/// Report textures referenced by the merged models (`assets/<ns>/models/**.json`) that no input
+/// provides.
+///
+/// References into the `minecraft` namespace are assumed to be vanilla textures and aren't
+/// checked, nor are `#variable` references. Models that aren't valid JSON are skipped.
+pub fn find_missing_textures(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<Vec<MissingTexture>> {
+    let mut plan = plan_merge(None, packs, opts, true)?;
+    missing_textures_in(&mut plan)
+}
+
+/// The report of [`find_missing_textures`] for an already planned merge.
+fn missing_textures_in(plan: &mut MergePlan) -> Result<Vec<MissingTexture>> {
+    let mut models: Vec<String> = plan
+        .files
+        .keys()
+        .filter(|k| k.ends_with(".json"))
+        .filter(|k| {
+            k.strip_prefix("assets/")
+                .and_then(|rest| rest.split_once('/'))
+                .is_some_and(|(_, rest)| rest.starts_with("models/"))
+        })
+        .cloned()
+        .collect();
+    models.sort();
+    let mut missing = BTreeSet::new();
+    for model in models {
+        let bytes = read_source(&plan.files[&model], &mut plan.zips)?;
+        let Some(json) = decode_json_text(&bytes)
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok())
+        else {
+            continue;
+        };
+        let Some(textures) = json.get("textures").and_then(|t| t.as_object()) else {
+            continue;
+        };
+        for location in textures.values().filter_map(|v| v.as_str()) {
+            if location.starts_with('#') {
+                continue;
+            }
+            let (ns, path) = location.split_once(':').unwrap_or(("minecraft", location));
+            if ns == "minecraft" {
+                continue;
+            }
+            let texture = format!("assets/{}/textures/{}.png", ns, path);
+            if !plan.files.contains_key(&texture) {
+                missing.insert(MissingTexture {
+                    model: model.clone(),
+                    texture,
+                });
+            }
+        }
+    }
+    Ok(missing.into_iter().collect())
+}
+
+/// Files stored with the same bytes at several paths, as reported by
+/// [`find_duplicate_contents`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateContent {
+    /// Paths holding the same bytes, sorted
+    pub paths: Vec<String>,
+    /// Size of one copy in bytes
+    pub size: u64,
+}
+
+/// Report merged files whose contents are byte-identical to a file at a different path, e.g. a
+/// texture copied under two names. Empty files are ignored, and copies of one path in overlay
+/// directories are left to [`find_identical_overlay_files`].
+pub fn find_duplicate_contents(
+    packs: &[PackInput],
+    opts: &MergeOptions,
+) -> Result<Vec<DuplicateContent>> {
+    let mut plan = plan_merge(None, packs, opts, true)?;
+    duplicate_contents_in(&mut plan)
+}
+
+/// The report of [`find_duplicate_contents`] for an already planned merge.
+fn duplicate_contents_in(plan: &mut MergePlan) -> Result<Vec<DuplicateContent>> {
+    use sha2::{Digest, Sha256};
+
+    let mut keys: Vec<String> = plan
+        .files
+        .keys()
+        .filter(|k| !SYNTHESIZED_ROOT_FILES.contains(&k.as_str()))
+        .cloned()
+        .collect();
+    keys.sort();
+    let mut groups: HashMap<Vec<u8>, (u64, Vec<String>)> = HashMap::new();
+    for key in keys {
+        let bytes = read_source(&plan.files[&key], &mut plan.zips)?;
+        if bytes.is_empty() {
+            continue;
+        }
+        let group = groups
+            .entry(Sha256::digest(&bytes).to_vec())
+            .or_insert_with(|| (bytes.len() as u64, Vec::new()));
+        group.1.push(key);
+    }
+    let prefixes: Vec<String> = overlay_dirs(plan)
+        .into_iter()
+        .map(|d| format!("{}/", d))
+        .collect();
+    let mut report: Vec<DuplicateContent> = groups
+        .into_values()
+        .filter(|(_, paths)| {
+            let distinct: BTreeSet<&str> = paths
+                .iter()
+                .map(|p| {
+                    prefixes
+                        .iter()
+                        .find_map(|pre| p.strip_prefix(pre.as_str()))
+                        .unwrap_or(p)
+                })
+                .collect();
+            distinct.len() > 1
+        })
+        .map(|(size, paths)| DuplicateContent { paths, size })
+        .collect();
+    report.sort_by(|a, b| a.paths.cmp(&b.paths));
+    Ok(report)
+}
+
+/// Everything the read-only checks find in a merge, as returned by [`analyze`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// Paths more than one input provides, as from [`find_conflicts`]
+    pub conflicts: Vec<String>,
+    /// The `warn_on_wide_format_span` message, if the inputs' pack_format values exceed it
+    pub format_span: Option<String>,
+    /// Cross-platform path problems, as from [`lint_paths`]
+    pub path_lints: Vec<PathLint>,
+    /// Textures referenced by models but not provided, as from [`find_missing_textures`]
+    pub missing_textures: Vec<MissingTexture>,
+    /// Files with the same bytes at different paths, as from [`find_duplicate_contents`]
+    pub duplicate_contents: Vec<DuplicateContent>,
+    /// Duplicate overlay contents, as from [`find_identical_overlay_files`]
+    pub identical_overlay_files: Vec<IdenticalOverlayFile>,
+}
+
+impl Analysis {
+    /// Whether conflicts, a too-wide format span, paths with illegal characters or missing
+    /// textures were found, e.g. to choose a linter's exit code. Uppercase and whitespace path
+    /// lints, duplicate contents and identical overlay files are only warnings.
+    pub fn has_errors(&self) -> bool {
+        !self.conflicts.is_empty()
+            || self.format_span.is_some()
+            || self
+                .path_lints
+                .iter()
+                .any(|l| matches!(l.reason, PathLintReason::IllegalCharacter(_)))
+            || !self.missing_textures.is_empty()
+    }
+}
+
+/// Run every read-only check on a merge in one pass and write nothing.
+///
+/// Inputs are planned once, resolving files last-wins like [`find_conflicts`] does, so neither
+/// [`OverwritePolicy::ErrorIfConflict`] nor `strict_format_span` fails the analysis.
+pub fn analyze(packs: &[PackInput], opts: &MergeOptions) -> Result<Analysis> {
+    let opts = MergeOptions {
+        overwrite: OverwritePolicy::LastWins,
+        overwrite_rules: Vec::new(),
+        coalesce_identical_overlay_files: false,
+        ..opts.clone()
+    };
+    let mut plan = plan_merge(None, packs, &opts, true)?;
+    Ok(Analysis {
+        conflicts: conflicts_in(&plan, &opts),
+        format_span: format_span_warning(&plan.found_formats, &opts),
+        path_lints: lints_in(&plan),
+        missing_textures: missing_textures_in(&mut plan)?,
+        duplicate_contents: duplicate_contents_in(&mut plan)?,
+        identical_overlay_files: identical_overlay_files_in(&mut plan)?,
+    })
+}
+
+/// What kind of pack an input looks like, as reported by [`classify_input`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackClassification {
+    /// pack.mcmeta and a top-level `assets/` folder
+    ResourcePack,
+    /// pack.mcmeta and a top-level `data/` folder, without `assets/`
+    DataPack,
+    /// Some pack markers but not a clear resource or data pack (e.g. pack.mcmeta without any
+    /// content folder, or `assets/` without pack.mcmeta)
+    Unknown,
+    /// Neither pack.mcmeta nor `assets/`/`data/` at the top level
+    NotAPack,
+}
+
+/// Classify an input by the presence of pack.mcmeta and top-level `assets/`/`data/` folders.
+///
+/// Only entry names are inspected (URLs are still downloaded), so encrypted zips can be
+/// classified without a password. Unreadable inputs return an error. Reads aren't time-bounded.
+pub fn classify_input(input: &PackInput) -> Result<PackClassification> {
+    let names: Vec<String> = match input {
+        PackInput::Dir(p) => {
+            if !p.is_dir() {
+                return Err(MergeError::InvalidInput(format!(
+                    "not a directory: {}",
+                    p.display()
+                )));
+            }
+            std::fs::read_dir(p)?
+                .map(|e| {
+                    let e = e?;
+                    let mut name = e.file_name().to_string_lossy().into_owned();
+                    if e.file_type()?.is_dir() {
+                        name.push('/');
+                    }
+                    Ok(name)
+                })
+                .collect::<std::io::Result<_>>()?
+        }
+        PackInput::ZipFile(p) if custom_reader_for(p).is_some() => {
+            let mut mem = HashMap::new();
+            read_custom_into_map(p, &mut mem)?;
+            mem.into_keys().collect()
+        }
+        PackInput::ZipFile(p) => ZipArchive::new(File::open(p)?)?
+            .file_names()
+            .map(|s| s.to_string())
+            .collect(),
+        PackInput::ZipBytes(b) => ZipArchive::new(Cursor::new(b))?
+            .file_names()
+            .map(|s| s.to_string())
+            .collect(),
+        PackInput::Url(u) => ZipArchive::new(Cursor::new(fetch_url_bytes(u)?))?
+            .file_names()
+            .map(|s| s.to_string())
+            .collect(),
+        PackInput::TarBytes(b) => {
+            let mut archive = tar::Archive::new(Cursor::new(b));
+            let mut names = Vec::new();
+            for entry in archive.entries()? {
+                names.push(entry?.path()?.to_string_lossy().into_owned());
+            }
+            names
+        }
+    };
+
+    let names: Vec<String> = names
+        .iter()
+        .filter_map(|n| sanitize_zip_entry_name(n))
+        .collect();
+    Ok(classify_names(names.iter().map(|n| n.as_str())))
+}
+
+/// Classify a pack from its normalized entry names (see [`classify_input`]).
+fn classify_names<'a>(names: impl IntoIterator<Item = &'a str>) -> PackClassification {
+    let (mut has_mcmeta, mut has_assets, mut has_data) = (false, false, false);
+    for n in names {
+        has_mcmeta |= n == "pack.mcmeta";
+        has_assets |= n == "assets" || n.starts_with("assets/");
+        has_data |= n == "data" || n.starts_with("data/");
+    }
+    match (has_mcmeta, has_assets, has_data) {
+        (true, true, _) => PackClassification::ResourcePack,
+        (true, false, true) => PackClassification::DataPack,
+        (false, false, false) => PackClassification::NotAPack,
+        _ => PackClassification::Unknown,
+    }
+}
+
+/// Settings that represent the full runtime configuration for a merge run.
+/// This mirrors the CLI args/config file and is the single object used to execute a merge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Ordered list of inputs (directories, zip files, or URLs). These are applied in order.
+    pub inputs: Vec<PackInput>,
+    /// Output path (file or directory) - resolved by the caller
+    pub out: PathBuf,
+    /// If true, write to a directory instead of a zip file
+    #[serde(default)]
+    pub dir: bool,
+    /// With `dir`, also write the merged pack as a zip at this path in the same run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub also_emit_zip: Option<PathBuf>,
+    /// Merge behavior options
+    #[serde(default)]
+    pub options: MergeOptions,
+}
+
+/// Execute a merge according to `Settings`.
+/// This is the single entrypoint consumers (like the CLI) should call.
+pub fn run_with_settings(settings: &Settings) -> Result<()> {
+    let opts = &settings.options;
+    if let (true, Some(zip), false) = (settings.dir, &settings.also_emit_zip, opts.dry_run) {
+        // Merge once into the zip, then extract the directory from it; a split zip can't be
+        // read back, so that case merges a second time
+        merge_packs_to_file_with_options(&settings.inputs, zip, opts)?;
+        if opts.split_output_bytes.is_some() {
+            return merge_packs_to_dir(&settings.inputs, &settings.out, opts);
+        }
+        write_output_dir(&settings.out, File::open(zip)?, opts)
+    } else if settings.dir {
+        merge_packs_to_dir(&settings.inputs, &settings.out, &settings.options)
+    } else {
+        merge_packs_to_file_with_options(&settings.inputs, &settings.out, &settings.options)
+    }
+}
+
+/// One entry of [`Config::inputs`]: either a plain path/URL string or an object with per-input
+/// settings, e.g. `{ "path": "branding.zip", "force_top": true }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConfigInput {
+    Path(String),
+    Detailed {
+        path: String,
+        /// Apply this input after every other input, regardless of its position in the list
+        #[serde(default)]
+        force_top: bool,
+        /// Only include this input when the run's `target_format` lies within `[min, max]`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        for_formats: Option<[u32; 2]>,
+        /// Use this subdirectory of a directory input as the pack root, e.g. `packs/mypack`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subdir: Option<String>,
+        /// Password of this input if it is an encrypted zip
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+}
+
+impl ConfigInput {
+    /// The input's path or URL.
+    pub fn path(&self) -> &str {
+        match self {
+            ConfigInput::Path(p) | ConfigInput::Detailed { path: p, .. } => p,
+        }
+    }
+
+    fn path_mut(&mut self) -> &mut String {
+        match self {
+            ConfigInput::Path(p) | ConfigInput::Detailed { path: p, .. } => p,
+        }
+    }
+
+    /// The [`PackInput`] to read: the path itself, or its `subdir` as a directory input.
+    ///
+    /// Fails with [`MergeError::InvalidInput`] if `subdir` is absolute, contains `..` or is set
+    /// on an input that isn't a directory.
+    pub fn pack_input(&self) -> Result<PackInput> {
+        let ConfigInput::Detailed {
+            path,
+            subdir: Some(sub),
+            ..
+        } = self
+        else {
+            return Ok(PackInput::from(self.path().to_string()));
+        };
+        let escapes = Path::new(sub)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+        if escapes {
+            return Err(MergeError::InvalidInput(format!(
+                "input {}: subdir {:?} must be a relative path inside the input",
+                path, sub
+            )));
+        }
+        if !Path::new(path).is_dir() {
+            return Err(MergeError::InvalidInput(format!(
+                "input {}: subdir is only supported on directory inputs",
+                path
+            )));
+        }
+        Ok(PackInput::Dir(Path::new(path).join(sub)))
+    }
+
+    /// The zip password set on this input, if any.
+    pub fn password(&self) -> Option<&str> {
+        match self {
+            ConfigInput::Detailed {
+                password: Some(pw), ..
+            } => Some(pw),
+            _ => None,
+        }
+    }
+
+    /// Whether the input is marked to always be applied last.
+    pub fn force_top(&self) -> bool {
+        matches!(
+            self,
+            ConfigInput::Detailed {
+                force_top: true,
+                ..
+            }
+        )
+    }
+
+    /// Whether the input should be used for `target_format`. Inputs without a `for_formats`
+    /// range, and every input when no target is set, always apply.
+    pub fn applies_to(&self, target_format: Option<u32>) -> bool {
+        match (self, target_format) {
+            (
+                ConfigInput::Detailed {
+                    for_formats: Some([min, max]),
+                    ..
+                },
+                Some(target),
+            ) => (*min..=*max).contains(&target),
+            _ => true,
+        }
+    }
+}
+
+impl From<&str> for ConfigInput {
+    fn from(s: &str) -> Self {
+        ConfigInput::Path(s.to_string())
+    }
+}
+
+/// Configuration structure for JSON config files.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    /// Ordered list of inputs (directories, zip files, or URLs). These are applied first.
+    pub inputs: Option<Vec<ConfigInput>>,
+    /// Overwrite policy: last, first, error, skip
+    pub overwrite: Option<String>,
+    /// Dry run
+    pub dry_run: Option<bool>,
+    /// Buffer size
+    pub buffer_size: Option<usize>,
+    /// Atomic write
+    pub atomic: Option<bool>,
+    /// Preserve timestamps
+    pub preserve_timestamps: Option<bool>,
+    /// Force pack_format
+    pub pack_format: Option<u32>,
+    /// Supported formats policy: one-to-highest, lowest-to-highest, one-to-latest
+    pub supported_formats: Option<String>,
+    /// Optional output path if you want the config to specify a default output file
+    pub out: Option<String>,
+    /// If true, write output as a directory instead of a zip file
+    pub dir: Option<bool>,
+    /// With `dir`, also write the merged pack as a zip at this path
+    pub also_emit_zip: Option<String>,
+    /// Optional description to use for generated pack.mcmeta
+    pub description: Option<String>,
+    /// If true, continue when input URLs fail to download or aren't valid zips
+    pub tolerate_missing_inputs: Option<bool>,
+    /// Which URL download failures skip the input: skip-all, skip-transient or error-all
+    pub url_failure_policy: Option<String>,
+    /// Keep only these namespaces under assets/ and data/
+    pub namespaces: Option<Vec<String>>,
+    /// Drop these namespaces under assets/ and data/
+    pub deny_namespaces: Option<Vec<String>>,
+    /// If true, union datapack tag `values` across inputs instead of overwriting
+    pub merge_tags: Option<bool>,
+    /// If true, concatenate font `providers` across inputs instead of overwriting
+    pub merge_fonts: Option<bool>,
+    /// If true, merge tags, fonts, langs, sounds.json and atlases across inputs
+    pub smart_json_merge: Option<bool>,
+    /// Target pack format; inputs whose `for_formats` range excludes it are skipped
+    pub target_format: Option<u32>,
+    /// Globs of paths that several inputs may provide without counting as a conflict
+    pub allowed_conflicts: Option<Vec<String>>,
+    /// If true, the CLI exits with code 3 when any other path is provided by more than one input
+    pub fail_on_conflict: Option<bool>,
+    /// If true, URL inputs ending in `/` are read as directory listings of zips
+    pub expand_url_listings: Option<bool>,
+    /// Default output compression: "stored", "fast" or "best"
+    pub compression: Option<String>,
+    /// Extra files to add to the output, as "SRC:DEST" strings
+    pub add_files: Option<Vec<String>>,
+    /// Shell command the CLI runs after a successful write, with `{out}` replaced by the output
+    /// path quoted as one shell word; the path is also in the `RESOURCE_MERGER_OUT` environment
+    /// variable. It runs with the user's privileges, so only load configs you trust.
+    pub post_merge_command: Option<String>,
+}
+
+impl Config {
+    /// Combine two configs, with `other` layered on top of `self`.
+    ///
+    /// Inputs are concatenated in order (`self` first); every other field is taken from `other`
+    /// when it is set there, otherwise kept from `self`.
+    pub fn merge(self, other: Config) -> Config {
+        let inputs = match (self.inputs, other.inputs) {
+            (Some(mut a), Some(b)) => {
+                a.extend(b);
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        Config {
+            inputs,
+            overwrite: other.overwrite.or(self.overwrite),
+            dry_run: other.dry_run.or(self.dry_run),
+            buffer_size: other.buffer_size.or(self.buffer_size),
+            atomic: other.atomic.or(self.atomic),
+            preserve_timestamps: other.preserve_timestamps.or(self.preserve_timestamps),
+            pack_format: other.pack_format.or(self.pack_format),
+            supported_formats: other.supported_formats.or(self.supported_formats),
+            out: other.out.or(self.out),
+            dir: other.dir.or(self.dir),
+            also_emit_zip: other.also_emit_zip.or(self.also_emit_zip),
+            description: other.description.or(self.description),
+            tolerate_missing_inputs: other
+                .tolerate_missing_inputs
+                .or(self.tolerate_missing_inputs),
+            url_failure_policy: other.url_failure_policy.or(self.url_failure_policy),
+            namespaces: other.namespaces.or(self.namespaces),
+            deny_namespaces: other.deny_namespaces.or(self.deny_namespaces),
+            merge_tags: other.merge_tags.or(self.merge_tags),
+            merge_fonts: other.merge_fonts.or(self.merge_fonts),
+            smart_json_merge: other.smart_json_merge.or(self.smart_json_merge),
+            target_format: other.target_format.or(self.target_format),
+            allowed_conflicts: other.allowed_conflicts.or(self.allowed_conflicts),
+            fail_on_conflict: other.fail_on_conflict.or(self.fail_on_conflict),
+            expand_url_listings: other.expand_url_listings.or(self.expand_url_listings),
+            compression: other.compression.or(self.compression),
+            add_files: other.add_files.or(self.add_files),
+            post_merge_command: other.post_merge_command.or(self.post_merge_command),
+        }
+    }
+
+    /// Resolve the [`MergeOptions`] this config describes, using the library defaults for
+    /// unset fields.
+    ///
+    /// String fields are parsed into their enums and `add_files` sources are read from disk; an
+    /// invalid value or unreadable file is a [`MergeError::InvalidInput`]. The run layout
+    /// (`inputs`, `out`, `dir`, `also_emit_zip`, `target_format`) and the CLI-only fields
+    /// (`fail_on_conflict`, `post_merge_command`) are not part of the options; see
+    /// [`Config::into_settings`].
+    pub fn into_options(self) -> Result<MergeOptions> {
+        fn parse<T: std::str::FromStr<Err = String>>(
+            field: &str,
+            value: Option<String>,
+        ) -> Result<Option<T>> {
+            value
+                .map(|s| s.parse())
+                .transpose()
+                .map_err(|e| MergeError::InvalidInput(format!("invalid {} value: {}", field, e)))
+        }
+        let defaults = MergeOptions::default();
+        let mut extra_files = Vec::new();
+        for spec in self.add_files.iter().flatten() {
+            // Split at the last colon so Windows drive letters stay part of SRC
+            let (src, dest) = spec.rsplit_once(':').ok_or_else(|| {
+                MergeError::InvalidInput(format!(
+                    "invalid add_files value (expected SRC:DEST): {}",
+                    spec
+                ))
+            })?;
+            let bytes = std::fs::read(src).map_err(|e| {
+                MergeError::InvalidInput(format!("failed to read added file {}: {}", src, e))
+            })?;
+            extra_files.push((dest.to_string(), bytes));
+        }
+        Ok(MergeOptions {
+            overwrite: parse("overwrite", self.overwrite)?.unwrap_or(defaults.overwrite),
+            dry_run: self.dry_run.unwrap_or(defaults.dry_run),
+            buffer_size: self.buffer_size.unwrap_or(defaults.buffer_size),
+            atomic: self.atomic.unwrap_or(defaults.atomic),
+            preserve_timestamps: self
+                .preserve_timestamps
+                .unwrap_or(defaults.preserve_timestamps),
+            pack_format_override: self.pack_format,
+            supported_formats_policy: parse("supported_formats", self.supported_formats)?
+                .unwrap_or(defaults.supported_formats_policy),
+            description_override: self.description,
+            tolerate_missing_inputs: self
+                .tolerate_missing_inputs
+                .unwrap_or(defaults.tolerate_missing_inputs),
+            url_failure_policy: parse("url_failure_policy", self.url_failure_policy)?
+                .unwrap_or(defaults.url_failure_policy),
+            namespaces: self.namespaces,
+            deny_namespaces: self.deny_namespaces.unwrap_or_default(),
+            merge_tags: self.merge_tags.unwrap_or(defaults.merge_tags),
+            merge_fonts: self.merge_fonts.unwrap_or(defaults.merge_fonts),
+            smart_json_merge: self.smart_json_merge.unwrap_or(defaults.smart_json_merge),
+            expand_url_listings: self
+                .expand_url_listings
+                .unwrap_or(defaults.expand_url_listings),
+            allowed_conflicts: self.allowed_conflicts.unwrap_or_default(),
+            compression: parse("compression", self.compression)?,
+            extra_files,
+            ..MergeOptions::default()
+        })
+    }
+
+    /// Resolve the full run this config describes: the inputs from [`Config::pack_inputs`], the
+    /// output layout and [`Config::into_options`]. Fails with [`MergeError::InvalidInput`] when
+    /// `out` is not set.
+    pub fn into_settings(self) -> Result<Settings> {
+        let inputs = self.pack_inputs(Vec::new())?;
+        let zip_passwords = self.zip_passwords(0);
+        let out = self.out.clone().ok_or_else(|| {
+            MergeError::InvalidInput("no output path; set `out` in the config".to_string())
+        })?;
+        let (dir, also_emit_zip) = (self.dir.unwrap_or(false), self.also_emit_zip.clone());
+        Ok(Settings {
+            inputs,
+            out: PathBuf::from(out),
+            dir,
+            also_emit_zip: also_emit_zip.map(PathBuf::from),
+            options: MergeOptions {
+                zip_passwords,
+                ..self.into_options()?
+            },
+        })
+    }
+
+    /// Build the ordered input list for a run: config inputs first, then `extra` (e.g. CLI
+    /// positional inputs), then any config inputs marked `force_top`, in their relative order.
+    /// Config inputs whose `for_formats` range excludes `target_format` are left out. Fails if
+    /// an input's `subdir` is invalid (see [`ConfigInput::pack_input`]).
+    pub fn pack_inputs(&self, extra: Vec<PackInput>) -> Result<Vec<PackInput>> {
+        let (normal, top) = self.ordered_inputs();
+        normal
+            .into_iter()
+            .map(ConfigInput::pack_input)
+            .chain(extra.into_iter().map(Ok))
+            .chain(top.into_iter().map(ConfigInput::pack_input))
+            .collect()
+    }
+
+    /// The `password`s set on config inputs, keyed by the input's position in
+    /// [`Config::pack_inputs`] called with `extra_count` extra inputs, i.e. the keys
+    /// [`MergeOptions::zip_passwords`] expects for that input list.
+    pub fn zip_passwords(&self, extra_count: usize) -> HashMap<usize, String> {
+        let (normal, top) = self.ordered_inputs();
+        normal
+            .into_iter()
+            .map(Some)
+            .chain(std::iter::repeat_n(None, extra_count))
+            .chain(top.into_iter().map(Some))
+            .enumerate()
+            .filter_map(|(idx, input)| Some((idx, input?.password()?.to_string())))
+            .collect()
+    }
+
+    /// The config inputs used for `target_format`, split into regular and `force_top` ones.
+    fn ordered_inputs(&self) -> (Vec<&ConfigInput>, Vec<&ConfigInput>) {
+        self.inputs
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|i| i.applies_to(self.target_format))
+            .partition(|i| !i.force_top())
+    }
+
+    /// A config populated with the library defaults and placeholder inputs/output, suitable
+    /// as a starting point for new users.
+    pub fn example() -> Config {
+        let defaults = MergeOptions::default();
+        Config {
+            inputs: Some(vec![
+                "path/to/base_pack".into(),
+                "path/to/override_pack.zip".into(),
+                "https://example.com/packs/extra_resources.zip".into(),
+            ]),
+            overwrite: Some("last".to_string()),
+            dry_run: Some(defaults.dry_run),
+            buffer_size: Some(defaults.buffer_size),
+            atomic: Some(defaults.atomic),
+            preserve_timestamps: Some(defaults.preserve_timestamps),
+            pack_format: None,
+            supported_formats: Some("one-to-highest".to_string()),
+            out: Some("merged.zip".to_string()),
+            dir: Some(false),
+            also_emit_zip: None,
+            description: None,
+            tolerate_missing_inputs: Some(defaults.tolerate_missing_inputs),
+            url_failure_policy: Some("error-all".to_string()),
+            namespaces: None,
+            deny_namespaces: Some(Vec::new()),
+            merge_tags: Some(defaults.merge_tags),
+            merge_fonts: Some(defaults.merge_fonts),
+            smart_json_merge: Some(defaults.smart_json_merge),
+            target_format: None,
+            allowed_conflicts: None,
+            fail_on_conflict: Some(false),
+            expand_url_listings: Some(defaults.expand_url_listings),
+            compression: None,
+            add_files: None,
+            post_merge_command: None,
+        }
+    }
+
+    /// Render [`Config::example`] as pretty JSON with a `"// <field>"` comment entry before each
+    /// field. The comment keys are ignored when the file is read back with [`read_config_file`].
+    pub fn example_json() -> String {
+        let body = serde_json::to_string_pretty(&Config::example()).unwrap_or_default();
+        let mut out = String::new();
+        for line in body.lines() {
+            // Top-level fields are the only lines indented by exactly two spaces
+            if let Some(rest) = line.strip_prefix("  \"") {
+                if !rest.starts_with(' ') {
+                    let field = rest.split('"').next().unwrap_or_default();
+                    if let Some(comment) = config_field_comment(field) {
+                        let comment = serde_json::to_string(comment).unwrap_or_default();
+                        out.push_str(&format!("  \"// {}\": {},\n", field, comment));
+                    }
+                }
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Short explanation for each config field, used when scaffolding an example config.
+fn config_field_comment(field: &str) -> Option<&'static str> {
+    Some(match field {
+        "inputs" => "Packs to merge (directories, zip files, or URLs). Later inputs win; relative paths resolve against this file. Use { \"path\": ..., \"force_top\": true } to always apply an input last, or \"for_formats\": [min, max] to only use it for those target formats.",
+        "overwrite" => "Overwrite policy: last | first | error | skip",
+        "dry_run" => "Validate inputs without writing output",
+        "buffer_size" => "Buffer size in bytes for streaming copies",
+        "atomic" => "Write to a temp file then rename",
+        "preserve_timestamps" => "Preserve file timestamps when extracting into a directory",
+        "pack_format" => "Force pack_format in pack.mcmeta (null = highest found)",
+        "supported_formats" => "supported_formats policy: one-to-highest | lowest-to-highest | one-to-latest",
+        "out" => "Output path (zip file, or directory when dir is true)",
+        "dir" => "Write output as a directory instead of a zip file",
+        "also_emit_zip" => "With dir, also write the merged pack as a zip at this path (null = directory only)",
+        "description" => "Description for the generated pack.mcmeta (null = default)",
+        "tolerate_missing_inputs" => "Warn and skip URL inputs that fail to download",
+        "url_failure_policy" => "Which URL download failures skip the input: skip-all|skip-transient|error-all (skip-transient skips unreachable hosts, timeouts and HTTP 5xx but fails on e.g. a 404)",
+        "namespaces" => "Keep only these namespaces under assets/ and data/ (null = all)",
+        "deny_namespaces" => "Drop these namespaces under assets/ and data/, applied after namespaces",
+        "merge_tags" => "Union data/<ns>/tags values across inputs instead of overwriting",
+        "merge_fonts" => "Concatenate assets/<ns>/font providers across inputs instead of overwriting",
+        "smart_json_merge" => "Merge tags, fonts, lang files, sounds.json and atlas sources across inputs instead of overwriting",
+        "target_format" => "Target pack format used to select inputs with for_formats (null = use all inputs)",
+        "allowed_conflicts" => "Globs of paths several inputs may provide without it counting as a conflict (e.g. \"assets/*/lang/**\")",
+        "fail_on_conflict" => "Exit with code 3 if any other path is provided by more than one input",
+        "add_files" => "Files to add to the output after all inputs, as \"SRC:DEST\" (e.g. \"credits.txt:credits.txt\")",
+        "compression" => "Output compression: stored|fast|best (null = default deflate)",
+        "post_merge_command" => "Shell command to run after a successful write, with {out} replaced by the quoted output path, also in $RESOURCE_MERGER_OUT (runs with your privileges; only use trusted configs)",
+        "expand_url_listings" => "Read URL inputs ending in / as HTML directory listings and merge every linked .zip in link order (needs the dir-listing feature)",
+        _ => return None,
+    })
+}
+
+/// Read a JSON config file and return a Config structure.
+///
+/// Relative `inputs`, `out` and `add_files` source paths are resolved against the config file's
+/// directory so the config behaves the same regardless of the current working directory.
+/// Absolute paths and URLs are left untouched.
+pub fn read_config_file(path: &Path) -> Result<Config> {
+    let s = std::fs::read_to_string(path)?;
+    let mut cfg: Config = serde_json::from_str(&s).map_err(|e| {
+        MergeError::InvalidInput(format!(
+            "failed to parse JSON config {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    if let Some(base) = path.parent() {
+        if let Some(inputs) = cfg.inputs.as_mut() {
+            for input in inputs.iter_mut() {
+                let resolved = resolve_config_path(base, input.path());
+                *input.path_mut() = resolved;
+            }
+        }
+        if let Some(out) = cfg.out.as_mut() {
+            *out = resolve_config_path(base, out);
+        }
+        if let Some(zip) = cfg.also_emit_zip.as_mut() {
+            *zip = resolve_config_path(base, zip);
+        }
+        for spec in cfg.add_files.iter_mut().flatten() {
+            if let Some((src, dest)) = spec.rsplit_once(':') {
+                *spec = format!("{}:{}", resolve_config_path(base, src), dest);
+            }
+        }
+    }
+    Ok(cfg)
+}
+
+/// Resolve a path string from a config file against the config's directory.
+fn resolve_config_path(base: &Path, s: &str) -> String {
+    if s.starts_with("http://") || s.starts_with("https://") || Path::new(s).is_absolute() {
+        return s.to_string();
+    }
+    base.join(s).to_string_lossy().into_owned()
+}
+
+fn read_dir_into_map(dir: &Path, map: &mut HashMap<String, Vec<u8>>) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(MergeError::InvalidInput(format!(
+            "{} is not a directory",
+            dir.display()
+        )));
+    }
+
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_file() {
+            let rel = path.strip_prefix(dir).unwrap();
+            // Use forward slashes as zip paths
+            let key = rel
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let mut f = File::open(path)?;
+            let mut buf = Vec::new();
+            f.read_to_end(&mut buf)?;
+            map.insert(key, buf);
+        }
+    }
+    Ok(())
+}
+
+/// List the files of a directory input as streamed entries without reading them.
+fn list_dir_entries(dir: &Path, map: &mut HashMap<String, EntrySource>) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(MergeError::InvalidInput(format!(
+            "{} is not a directory",
+            dir.display()
+        )));
+    }
+
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_file() {
+            let rel = path.strip_prefix(dir).unwrap();
+            let key = rel
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            map.insert(key, EntrySource::File(path.to_path_buf()));
+        }
+    }
+    Ok(())
+}
+
+/// Encoding used for zip entry names without the UTF-8 flag; `None` keeps the CP437 default.
+pub type NameEncoding = Option<&'static encoding_rs::Encoding>;
+
+/// The name of a zip entry, decoding its raw bytes with `encoding` if the archive doesn't flag
+/// it as UTF-8.
+fn decoded_entry_name<R: Read>(file: &zip::read::ZipFile<'_, R>, encoding: NameEncoding) -> String {
+    match encoding {
+        // The zip crate decodes unflagged names as CP437, so a decoded name that differs from
+        // the raw bytes wasn't UTF-8 (and ASCII names read the same in every encoding)
+        Some(enc) if file.name().as_bytes() != file.name_raw() => enc
+            .decode_without_bom_handling(file.name_raw())
+            .0
+            .into_owned(),
+        _ => file.name().to_string(),
+    }
+}
+
+/// List the entries of an opened zip as streamed entries referring to `zips[archive_idx]`.
+fn list_zip_entries<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    archive_idx: usize,
+    map: &mut HashMap<String, EntrySource>,
+    encoding: NameEncoding,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    for i in 0..archive.len() {
+        let name = match encoding {
+            // Raw names are only needed to decode them; the central directory is already read
+            Some(_) => decoded_entry_name(&archive.by_index_raw(i)?, encoding),
+            None => archive.name_for_index(i).unwrap_or_default().to_string(),
+        };
+        if name.ends_with('/') {
+            continue;
+        }
+        if let Some(name) = sanitize_zip_entry_name(&name) {
+            let src = EntrySource::ZipEntry {
+                archive: archive_idx,
+                index: i,
+            };
+            insert_archive_entry(map, name, src, on_duplicate, label)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_zipfile_into_map(
+    path: &Path,
+    map: &mut HashMap<String, Vec<u8>>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+    on_duplicate: DuplicateEntryPolicy,
+) -> Result<()> {
+    let label = path.display().to_string();
+    read_zip_into_map(
+        File::open(path)?,
+        map,
+        password,
+        encoding,
+        on_duplicate,
+        &label,
+    )
+}
+
+fn read_zipbytes_into_map(
+    bytes: &[u8],
+    map: &mut HashMap<String, Vec<u8>>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    read_zip_into_map(
+        Cursor::new(bytes),
+        map,
+        password,
+        encoding,
+        on_duplicate,
+        label,
+    )
+}
+
+/// Read every file entry of a zip into `map`, which must only hold entries of this archive so
+/// that duplicate names can be detected.
+fn read_zip_into_map<R: Read + Seek>(
+    reader: R,
+    map: &mut HashMap<String, Vec<u8>>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    let mut archive = open_zip_checked(reader, on_duplicate, label)?;
+    read_archive_into_map(&mut archive, map, password, encoding, on_duplicate, label)
+}
+
+/// Read every file entry of an already opened zip into `map`, like [`read_zip_into_map`].
+fn read_archive_into_map<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    map: &mut HashMap<String, Vec<u8>>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut file = open_zip_entry(archive, i, password)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = decoded_entry_name(&file, encoding);
+        // Sanitize zip entry name to a normalized forward-slash form and skip unsafe entries
+        let name = match sanitize_zip_entry_name(&name) {
+            Some(n) => n,
+            None => continue,
+        };
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        insert_archive_entry(map, name, buf, on_duplicate, label)?;
+    }
+    Ok(())
+}
+
+/// Insert an entry read from an archive, applying `on_duplicate` when the same archive already
+/// provided an entry with this name.
+fn insert_archive_entry<V>(
+    map: &mut HashMap<String, V>,
+    name: String,
+    value: V,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    if map.contains_key(&name) {
+        match on_duplicate {
+            DuplicateEntryPolicy::Error => {
+                return Err(MergeError::InvalidInput(format!(
+                    "{}: duplicate entry {}",
+                    label, name
+                )));
+            }
+            DuplicateEntryPolicy::KeepFirst => {
+                eprintln!(
+                    "warning: {}: duplicate entry {}; keeping the first copy",
+                    label, name
+                );
+                return Ok(());
+            }
+            DuplicateEntryPolicy::KeepLast => eprintln!(
+                "warning: {}: duplicate entry {}; keeping the last copy",
+                label, name
+            ),
+        }
+    }
+    map.insert(name, value);
+    Ok(())
+}
+
+/// Open a zip archive, first checking for entries with byte-identical names.
+///
+/// The zip reader collapses such entries into one (keeping the last copy), so they can only be
+/// detected by comparing the entry count declared in the end-of-central-directory record with
+/// the number of entries the reader exposes.
+fn open_zip_checked<R: Read + Seek>(
+    mut reader: R,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<ZipArchive<R>> {
+    let declared = declared_zip_entry_count(&mut reader);
+    let archive = ZipArchive::new(reader)?;
+    if let Some(declared) = declared.filter(|d| *d > archive.len()) {
+        let msg = format!(
+            "{}: {} entries share a name with another entry",
+            label,
+            declared - archive.len()
+        );
+        if on_duplicate == DuplicateEntryPolicy::Error {
+            return Err(MergeError::InvalidInput(msg));
+        }
+        eprintln!("warning: {}; only the last copy of each can be read", msg);
+    }
+    Ok(archive)
+}
+
+/// Entry count declared by a zip's end-of-central-directory record. Returns None when the
+/// record can't be found or the archive uses zip64 counts.
+fn declared_zip_entry_count<R: Read + Seek>(reader: &mut R) -> Option<usize> {
+    const EOCD_LEN: u64 = 22;
+    let len = reader.seek(SeekFrom::End(0)).ok()?;
+    // The record is at most a maximum-length comment away from the end
+    let tail_len = len.min(EOCD_LEN + u16::MAX as u64);
+    reader.seek(SeekFrom::Start(len - tail_len)).ok()?;
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.read_exact(&mut tail).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let pos = tail.windows(4).rposition(|w| w == b"PK\x05\x06")?;
+    let record = tail.get(pos..pos + EOCD_LEN as usize)?;
+    let count = u16::from_le_bytes([record[10], record[11]]);
+    (count != u16::MAX).then_some(count as usize)
+}
+
+/// Read every regular file of a tar stream into `map`, using the same entry-name sanitizing as
+/// zip inputs.
+fn read_tar_into_map<R: Read>(
+    reader: R,
+    map: &mut HashMap<String, Vec<u8>>,
+    on_duplicate: DuplicateEntryPolicy,
+    label: &str,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let raw = entry.path()?.to_string_lossy().into_owned();
+        let name = match sanitize_zip_entry_name(&raw) {
+            Some(n) => n,
+            None => continue,
+        };
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        insert_archive_entry(map, name, buf, on_duplicate, label)?;
+    }
+    Ok(())
+}
+
+/// Open a zip entry by index, decrypting it when a password is given.
+/// Entries that aren't encrypted are read normally even if a password is supplied.
+fn open_zip_entry<'a, R: Read + Seek>(
+    archive: &'a mut ZipArchive<R>,
+    index: usize,
+    password: Option<&str>,
+) -> Result<zip::read::ZipFile<'a, R>> {
+    let file = match password {
+        Some(pw) => archive.by_index_decrypt(index, pw.as_bytes())?,
+        None => archive.by_index(index)?,
+    };
+    Ok(file)
+}
+
+/// Decompress every entry of `archive` and compare it against the CRC-32 stored in the archive.
+fn verify_zip_crcs<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    password: Option<&str>,
+    label: &str,
+) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut file = open_zip_entry(archive, i, password)?;
+        if file.is_dir() {
+            continue;
+        }
+        // The zip reader checks the CRC-32 once the entry has been read to the end
+        if let Err(e) = std::io::copy(&mut file, &mut std::io::sink()) {
+            if e.kind() == std::io::ErrorKind::InvalidData {
+                return Err(MergeError::InvalidInput(format!(
+                    "{}: entry {} failed CRC-32 verification (stored {:08x}): {}",
+                    label,
+                    file.name(),
+                    file.crc32(),
+                    e
+                )));
+            }
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+/// Match a forward-slash `path` against a glob `pattern`: `*` matches any run of characters
+/// within one segment, `**` matches across segments (`**/` may also match nothing) and `?`
+/// matches a single non-`/` character.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(p: &[char], s: &[char]) -> bool {
+        match p {
+            [] => s.is_empty(),
+            ['*', '*', rest @ ..] => {
+                if let ['/', after @ ..] = rest {
+                    if matches(after, s) {
+                        return true;
+                    }
+                }
+                (0..=s.len()).any(|i| matches(rest, &s[i..]))
+            }
+            ['*', rest @ ..] => (0..=s.len())
+                .take_while(|&i| i == 0 || s[i - 1] != '/')
+                .any(|i| matches(rest, &s[i..])),
+            ['?', rest @ ..] => matches!(s, [c, tail @ ..] if *c != '/' && matches(rest, tail)),
+            [c, rest @ ..] => matches!(s, [d, tail @ ..] if d == c && matches(rest, tail)),
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = path.chars().collect();
+    matches(&p, &s)
+}
+
+/// Normalize a zip entry name into a safe forward-slash form suitable for
+/// using as a zip path and for converting into OS paths when extracting.
+/// Returns None for absolute paths or entries that attempt to traverse up
+/// the filesystem ("..").
+fn sanitize_zip_entry_name(name: &str) -> Option<String> {
+    // Convert any backslashes to forward slashes (some zip writers use them)
+    let n = name.replace('\\', "/");
+    // Reject absolute paths
+    if n.starts_with('/') || n.starts_with("\\") {
+        return None;
+    }
+    // Split and remove any empty components (caused by leading/trailing slashes) and `.`, so
+    // e.g. `./pack.mcmeta` is recognized as the root pack.mcmeta
+    let comps: Vec<&str> = n
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect();
+    // Reject parent-traversal components for safety (zip-slip)
+    if comps.contains(&"..") {
+        return None;
+    }
+    if comps.is_empty() {
+        return None;
+    }
+    Some(comps.join("/"))
+}
+
+/// Whether a normalized entry path should be kept when restricting output to `namespaces`.
+/// Root files are always kept. `assets/<ns>/**` and `data/<ns>/**` are kept for listed
+/// namespaces, including inside overlay directories (`<overlay>/assets/<ns>/**`). Anything else
+/// is dropped.
+fn path_in_namespaces(path: &str, namespaces: &[String]) -> bool {
+    if !path.contains('/') {
+        return true;
+    }
+    match path_namespace(path) {
+        Some(ns) => namespaces.iter().any(|n| n == ns),
+        None => false,
+    }
+}
+
+/// The namespace of an `assets/<ns>/**` or `data/<ns>/**` path, also inside an overlay
+/// directory (`<overlay>/assets/<ns>/**`).
+fn path_namespace(path: &str) -> Option<&str> {
+    let comps: Vec<&str> = path.split('/').collect();
+    if comps.len() > 1 && (comps[0] == "assets" || comps[0] == "data") {
+        Some(comps[1])
+    } else if comps.len() > 2 && (comps[1] == "assets" || comps[1] == "data") {
+        Some(comps[2])
+    } else {
+        None
+    }
+}
+
+/// Combines an earlier and a later version of a JSON file, or None to fall back to overwriting.
+type JsonMerge = fn(&[u8], &[u8]) -> Option<Vec<u8>>;
+
+/// Pick how a later copy of `path` is combined with an earlier one, if the options merge it.
+fn json_merge_for(path: &str, opts: &MergeOptions) -> Option<JsonMerge> {
+    let smart = opts.smart_json_merge;
+    if (opts.merge_tags || smart) && is_tag_path(path) {
+        Some(merge_tag_json)
+    } else if (opts.merge_fonts || smart) && is_font_path(path) {
+        Some(merge_font_json)
+    } else if smart && (is_lang_json_path(path) || is_sounds_path(path)) {
+        Some(merge_object_json)
+    } else if smart && is_atlas_path(path) {
+        Some(merge_atlas_json)
+    } else {
+        None
+    }
+}
+
+/// Whether a normalized entry path is a datapack tag file (`data/<ns>/tags/**.json`).
+fn is_tag_path(path: &str) -> bool {
+    let comps: Vec<&str> = path.split('/').collect();
+    comps.len() > 3 && comps[0] == "data" && comps[2] == "tags" && path.ends_with(".json")
+}
+
+/// Merge a later tag file into an earlier one the way Minecraft does: `values` are concatenated
+/// without duplicates, unless the later file sets `"replace": true`, in which case it wins
+/// outright. Returns None if either side isn't a valid tag object.
+fn merge_tag_json(prev: &[u8], next: &[u8]) -> Option<Vec<u8>> {
+    let mut base: serde_json::Value = serde_json::from_str(decode_json_text(prev).ok()?).ok()?;
+    let over: serde_json::Value = serde_json::from_str(decode_json_text(next).ok()?).ok()?;
+    if over.get("replace").and_then(|v| v.as_bool()) == Some(true) {
+        return Some(next.to_vec());
+    }
+    let extra = over.get("values")?.as_array()?;
+    let values = base.get_mut("values")?.as_array_mut()?;
+    for v in extra {
+        if !values.contains(v) {
+            values.push(v.clone());
+        }
+    }
+    serde_json::to_vec_pretty(&base).ok()
+}
+
+/// Whether a normalized entry path is a font definition (`assets/<ns>/font/**.json`).
+fn is_font_path(path: &str) -> bool {
+    let comps: Vec<&str> = path.split('/').collect();
+    comps.len() > 3 && comps[0] == "assets" && comps[2] == "font" && path.ends_with(".json")
+}
+
+/// Merge a later font definition into an earlier one: the later `providers` are appended,
+/// skipping providers identical to one already present. Returns None if either side isn't a
+/// valid font object.
+fn merge_font_json(prev: &[u8], next: &[u8]) -> Option<Vec<u8>> {
+    let mut base: serde_json::Value = serde_json::from_str(decode_json_text(prev).ok()?).ok()?;
+    let over: serde_json::Value = serde_json::from_str(decode_json_text(next).ok()?).ok()?;
+    let extra = over.get("providers")?.as_array()?;
+    let providers = base.get_mut("providers")?.as_array_mut()?;
+    for p in extra {
+        if !providers.contains(p) {
+            providers.push(p.clone());
+        }
+    }
+    serde_json::to_vec_pretty(&base).ok()
+}
+
+/// Whether a normalized entry path is a JSON language file (`assets/<ns>/lang/*.json`).
+fn is_lang_json_path(path: &str) -> bool {
+    let comps: Vec<&str> = path.split('/').collect();
+    comps.len() == 4 && comps[0] == "assets" && comps[2] == "lang" && path.ends_with(".json")
+}
+
+/// Whether a normalized entry path is a namespace's sound event list (`assets/<ns>/sounds.json`).
+fn is_sounds_path(path: &str) -> bool {
+    let comps: Vec<&str> = path.split('/').collect();
+    comps.len() == 3 && comps[0] == "assets" && comps[2] == "sounds.json"
+}
+
+/// Whether a normalized entry path is a texture atlas definition (`assets/<ns>/atlases/**.json`).
+fn is_atlas_path(path: &str) -> bool {
+    let comps: Vec<&str> = path.split('/').collect();
+    comps.len() > 3 && comps[0] == "assets" && comps[2] == "atlases" && path.ends_with(".json")
+}
+
+/// Merge two JSON objects key by key, the later file winning for keys both define (lang
+/// entries, sound events). Returns None if either side isn't a JSON object.
+fn merge_object_json(prev: &[u8], next: &[u8]) -> Option<Vec<u8>> {
+    let mut base: serde_json::Value = serde_json::from_str(decode_json_text(prev).ok()?).ok()?;
+    let over: serde_json::Value = serde_json::from_str(decode_json_text(next).ok()?).ok()?;
+    let base_map = base.as_object_mut()?;
+    for (k, v) in over.as_object()? {
+        base_map.insert(k.clone(), v.clone());
+    }
+    serde_json::to_vec_pretty(&base).ok()
+}
+
+/// Merge a later atlas definition into an earlier one: the later `sources` are appended,
+/// skipping sources identical to one already present. Returns None if either side isn't a
+/// valid atlas object.
+fn merge_atlas_json(prev: &[u8], next: &[u8]) -> Option<Vec<u8>> {
+    let mut base: serde_json::Value = serde_json::from_str(decode_json_text(prev).ok()?).ok()?;
+    let over: serde_json::Value = serde_json::from_str(decode_json_text(next).ok()?).ok()?;
+    let extra = over.get("sources")?.as_array()?;
+    let sources = base.get_mut("sources")?.as_array_mut()?;
+    for s in extra {
+        if !sources.contains(s) {
+            sources.push(s.clone());
+        }
+    }
+    serde_json::to_vec_pretty(&base).ok()
+}
+
+/// Return the raw text of an input's root pack.mcmeta, or `None` if it has none.
+///
+/// Only pack.mcmeta is read (URLs are still downloaded whole). Unreadable inputs, encrypted
+/// entries and text that isn't UTF-8 are errors. Reads aren't time-bounded.
+pub fn read_mcmeta(input: &PackInput) -> Result<Option<String>> {
+    read_mcmeta_with_options(input, &MergeOptions::default())
+}
+
+/// Like [`read_mcmeta`], opening the input as a merge with `opts` would: `opts.zip_passwords`
+/// entry `0` decrypts an encrypted zip, `opts.filename_encoding` decodes entry names and
+/// `opts.per_input_timeout` bounds a URL download (directory and zip file reads aren't bounded).
+pub fn read_mcmeta_with_options(input: &PackInput, opts: &MergeOptions) -> Result<Option<String>> {
+    read_root_mcmeta(0, input, opts)?
+        .map(|b| {
+            decode_json_text(&b)
+                .map(str::to_string)
+                .map_err(|e| MergeError::InvalidInput(format!("pack.mcmeta: {}", e)))
+        })
+        .transpose()
+}
+
+/// The raw root pack.mcmeta of input `idx`, if it has one. The input is opened like a merge
+/// opens it: through a registered reader if one matches, with its `opts.zip_passwords` entry,
+/// `opts.filename_encoding` and, for URLs, `opts.per_input_timeout`.
+fn read_root_mcmeta(idx: usize, pack: &PackInput, opts: &MergeOptions) -> Result<Option<Vec<u8>>> {
+    let password = opts.zip_passwords.get(&idx).map(|s| s.as_str());
+    let encoding = opts.filename_encoding;
+    match pack {
+        PackInput::Dir(p) => {
+            let path = p.join("pack.mcmeta");
+            match path.is_file() {
+                true => Ok(Some(std::fs::read(path)?)),
+                false => Ok(None),
+            }
+        }
+        PackInput::ZipFile(p) if custom_reader_for(p).is_some() => {
+            let mut mem = HashMap::new();
+            read_custom_into_map(p, &mut mem)?;
+            Ok(mem.remove("pack.mcmeta"))
+        }
+        PackInput::ZipFile(p) => {
+            root_mcmeta_from_archive(&mut ZipArchive::new(File::open(p)?)?, password, encoding)
+        }
+        PackInput::ZipBytes(b) => {
+            root_mcmeta_from_archive(&mut ZipArchive::new(Cursor::new(b))?, password, encoding)
+        }
+        PackInput::Url(u) => {
+            let bytes = fetch_url_bytes_with_timeout(u, opts.per_input_timeout)?;
+            root_mcmeta_from_archive(
+                &mut ZipArchive::new(Cursor::new(bytes))?,
+                password,
+                encoding,
+            )
+        }
+        PackInput::TarBytes(b) => {
+            let mut archive = tar::Archive::new(Cursor::new(b));
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = sanitize_zip_entry_name(&entry.path()?.to_string_lossy());
+                if name.as_deref() == Some("pack.mcmeta") {
+                    let mut buf = Vec::new();
+                    entry.read_to_end(&mut buf)?;
+                    return Ok(Some(buf));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// The raw root pack.mcmeta of an opened zip, if it has one, decrypted with `password`.
+fn root_mcmeta_from_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+) -> Result<Option<Vec<u8>>> {
+    let Some(idx) = root_entry_index(archive, "pack.mcmeta", encoding)? else {
+        return Ok(None);
+    };
+    let mut buf = Vec::new();
+    open_zip_entry(archive, idx, password)?.read_to_end(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// Index of the entry that normalizes to the root file `name`, e.g. `./pack.mcmeta` for
+/// `pack.mcmeta`, with entry names decoded by `encoding`. An exact match wins; otherwise the
+/// last normalized match, as it is the one a merge keeps.
+fn root_entry_index<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    encoding: NameEncoding,
+) -> Result<Option<usize>> {
+    // An ASCII name reads the same in every encoding
+    if let Some(idx) = archive.index_for_name(name) {
+        return Ok(Some(idx));
+    }
+    for i in (0..archive.len()).rev() {
+        let entry_name = match encoding {
+            Some(_) => decoded_entry_name(&archive.by_index_raw(i)?, encoding),
+            None => archive.name_for_index(i).unwrap_or_default().to_string(),
+        };
+        if sanitize_zip_entry_name(&entry_name).as_deref() == Some(name) {
+            return Ok(Some(i));
+        }
+    }
+    Ok(None)
+}
+
+// Peek functions: try to locate pack.mcmeta and extract pack_format without reading all files.
+// Returns (pack_format, max_format_option, overlays_option)
+fn peek_pack_format_from_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    password: Option<&str>,
+    encoding: NameEncoding,
+) -> Option<PeekedFormats> {
+    let buf = root_mcmeta_from_archive(archive, password, encoding).ok()??;
+    parse_peeked_mcmeta(&buf)
+}
+
+fn peek_pack_format_from_dir(dir: &Path) -> Option<PeekedFormats> {
+    let p = dir.join("pack.mcmeta");
+    if p.is_file() {
+        if let Ok(buf) = std::fs::read(p) {
+            return parse_peeked_mcmeta(&buf);
+        }
+    }
+    None
+}
+
+/// Decode raw pack.mcmeta bytes and extract (pack_format, max_format, overlays).
+/// Peeking is best-effort: text that isn't UTF-8 gives `None` here, and merges reject it with
+/// [`check_root_mcmeta_encoding`].
+fn parse_peeked_mcmeta(bytes: &[u8]) -> Option<PeekedFormats> {
+    let s = decode_json_text(bytes).ok()?;
+    let formats = extract_pack_format_from_mcmeta(s).ok()?;
+    let overlays = extract_overlays_from_mcmeta(s);
+    Some((formats.0, formats.1, overlays))
+}
+
+/// Fail with [`MergeError::InvalidInput`] if input `idx`'s root pack.mcmeta isn't UTF-8 text
+/// (e.g. UTF-16 from a Windows editor): the game can't read it, so its formats would silently
+/// go missing from the merge.
+fn check_root_mcmeta_encoding(idx: usize, mcmeta: &[u8]) -> Result<()> {
+    decode_json_text(mcmeta)
+        .map(|_| ())
+        .map_err(|e| MergeError::InvalidInput(format!("input {}: pack.mcmeta: {}", idx + 1, e)))
+}
+
+/// How `minify_text` shrinks a file, by extension.
+#[derive(Clone, Copy)]
+enum MinifiedKind {
+    Json,
+    Lines,
+}
+
+fn minified_kind(path: &str) -> Option<MinifiedKind> {
+    let ext = Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "json" | "mcmeta" => Some(MinifiedKind::Json),
+        "properties" | "lang" => Some(MinifiedKind::Lines),
+        _ => None,
+    }
+}
+
+/// Compact JSON, or trim trailing whitespace from each line of a properties/lang file. Other
+/// files and text that doesn't decode or parse are returned unchanged.
+fn minify_text(path: &str, bytes: Vec<u8>) -> Vec<u8> {
+    match minified_kind(path) {
+        Some(MinifiedKind::Json) => decode_json_text(&bytes)
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok())
+            .map(|v| v.to_string().into_bytes())
+            .unwrap_or(bytes),
+        Some(MinifiedKind::Lines) => match std::str::from_utf8(&bytes) {
+            Ok(text) => {
+                let mut out: String = text
+                    .lines()
+                    .map(str::trim_end)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if text.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.into_bytes()
+            }
+            Err(_) => bytes,
+        },
+        None => bytes,
+    }
+}
+
+/// Decode a JSON text file as UTF-8, stripping a leading byte-order mark if present.
+/// UTF-16 files (detected by their BOM) are rejected with a descriptive error.
+fn decode_json_text(bytes: &[u8]) -> std::result::Result<&str, String> {
+    if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+        return Err("file is UTF-16 encoded; re-save it as UTF-8".to_string());
+    }
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    std::str::from_utf8(bytes).map_err(|e| format!("file is not valid UTF-8: {}", e))
+}
+
+/// Extract overlays section from a pack.mcmeta JSON string.
+fn extract_overlays_from_mcmeta(s: &str) -> Option<serde_json::Value> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(s) {
+        if let Some(overlays) = json.get("overlays") {
+            return Some(overlays.clone());
+        }
+    }
+    None
+}
+
+/// Describe the first overlay directory whose format ranges across `overlays_list` can't be
+/// combined into one range (they leave a gap), or None if every directory's ranges are unionable.
+fn overlay_range_conflict(overlays_list: &[serde_json::Value]) -> Option<String> {
+    let mut ranges: BTreeMap<&str, Vec<(u32, u32)>> = BTreeMap::new();
+    let entries = overlays_list
+        .iter()
+        .filter_map(|o| o.get("entries").and_then(|v| v.as_array()))
+        .flatten();
+    for entry in entries {
+        if let (Some(dir), Some(range)) = (
+            entry.get("directory").and_then(|v| v.as_str()),
+            overlay_format_range(entry),
+        ) {
+            ranges.entry(dir).or_default().push(range);
+        }
+    }
+    for (dir, mut list) in ranges {
+        list.sort();
+        let mut covered = list[0];
+        for &(min, max) in &list[1..] {
+            if min > covered.1.saturating_add(1) {
+                return Some(format!(
+                    "overlay directory \"{}\" declares formats {}-{} and {}-{} in different entries; the ranges can't be combined",
+                    dir, covered.0, covered.1, min, max
+                ));
+            }
+            covered.1 = covered.1.max(max);
+        }
+    }
+    None
+}
+
+/// Merge overlays from multiple pack.mcmeta files.
+/// Later overlays overwrite earlier ones based on directory name.
+fn merge_overlays(overlays_list: &[serde_json::Value]) -> Option<serde_json::Value> {
+    if overlays_list.is_empty() {
+        return None;
+    }
+
+    // Collect all overlay entries, keyed by directory name. Entries for the same directory are
+    // deep-merged, so fields a later entry doesn't set are kept from earlier ones. The game
+    // applies overlays in the order they are listed, so entries keep the position of their
+    // directory's first declaration (inputs in order, then entries in order).
+    let mut merged_entries: Vec<(String, serde_json::Value)> = Vec::new();
+
+    for overlay_val in overlays_list {
+        if let Some(entries_arr) = overlay_val.get("entries").and_then(|v| v.as_array()) {
+            for entry in entries_arr {
+                if let Some(dir) = entry.get("directory").and_then(|v| v.as_str()) {
+                    match merged_entries.iter_mut().find(|(d, _)| d == dir) {
+                        Some((_, prev)) => deep_merge_json(prev, entry),
+                        None => merged_entries.push((dir.to_string(), entry.clone())),
+                    }
+                }
+            }
+        }
+    }
+
+    if merged_entries.is_empty() {
+        return None;
+    }
+
+    let entries_array: Vec<serde_json::Value> =
+        merged_entries.into_iter().map(|(_, v)| v).collect();
+
+    Some(serde_json::json!({
+        "entries": entries_array
+    }))
+}
+
+/// Add the `language` entries of one input's pack.mcmeta to `merged`, replacing entries with
+/// the same language code. Unreadable files and missing blocks are ignored.
+fn merge_language_block(merged: &mut Option<serde_json::Value>, mcmeta: &[u8]) {
+    let Some(serde_json::Value::Object(codes)) = decode_json_text(mcmeta)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|mut v| v.get_mut("language").map(|l| l.take()))
+    else {
+        return;
+    };
+    let merged = merged.get_or_insert_with(|| serde_json::json!({}));
+    if let Some(obj) = merged.as_object_mut() {
+        obj.extend(codes);
+    }
+}
+
+/// Replace the `language` block of the pack.mcmeta in `mcmeta` with `language`.
+fn with_language_block(
+    mcmeta: &[u8],
+    language: &serde_json::Value,
+    pretty: bool,
+) -> Result<Vec<u8>> {
+    let text = decode_json_text(mcmeta)
+        .map_err(|e| MergeError::InvalidInput(format!("pack.mcmeta: {}", e)))?;
+    let mut meta: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| MergeError::InvalidInput(format!("pack.mcmeta: {}", e)))?;
+    if let Some(obj) = meta.as_object_mut() {
+        obj.insert("language".to_string(), language.clone());
+    }
+    match pretty {
+        true => Ok(format!("{:#}", meta).into_bytes()),
+        false => Ok(meta.to_string().into_bytes()),
+    }
+}
+
+/// Merge `next` into `base`: objects are merged key by key, anything else in `next` replaces
+/// the value in `base`.
+fn deep_merge_json(base: &mut serde_json::Value, next: &serde_json::Value) {
+    match (base, next) {
+        (serde_json::Value::Object(b), serde_json::Value::Object(n)) => {
+            for (k, v) in n {
+                match b.get_mut(k) {
+                    Some(existing) => deep_merge_json(existing, v),
+                    None => {
+                        b.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+        }
+        (b, n) => *b = n.clone(),
+    }
+}
+
+/// Read the inclusive (min, max) format range an overlay entry applies to.
+///
+/// Understands `formats` as a single number, a `[min, max]` array or a
+/// `{ "min_inclusive", "max_inclusive" }` object, as well as the newer `min_format`/`max_format`
+/// fields (where a `[major, minor]` array is reduced to its major version).
+fn overlay_format_range(entry: &serde_json::Value) -> Option<(u32, u32)> {
+    let num = |v: &serde_json::Value| -> Option<u32> {
+        v.as_u64()
+            .or_else(|| {
+                v.as_array()
+                    .and_then(|a| a.first())
+                    .and_then(|f| f.as_u64())
+            })
+            .map(|n| n as u32)
+    };
+    if let Some(f) = entry.get("formats") {
+        if let Some(n) = f.as_u64() {
+            return Some((n as u32, n as u32));
+        }
+        if let Some(arr) = f.as_array() {
+            let min = arr.first().and_then(|v| v.as_u64())? as u32;
+            let max = arr.last().and_then(|v| v.as_u64())? as u32;
+            return Some((min, max));
+        }
+        if f.is_object() {
+            let min = f.get("min_inclusive").and_then(num)?;
+            let max = f.get("max_inclusive").and_then(num)?;
+            return Some((min, max));
+        }
+    }
+    let min = entry.get("min_format").and_then(num)?;
+    let max = entry.get("max_format").and_then(num).unwrap_or(min);
+    Some((min, max))
+}
+
+/// Apply overlays that cover `target` onto the base paths (in declaration order, so later
+/// entries win) and remove every overlay directory from `files`.
+fn flatten_overlays<V: Clone>(
+    files: &mut HashMap<String, V>,
+    overlays: &serde_json::Value,
+    target: u32,
+) {
+    let entries = match overlays.get("entries").and_then(|v| v.as_array()) {
+        Some(e) => e,
+        None => return,
+    };
+    let mut dirs: Vec<String> = Vec::new();
+    let mut applied: Vec<(String, V)> = Vec::new();
+    for entry in entries {
+        let dir = match entry.get("directory").and_then(|v| v.as_str()) {
+            Some(d) => d,
+            None => continue,
+        };
+        let prefix = format!("{}/", dir);
+        let covers = overlay_format_range(entry)
+            .map(|(min, max)| min <= target && target <= max)
+            .unwrap_or(false);
+        if covers {
+            let mut keys: Vec<&String> = files.keys().filter(|k| k.starts_with(&prefix)).collect();
+            keys.sort();
+            for k in keys {
+                applied.push((k[prefix.len()..].to_string(), files[k].clone()));
+            }
+        }
+        dirs.push(prefix);
+    }
+    files.retain(|k, _| !dirs.iter().any(|d| k.starts_with(d.as_str())));
+    for (k, v) in applied {
+        files.insert(k, v);
+    }
+}
+
+/// Try to extract pack_format and max_format from a pack.mcmeta JSON string.
+/// Returns (pack_format, max_format) where max_format might be higher than pack_format.
+fn extract_pack_format_from_mcmeta(s: &str) -> std::result::Result<(u32, Option<u32>), ()> {
+    // Quick and tolerant parser: look for "pack_format", "max_format", and "supported_formats".
+    // Accept both the common shape { "pack": { ... } } and rare top-level fields.
+    if let Ok(v) = serde_json::from_str::<serde_json::Value>(s) {
+        // helper to extract numeric from a Value
+        let try_from_value = |val: &serde_json::Value| -> Option<u32> {
+            if let Some(n) = val.as_u64() {
+                return Some(n as u32);
+            }
+            if let Some(s) = val.as_str() {
+                if let Ok(n) = s.parse::<u32>() {
+                    return Some(n);
+                }
+            }
+            None
+        };
+
+        let mut pack_format = None;
+        let mut max_format = None;
+
+        // Check common shape: { "pack": { ... } }
+        if let Some(pack) = v.get("pack") {
+            if let Some(fmt) = pack.get("pack_format") {
+                pack_format = try_from_value(fmt);
+            }
+
+            // Look for explicit max_format
+            if let Some(mf) = pack.get("max_format") {
+                max_format = try_from_value(mf);
+            }
+
+            // Also check supported_formats for max_inclusive
+            if let Some(sf) = pack.get("supported_formats") {
+                if let Some(obj) = sf.as_object() {
+                    if let Some(max_inc) = obj.get("max_inclusive") {
+                        if let Some(n) = try_from_value(max_inc) {
+                            max_format = Some(n);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Fallback: check top-level pack_format and max_format
+        if pack_format.is_none() {
+            if let Some(fmt) = v.get("pack_format") {
+                pack_format = try_from_value(fmt);
+            }
+        }
+        if max_format.is_none() {
+            if let Some(mf) = v.get("max_format") {
+                max_format = try_from_value(mf);
+            }
+        }
+
+        if let Some(pf) = pack_format {
+            return Ok((pf, max_format));
+        }
+    }
+    Err(())
+}
+
+fn make_pack_mcmeta(
+    pack_format: u32,
+    supported_formats: &[u32],
+    desc: &str,
+    max_format: u32,
+    overlays: Option<&serde_json::Value>,
+    post_process: Option<&McmetaPostProcess>,
+    pretty: bool,
+) -> String {
+    // Threshold for backwards compatibility: resource pack format < 65 requires old format
+    const OLD_FORMAT_THRESHOLD: u32 = 65;
+
+    // Determine min from supported_formats array
+    let min_format = supported_formats.first().copied().unwrap_or(pack_format);
+
+    // Check if we need backwards compatibility fields (if min_format < 65)
+    let needs_old_format = min_format < OLD_FORMAT_THRESHOLD;
+
+    let mut meta = if needs_old_format {
+        // Old format: include pack_format and supported_formats for backwards compatibility
+        serde_json::json!({
+            "pack": {
+                "pack_format": pack_format,
+                "min_format": min_format,
+                "max_format": max_format,
+                "description": desc,
+                "supported_formats": supported_formats
+            }
+        })
+    } else {
+        // New format (1.21.9+): use min_format and max_format only
+        serde_json::json!({
+            "pack": {
+                "min_format": min_format,
+                "max_format": max_format,
+                "description": desc
+            }
+        })
+    };
+
+    // Add overlays if present
+    if let Some(overlays_val) = overlays {
+        if let Some(obj) = meta.as_object_mut() {
+            obj.insert("overlays".to_string(), overlays_val.clone());
+        }
+    }
+
+    if let Some(hook) = post_process {
+        meta = (hook.0)(meta);
+    }
+
+    // Compact JSON (single-line) by default for smaller file size - Minecraft supports both
+    let text = match pretty {
+        true => serde_json::to_string_pretty(&meta),
+        false => serde_json::to_string(&meta),
+    };
+    text.unwrap_or_else(|_| {
+        "{\"pack\":{\"min_format\":1,\"max_format\":1,\"description\":\"resource_merger\"}}"
+            .to_string()
+    })
+}
+
+/// The eight bytes every PNG file starts with.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Smallest pack.png edge length we consider acceptable when validating icons.
+const MIN_PACK_PNG_SIZE: u32 = 64;
+
+/// Decode the PNG header and check that the icon is square and not too small.
+/// Returns the (width, height) on success or a human-readable reason on failure.
+fn check_pack_png_dimensions(bytes: &[u8]) -> std::result::Result<(u32, u32), String> {
+    let (w, h) = image::ImageReader::with_format(Cursor::new(bytes), image::ImageFormat::Png)
+        .into_dimensions()
+        .map_err(|e| format!("could not decode PNG header: {}", e))?;
+    if w != h {
+        return Err(format!("image is not square ({}x{})", w, h));
+    }
+    if w < MIN_PACK_PNG_SIZE {
+        return Err(format!(
+            "image is smaller than {0}x{0} ({1}x{2})",
+            MIN_PACK_PNG_SIZE, w, h
+        ));
+    }
+    Ok((w, h))
+}
+
+/// The candidate icon with the largest square dimensions; on ties the later input wins.
+fn highest_resolution_png(
+    candidates: &[EntrySource],
+    zips: &mut OpenZips,
+) -> Result<Option<Vec<u8>>> {
+    let mut best: Option<(u32, Vec<u8>)> = None;
+    for src in candidates {
+        let bytes = read_source(src, zips)?;
+        let size = image::ImageReader::with_format(Cursor::new(&bytes), image::ImageFormat::Png)
+            .into_dimensions()
+            .ok()
+            .and_then(|(w, h)| (w == h).then_some(w));
+        if let Some(size) = size {
+            if best.as_ref().is_none_or(|(b, _)| size >= *b) {
+                best = Some((size, bytes));
+            }
+        }
+    }
+    Ok(best.map(|(_, bytes)| bytes))
+}
+
+/// Decode a PNG icon and rescale it to a `size`x`size` square, re-encoded as PNG.
+fn resize_pack_png(bytes: &[u8], size: u32) -> std::result::Result<Vec<u8>, String> {
+    if size == 0 {
+        return Err("resize target must be at least 1 pixel".to_string());
+    }
+    let img = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .map_err(|e| format!("could not decode PNG: {}", e))?;
+    let resized = img.resize_exact(size, size, image::imageops::FilterType::Lanczos3);
+    let mut out = Vec::new();
+    resized
+        .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+        .map_err(|e| format!("could not encode PNG: {}", e))?;
+    Ok(out)
+}
+
+fn default_pack_png_bytes() -> Vec<u8> {
+    // Include the default pack image binary at compile time. The build script picks the PNG
+    // named by `RESOURCE_MERGER_DEFAULT_PNG`, or the bundled 64x64 `assets/default-pack-64.png`,
+    // and its bytes are embedded so we can always write `pack.png` when inputs don't provide one.
+    const BYTES: &[u8] = include_bytes!(env!("RESOURCE_MERGER_DEFAULT_PNG_PATH"));
+    BYTES.to_vec()
+}
+
+fn make_readme(
+    inputs: &[String],
+    dropped: &BTreeSet<usize>,
+    conflicts: Option<&[(&str, usize)]>,
+) -> String {
+    let mut out = String::new();
+    out.push_str("This resource pack was generated by resource_merger.\n\n");
+    out.push_str("Inputs used (in order, first -> last):\n");
+    let kept = inputs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !dropped.contains(i));
+    for (_, input) in kept {
+        out.push_str(&format!("- {}\n", input));
+    }
+    if let Some(conflicts) = conflicts {
+        out.push_str("\nOverridden files (path -> winning input):\n");
+        if conflicts.is_empty() {
+            out.push_str("- none\n");
+        }
+        for (path, idx) in conflicts {
+            out.push_str(&format!("- {} -> {}\n", path, winner_label(inputs, *idx)));
+        }
+    }
+    out.push_str(&format!(
+        "\nGenerated with resource_merger {}",
+        env!("CARGO_PKG_VERSION")
+    ));
+    out
+}
+
+/// Labels of `packs` as listed in the generated README.
+fn input_labels(packs: &[PackInput]) -> Vec<String> {
+    packs.iter().map(describe_input).collect()
+}
+
+/// Human-readable label for an input, as listed in the generated README.
+fn describe_input(p: &PackInput) -> String {
+    match p {
+        PackInput::Dir(pb) => format!("Dir: {}", pb.display()),
+        PackInput::ZipFile(pb) => format!("ZipFile: {}", pb.display()),
+        PackInput::ZipBytes(_) => "ZipBytes: <in-memory>".to_string(),
+        PackInput::Url(u) => format!("Url: {}", u),
+        PackInput::TarBytes(_) => "TarBytes: <in-memory>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+    use tempfile::tempdir;
+
+    /// Read every entry of a zip into a sorted name -> contents map.
+    fn zip_entries(bytes: &[u8]) -> anyhow::Result<std::collections::BTreeMap<String, Vec<u8>>> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut out = std::collections::BTreeMap::new();
+        for i in 0..archive.len() {
+            let mut f = archive.by_index(i)?;
+            let mut buf = Vec::new();
+            f.read_to_end(&mut buf)?;
+            out.insert(f.name().to_string(), buf);
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn merge_dirs_and_zipbytes() -> anyhow::Result<()> {
+        let d1 = tempdir()?;
+        let base = d1.path().join("base");
+        create_dir_all(base.join("assets/test"))?;
+        write(base.join("assets/test/a.txt"), b"hello")?;
+        write(base.join("assets/test/only_in_base.txt"), b"base")?;
+
+        let d2 = tempdir()?;
+        let override_dir = d2.path().join("over");
+        create_dir_all(override_dir.join("assets/test"))?;
+        write(override_dir.join("assets/test/a.txt"), b"world")?;
+
+        // create an in-memory zip with another file
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            zw.start_file(
+                "assets/test/b.txt",
+                zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default(),
+            )?;
+            zw.write_all(b"fromzip")?;
+            zw.finish()?;
+        }
+
+        let bytes = cursor.into_inner();
+
+        let packs = vec![
+            PackInput::Dir(base),
+            PackInput::Dir(override_dir),
+            PackInput::ZipBytes(bytes),
+        ];
+
+        let out = merge_packs_to_bytes(&packs)?;
+        // open output and assert contents
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        {
+            let mut a = archive.by_name("assets/test/a.txt")?;
+            let mut s = String::new();
+            a.read_to_string(&mut s)?;
+            assert_eq!(s, "world"); // overridden by second pack
+        }
+
+        {
+            let mut b = archive.by_name("assets/test/b.txt")?;
+            let mut s2 = String::new();
+            b.read_to_string(&mut s2)?;
+            assert_eq!(s2, "fromzip");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_password_protected_zip() -> anyhow::Result<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            zw.start_file(
+                "assets/test/secret.txt",
+                zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default()
+                    .with_aes_encryption(zip::AesMode::Aes256, "hunter2"),
+            )?;
+            zw.write_all(b"hidden")?;
+            zw.finish()?;
+        }
+        let packs = vec![PackInput::ZipBytes(cursor.into_inner())];
+
+        // Without a password the encrypted entry can't be read
+        assert!(merge_packs_to_bytes(&packs).is_err());
+
+        let mut opts = MergeOptions::default();
+        opts.zip_passwords.insert(0, "hunter2".to_string());
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let mut f = archive.by_name("assets/test/secret.txt")?;
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        assert_eq!(s, "hidden");
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_input_passwords_follow_reordering() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let mut zw = ZipWriter::new(File::create(d.path().join("secret.zip"))?);
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default()
+            .with_aes_encryption(zip::AesMode::Aes256, "hunter2");
+        zw.start_file("pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":22}}"#)?;
+        zw.start_file("assets/test/secret.txt", fo)?;
+        zw.write_all(b"hidden")?;
+        zw.finish()?;
+        for name in ["legacy", "base"] {
+            create_dir_all(d.path().join(name).join("assets/test"))?;
+            write(d.path().join(name).join("assets/test/a.txt"), name)?;
+        }
+        // The encrypted input is listed first but applied last, and the input before it is
+        // left out by for_formats, so its position in the pack list is 1, not 0
+        write(
+            d.path().join("merge.json"),
+            r#"{
+                "inputs": [
+                    { "path": "secret.zip", "force_top": true, "password": "hunter2" },
+                    { "path": "legacy", "for_formats": [1, 5] },
+                    "base"
+                ],
+                "target_format": 34,
+                "out": "out.zip"
+            }"#,
+        )?;
+        let settings = read_config_file(&d.path().join("merge.json"))?.into_settings()?;
+        assert_eq!(settings.inputs.len(), 2);
+        assert_eq!(
+            settings.options.zip_passwords.get(&1).map(|s| s.as_str()),
+            Some("hunter2")
+        );
+
+        let out = merge_packs_to_bytes_with_options(&settings.inputs, &settings.options)?;
+        assert_eq!(zip_entries(&out)?["assets/test/secret.txt"], b"hidden");
+        assert_eq!(scan_formats(&settings.inputs)[1].pack_format, None);
+        let scanned = scan_formats_with_options(&settings.inputs, &settings.options);
+        assert_eq!(scanned[1].pack_format, Some(22));
+        let merged = compute_merged_mcmeta(&settings.inputs, &settings.options)?;
+        assert_eq!(merged["pack"]["pack_format"], 22);
+
+        // read_mcmeta_with_options takes the password of a single input as entry 0
+        let secret = PackInput::ZipFile(d.path().join("secret.zip"));
+        assert!(read_mcmeta(&secret).is_err());
+        let opts = MergeOptions {
+            zip_passwords: HashMap::from([(0, "hunter2".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            read_mcmeta_with_options(&secret, &opts)?.as_deref(),
+            Some(r#"{"pack":{"pack_format":22}}"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pack_png_dimension_check() -> anyhow::Result<()> {
+        let mut png = Vec::new();
+        image::RgbaImage::new(128, 96)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+        let err = check_pack_png_dimensions(&png).unwrap_err();
+        assert!(err.contains("not square"), "{}", err);
+
+        assert_eq!(
+            check_pack_png_dimensions(&default_pack_png_bytes()),
+            Ok((64, 64))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pack_png_dimension_check_only_warns_during_merge() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(&pack)?;
+        image::RgbaImage::new(128, 96).save(pack.join("pack.png"))?;
+        let icon = std::fs::read(pack.join("pack.png"))?;
+
+        let opts = MergeOptions {
+            validate_png_dimensions: true,
+            prefer_input_metadata: true,
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Dir(pack)],
+            &opts,
+        )?)?;
+        // The non-square icon is reported but still carried unchanged
+        assert_eq!(out["pack.png"], icon);
+        Ok(())
+    }
+
+    #[test]
+    fn resize_input_pack_png() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(&pack)?;
+        image::RgbaImage::new(256, 200).save(pack.join("pack.png"))?;
+
+        let opts = MergeOptions {
+            resize_png_to: Some(128),
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack)], &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let mut png = Vec::new();
+        archive.by_name("pack.png")?.read_to_end(&mut png)?;
+        assert_eq!(check_pack_png_dimensions(&png), Ok((128, 128)));
+        Ok(())
+    }
+
+    #[test]
+    fn config_paths_resolve_against_config_dir() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let cfg_dir = d.path().join("packs");
+        create_dir_all(cfg_dir.join("base"))?;
+        let abs = d.path().join("abs.zip");
+        let cfg_path = cfg_dir.join("merge.json");
+        write(
+            &cfg_path,
+            serde_json::json!({
+                "inputs": ["./base", abs.to_string_lossy(), "https://example.com/p.zip"],
+                "out": "merged.zip"
+            })
+            .to_string(),
+        )?;
+
+        // Relative inputs are joined onto the config's directory (the CLI test
+        // `config_paths_resolve_from_any_working_directory` runs from elsewhere)
+        let cfg = read_config_file(&cfg_path)?;
+        let inputs = cfg.inputs.unwrap();
+        assert!(Path::new(inputs[0].path()).is_dir());
+        assert!(matches!(
+            PackInput::from(inputs[0].path().to_string()),
+            PackInput::Dir(_)
+        ));
+        assert_eq!(Path::new(inputs[1].path()), abs.as_path());
+        assert_eq!(inputs[2].path(), "https://example.com/p.zip");
+        assert_eq!(
+            Path::new(&cfg.out.unwrap()),
+            cfg_dir.join("merged.zip").as_path()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn later_config_overrides_earlier() -> anyhow::Result<()> {
+        let common: Config = serde_json::from_str(
+            r#"{ "inputs": ["https://example.com/base.zip"], "overwrite": "last", "atomic": false }"#,
+        )?;
+        let project: Config = serde_json::from_str(
+            r#"{ "inputs": ["https://example.com/extra.zip"], "overwrite": "first" }"#,
+        )?;
+        let cfg = common.merge(project);
+        assert_eq!(
+            cfg.inputs.unwrap(),
+            vec![
+                ConfigInput::from("https://example.com/base.zip"),
+                ConfigInput::from("https://example.com/extra.zip")
+            ]
+        );
+        assert_eq!(cfg.overwrite.as_deref(), Some("first"));
+        assert_eq!(cfg.atomic, Some(false));
+        Ok(())
+    }
+
+    #[test]
+    fn namespace_filter_drops_unlisted() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft/textures"))?;
+        create_dir_all(pack.join("assets/othermod/textures"))?;
+        write(pack.join("assets/minecraft/textures/a.png"), b"a")?;
+        write(pack.join("assets/othermod/textures/b.png"), b"b")?;
+        write(pack.join("credits.txt"), b"me")?;
+
+        let opts = MergeOptions {
+            namespaces: Some(vec!["minecraft".to_string()]),
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack)], &opts)?;
+        let archive = ZipArchive::new(Cursor::new(out))?;
+        assert!(archive
+            .index_for_name("assets/minecraft/textures/a.png")
+            .is_some());
+        assert!(archive
+            .index_for_name("assets/othermod/textures/b.png")
+            .is_none());
+        assert!(archive.index_for_name("credits.txt").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn merge_tags_unions_values() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let tag = "data/test/tags/function/load.json";
+        let mut packs = Vec::new();
+        for (name, json) in [
+            ("a", r#"{"values":["test:a","test:shared"]}"#),
+            ("b", r#"{"values":["test:shared","test:b"]}"#),
+        ] {
+            let dir = d.path().join(name);
+            create_dir_all(dir.join("data/test/tags/function"))?;
+            write(dir.join(tag), json)?;
+            packs.push(PackInput::Dir(dir));
+        }
+
+        let opts = MergeOptions {
+            merge_tags: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let merged: serde_json::Value = serde_json::from_reader(archive.by_name(tag)?)?;
+        assert_eq!(
+            merged["values"],
+            serde_json::json!(["test:a", "test:shared", "test:b"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bom_prefixed_mcmeta_format_detected() -> anyhow::Result<()> {
+        let mut mcmeta = vec![0xEF, 0xBB, 0xBF];
+        mcmeta.extend_from_slice(br#"{"pack":{"pack_format":42,"description":""}}"#);
+        assert_eq!(parse_peeked_mcmeta(&mcmeta).map(|(pf, _, _)| pf), Some(42));
+        assert!(decode_json_text(&[0xFF, 0xFE, b'{', 0])
+            .unwrap_err()
+            .contains("UTF-16"));
+
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(&pack)?;
+        write(pack.join("pack.mcmeta"), &mcmeta)?;
+        let opts = MergeOptions {
+            supported_formats_policy: SupportedFormatsPolicy::LowestToHighest,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack.clone())], &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let meta: serde_json::Value = serde_json::from_reader(archive.by_name("pack.mcmeta")?)?;
+        assert_eq!(meta["pack"]["pack_format"], 42);
+
+        // UTF-16 can't be read by the game, so it fails the merge instead of dropping the format
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(r#"{"pack":{"pack_format":42}}"#.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        write(pack.join("pack.mcmeta"), utf16)?;
+        let packs = [PackInput::Dir(pack)];
+        for err in [
+            merge_packs_to_bytes_with_options(&packs, &opts).unwrap_err(),
+            compute_merged_mcmeta(&packs, &opts).unwrap_err(),
+        ] {
+            assert!(matches!(err, MergeError::InvalidInput(m) if m.contains("UTF-16")));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn scan_formats_over_mixed_inputs() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let dir = d.path().join("dir_pack");
+        create_dir_all(&dir)?;
+        write(
+            dir.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34,"supported_formats":{"min_inclusive":34,"max_inclusive":46}},
+                "overlays":{"entries":[{"formats":[40,46],"directory":"ov_a"},{"formats":46,"directory":"ov_b"}]}}"#,
+        )?;
+
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            zw.start_file(
+                "pack.mcmeta",
+                zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default(),
+            )?;
+            zw.write_all(br#"{"pack":{"pack_format":15}}"#)?;
+            zw.finish()?;
+        }
+        let empty = d.path().join("empty");
+        create_dir_all(&empty)?;
+
+        let formats = scan_formats(&[
+            PackInput::Dir(dir.clone()),
+            PackInput::ZipBytes(cursor.into_inner()),
+            PackInput::Dir(empty.clone()),
+        ]);
+        assert_eq!(
+            formats,
+            vec![
+                InputFormat {
+                    input: dir.display().to_string(),
+                    pack_format: Some(34),
+                    max_format: Some(46),
+                    overlay_count: 2,
+                },
+                InputFormat {
+                    input: "<in-memory>".to_string(),
+                    pack_format: Some(15),
+                    max_format: None,
+                    overlay_count: 0,
+                },
+                InputFormat {
+                    input: empty.display().to_string(),
+                    pack_format: None,
+                    max_format: None,
+                    overlay_count: 0,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn classify_resource_pack_and_random_zip() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let rp = d.path().join("rp");
+        create_dir_all(rp.join("assets/minecraft/textures"))?;
+        write(rp.join("pack.mcmeta"), r#"{"pack":{"pack_format":34}}"#)?;
+        assert_eq!(
+            classify_input(&PackInput::Dir(rp))?,
+            PackClassification::ResourcePack
+        );
+
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("notes.txt", o.clone())?;
+            zw.write_all(b"hello")?;
+            zw.start_file("photos/cat.jpg", o)?;
+            zw.write_all(b"meow")?;
+            zw.finish()?;
+        }
+        assert_eq!(
+            classify_input(&PackInput::ZipBytes(cursor.into_inner()))?,
+            PackClassification::NotAPack
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prefer_input_metadata_keeps_provided_files() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let base = d.path().join("base");
+        let docs = d.path().join("docs");
+        create_dir_all(&base)?;
+        create_dir_all(&docs)?;
+        write(base.join("pack.mcmeta"), r#"{"pack":{"pack_format":34}}"#)?;
+        write(docs.join("README.md"), "# Hand-written notes")?;
+        let packs = [PackInput::Dir(base), PackInput::Dir(docs)];
+
+        let opts = MergeOptions {
+            prefer_input_metadata: true,
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(out["README.md"], b"# Hand-written notes");
+        assert_eq!(out["pack.mcmeta"], br#"{"pack":{"pack_format":34}}"#);
+        // No input provided pack.png, so the default icon is synthesized
+        assert_eq!(out["pack.png"], default_pack_png_bytes());
+
+        // Without the option pack.mcmeta is synthesized but the README is still carried
+        let out = zip_entries(&merge_packs_to_bytes(&packs)?)?;
+        assert_eq!(out["README.md"], b"# Hand-written notes");
+        assert_ne!(out["pack.mcmeta"], br#"{"pack":{"pack_format":34}}"#);
+
+        // Asking for a generated README replaces the input's copy
+        let opts = MergeOptions {
+            generate_readme: true,
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_ne!(out["README.md"], b"# Hand-written notes");
+        Ok(())
+    }
+
+    #[test]
+    fn compute_merged_mcmeta_combines_formats_and_overlays() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let old = d.path().join("old");
+        let new = d.path().join("new");
+        create_dir_all(&old)?;
+        create_dir_all(&new)?;
+        write(
+            old.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":15},
+                "overlays":{"entries":[{"formats":[15,18],"directory":"ov_old"}]}}"#,
+        )?;
+        write(
+            new.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34,"supported_formats":{"min_inclusive":34,"max_inclusive":46}},
+                "overlays":{"entries":[{"formats":46,"directory":"ov_new"}]}}"#,
+        )?;
+        // Asset files are never read, so an unreadable one doesn't matter
+        create_dir_all(new.join("assets/test"))?;
+
+        let meta = compute_merged_mcmeta(
+            &[PackInput::Dir(old), PackInput::Dir(new)],
+            &MergeOptions {
+                supported_formats_policy: SupportedFormatsPolicy::LowestToHighest,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(meta["pack"]["pack_format"], 34);
+        assert_eq!(
+            meta["pack"]["supported_formats"],
+            serde_json::json!([15, 34])
+        );
+        assert_eq!(meta["pack"]["max_format"], 46);
+        let dirs: Vec<&str> = meta["overlays"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["directory"].as_str().unwrap())
+            .collect();
+        // In declaration order: inputs first to last
+        assert_eq!(dirs, ["ov_old", "ov_new"]);
+        Ok(())
+    }
+
+    #[test]
+    fn prune_empty_dirs_after_extraction() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/a.txt"), "a")?;
+        let out = d.path().join("out");
+        // Leftovers from an earlier run
+        create_dir_all(out.join("assets/old/textures/block"))?;
+        create_dir_all(out.join("empty"))?;
+
+        let opts = MergeOptions {
+            prune_empty_dirs: true,
+            write_gitattributes: true,
+            ..Default::default()
+        };
+        merge_packs_to_dir(&[PackInput::Dir(pack)], &out, &opts)?;
+
+        for entry in WalkDir::new(&out) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                assert!(
+                    std::fs::read_dir(entry.path())?.next().is_some(),
+                    "empty directory left: {}",
+                    entry.path().display()
+                );
+            }
+        }
+        assert!(out.join("assets/test/a.txt").is_file());
+        assert!(std::fs::read_to_string(out.join(".gitattributes"))?.contains("*.png binary"));
+        Ok(())
+    }
+
+    #[test]
+    fn merge_onto_patches_base_zip() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let base_pack = d.path().join("base");
+        create_dir_all(base_pack.join("assets/test"))?;
+        write(
+            base_pack.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34}}"#,
+        )?;
+        write(base_pack.join("assets/test/a.txt"), "base a")?;
+        write(base_pack.join("assets/test/b.txt"), "base b")?;
+        let base_zip = merge_packs_to_bytes(&[PackInput::Dir(base_pack)])?;
+
+        let patch = d.path().join("patch");
+        create_dir_all(patch.join("assets/test"))?;
+        write(patch.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        write(patch.join("assets/test/b.txt"), "patched b")?;
+
+        let opts = MergeOptions {
+            description_override: Some("patched".to_string()),
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_onto(&base_zip, &[PackInput::Dir(patch)], &opts)?)?;
+        assert_eq!(out["assets/test/a.txt"], b"base a");
+        assert_eq!(out["assets/test/b.txt"], b"patched b");
+        // pack.mcmeta is recomputed: the base's format still wins as the highest
+        let meta: serde_json::Value = serde_json::from_slice(&out["pack.mcmeta"])?;
+        assert_eq!(meta["pack"]["pack_format"], 34);
+        assert_eq!(meta["pack"]["description"], "patched");
+        Ok(())
+    }
+
+    #[test]
+    fn conflict_log_lists_overridden_files() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        for dir in [&a, &b] {
+            create_dir_all(dir.join("assets/test"))?;
+            write(dir.join("assets/test/shared.txt"), "x")?;
+        }
+        write(a.join("assets/test/only_a.txt"), "a")?;
+
+        let opts = MergeOptions {
+            include_conflict_log: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(
+            &[PackInput::Dir(a), PackInput::Dir(b.clone())],
+            &opts,
+        )?;
+        let readme = String::from_utf8(zip_entries(&out)?["README.md"].clone())?;
+        assert!(readme.contains("Overridden files"));
+        assert!(readme.contains(&format!("- assets/test/shared.txt -> Dir: {}", b.display())));
+        assert!(!readme.contains("only_a.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_entries_in_zip_input() -> anyhow::Result<()> {
+        let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        // Names that only differ in separators normalize to the same entry
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            zw.start_file("assets/test/a.txt", o.clone())?;
+            zw.write_all(b"first")?;
+            zw.start_file("assets\\test\\a.txt", o.clone())?;
+            zw.write_all(b"second")?;
+            zw.finish()?;
+        }
+        let normalized = cursor.into_inner();
+
+        // Byte-identical names: write two distinct names of equal length, then rename one
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            zw.start_file("a.txt", o.clone())?;
+            zw.write_all(b"first")?;
+            zw.start_file("b.txt", o)?;
+            zw.write_all(b"second")?;
+            zw.finish()?;
+        }
+        let mut identical = cursor.into_inner();
+        for i in 0..identical.len() - 5 {
+            if &identical[i..i + 5] == b"b.txt" {
+                identical[i] = b'a';
+            }
+        }
+
+        let with = |policy| MergeOptions {
+            on_duplicate_in_archive: policy,
+            ..Default::default()
+        };
+        let packs = [PackInput::ZipBytes(normalized)];
+        let first =
+            merge_packs_to_bytes_with_options(&packs, &with(DuplicateEntryPolicy::KeepFirst))?;
+        assert_eq!(zip_entries(&first)?["assets/test/a.txt"], b"first");
+        let last =
+            merge_packs_to_bytes_with_options(&packs, &with(DuplicateEntryPolicy::KeepLast))?;
+        assert_eq!(zip_entries(&last)?["assets/test/a.txt"], b"second");
+        let err = merge_packs_to_bytes_with_options(&packs, &with(DuplicateEntryPolicy::Error));
+        assert!(matches!(err, Err(MergeError::InvalidInput(m)) if m.contains("assets/test/a.txt")));
+
+        let packs = [PackInput::ZipBytes(identical)];
+        let err = merge_packs_to_bytes_with_options(&packs, &with(DuplicateEntryPolicy::Error));
+        assert!(matches!(err, Err(MergeError::InvalidInput(m)) if m.contains("share a name")));
+        Ok(())
+    }
+
+    #[test]
+    fn split_output_parts_cover_all_files() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        // Poorly compressible contents so part sizes track the file sizes
+        let mut state = 0x2545_f491_u32;
+        for i in 0..8 {
+            let body: Vec<u8> = (0..3000)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect();
+            write(pack.join(format!("assets/test/f{}.bin", i)), body)?;
+        }
+        let packs = [PackInput::Dir(pack)];
+        let out = d.path().join("merged.zip");
+        let cap = 16_000;
+        let opts = MergeOptions {
+            split_output_bytes: Some(cap),
+            ..Default::default()
+        };
+        // Leftovers of earlier runs: an unsplit output and more parts than this run needs
+        write(&out, "old")?;
+        for n in 1..=12 {
+            write(d.path().join(format!("merged.part{}.zip", n)), "old")?;
+        }
+        let report = merge_with_controls(&packs, &out, &opts, |_| {}, &AtomicBool::new(false))?;
+        assert!(!out.exists());
+
+        let full = zip_entries(&merge_packs_to_bytes(&packs)?)?;
+        let mut union = std::collections::BTreeMap::new();
+        let mut n = 1;
+        while d.path().join(format!("merged.part{}.zip", n)).exists() {
+            let part = std::fs::read(d.path().join(format!("merged.part{}.zip", n)))?;
+            assert!(part.len() as u64 <= cap);
+            let entries = zip_entries(&part)?;
+            assert_eq!(entries["pack.mcmeta"], full["pack.mcmeta"]);
+            union.extend(entries);
+            n += 1;
+        }
+        assert!(n > 2, "expected multiple parts");
+        assert_eq!(union, full);
+        let parts_size: u64 = (1..n)
+            .map(|i| std::fs::metadata(d.path().join(format!("merged.part{}.zip", i))))
+            .map(|m| m.map(|m| m.len()))
+            .sum::<std::io::Result<u64>>()?;
+        assert_eq!(report.bytes_written, parts_size);
+
+        // A later run that fits in one file removes the parts
+        let opts = MergeOptions {
+            split_output_bytes: Some(1 << 20),
+            ..opts
+        };
+        merge_packs_to_file_with_options(&packs, &out, &opts)?;
+        assert!(out.is_file());
+        assert!(!d.path().join("merged.part1.zip").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn html_download_error_includes_preview() {
+        let page = b"<!DOCTYPE html>\n<html><head>\n<title>\n  Sign in - Example\n</title></head>\n<body>Please log in</body></html>";
+        let err = check_zip_download(
+            "https://example.com/pack.zip",
+            page.to_vec(),
+            Some("text/html"),
+        )
+        .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("content-type: text/html"), "{}", msg);
+        assert!(msg.contains("\"Sign in - Example\""), "{}", msg);
+
+        // Without a title the start of the body is used, truncated to 200 characters
+        let err = check_zip_download("https://example.com/x", "x".repeat(500).into_bytes(), None)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&format!("\"{}...\"", "x".repeat(200))));
+        assert!(check_zip_download("https://example.com/x", b"PK\x03\x04".to_vec(), None).is_ok());
+    }
+
+    #[test]
+    fn metadata_from_first_input_keeps_base_description() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let base = d.path().join("base");
+        let extra = d.path().join("extra");
+        create_dir_all(base.join("assets/test"))?;
+        create_dir_all(extra.join("assets/test"))?;
+        let base_meta = r#"{"pack":{"pack_format":34,"description":"Official base"}}"#;
+        write(base.join("pack.mcmeta"), base_meta)?;
+        write(
+            extra.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":46,"description":"Addon"}}"#,
+        )?;
+        write(extra.join("README.md"), "addon notes")?;
+        write(extra.join("assets/test/a.txt"), "addon")?;
+
+        let opts = MergeOptions {
+            metadata_from: MetadataSource::FirstInput,
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Dir(base), PackInput::Dir(extra)],
+            &opts,
+        )?)?;
+        assert_eq!(out["pack.mcmeta"], base_meta.as_bytes());
+        assert_eq!(out["assets/test/a.txt"], b"addon");
+        // The base has no README, so one is synthesized rather than taken from the addon
+        assert!(String::from_utf8(out["README.md"].clone())?.contains("resource_merger"));
+        Ok(())
+    }
+
+    #[test]
+    fn lint_paths_flags_colon() -> anyhow::Result<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("assets/test/textures/ok.png", o.clone())?;
+            zw.write_all(b"png")?;
+            zw.start_file("assets/test/textures/bad:name.png", o)?;
+            zw.write_all(b"png")?;
+            zw.finish()?;
+        }
+        let lints = lint_paths(
+            &[PackInput::ZipBytes(cursor.into_inner())],
+            &MergeOptions::default(),
+        )?;
+        assert_eq!(
+            lints,
+            vec![PathLint {
+                path: "assets/test/textures/bad:name.png".to_string(),
+                reason: PathLintReason::IllegalCharacter(':'),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn transform_uppercases_matching_text_files() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test/texts"))?;
+        write(pack.join("assets/test/texts/credits.txt"), "made by steve")?;
+        write(pack.join("assets/test/notes.json"), "made by alex")?;
+
+        let opts = MergeOptions {
+            transforms: vec![FileTransform::new("assets/**/*.txt", |_, bytes| {
+                Ok(bytes.to_ascii_uppercase())
+            })],
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Dir(pack)],
+            &opts,
+        )?)?;
+        assert_eq!(out["assets/test/texts/credits.txt"], b"MADE BY STEVE");
+        assert_eq!(out["assets/test/notes.json"], b"made by alex");
+
+        assert!(glob_match("**/*.png", "pack.png"));
+        assert!(!glob_match("assets/*.txt", "assets/test/a.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn base_url_with_local_overrides() -> anyhow::Result<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut zw = ZipWriter::new(&mut cursor);
+            let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("assets/test/a.txt", o.clone())?;
+            zw.write_all(b"base a")?;
+            zw.start_file("assets/test/b.txt", o)?;
+            zw.write_all(b"base b")?;
+            zw.finish()?;
+        }
+        let body = cursor.into_inner();
+
+        // Serve the base zip once from a local mock server
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/base.zip", listener.local_addr()?);
+        let server = std::thread::spawn(move || -> std::io::Result<()> {
+            let (mut stream, _) = listener.accept()?;
+            let mut req = [0u8; 1024];
+            let _ = stream.read(&mut req)?;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )?;
+            stream.write_all(&body)
+        });
+
+        let d = tempdir()?;
+        let overrides = d.path().join("overrides");
+        create_dir_all(overrides.join("assets/test"))?;
+        write(overrides.join("assets/test/b.txt"), "local b")?;
+        let out = d.path().join("merged.zip");
+        merge_base_url_with_dir(&url, &overrides, &out, &MergeOptions::default())?;
+        server.join().unwrap()?;
+
+        let entries = zip_entries(&std::fs::read(&out)?)?;
+        assert_eq!(entries["assets/test/a.txt"], b"base a");
+        assert_eq!(entries["assets/test/b.txt"], b"local b");
+        Ok(())
+    }
+
+    #[cfg(feature = "dir-listing")]
+    #[test]
+    fn url_listing_inputs_merge_linked_zips_in_order() -> anyhow::Result<()> {
+        let zip_with = |body: &[u8]| -> anyhow::Result<Vec<u8>> {
+            let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+            let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("assets/test/a.txt", o)?;
+            zw.write_all(body)?;
+            Ok(zw.finish()?.into_inner())
+        };
+        let (first, second) = (zip_with(b"first")?, zip_with(b"second")?);
+        let listing = "<html><body><a href=\"../\">Parent</a>\
+            <a href=\"2-second.zip\">2-second.zip</a> <a href='readme.txt'>readme</a>\
+            <a href=\"/mirror/1-first.zip\">1-first.zip</a></body></html>";
+
+        // Mock mirror: the listing, then each linked zip, one request per connection
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/mirror/", listener.local_addr()?);
+        let server = std::thread::spawn(move || -> std::io::Result<Vec<String>> {
+            let mut requested = Vec::new();
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept()?;
+                let mut req = [0u8; 1024];
+                let n = stream.read(&mut req)?;
+                let path = String::from_utf8_lossy(&req[..n])
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let (ct, body) = match path.as_str() {
+                    "/mirror/" => ("text/html", listing.as_bytes().to_vec()),
+                    "/mirror/2-second.zip" => ("application/zip", second.clone()),
+                    _ => ("application/zip", first.clone()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    ct,
+                    body.len()
+                )?;
+                stream.write_all(&body)?;
+                requested.push(path);
+            }
+            Ok(requested)
+        });
+
+        let opts = MergeOptions {
+            expand_url_listings: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Url(url)], &opts)?;
+        let requested = server.join().unwrap()?;
+        assert_eq!(
+            requested,
+            ["/mirror/", "/mirror/2-second.zip", "/mirror/1-first.zip"]
+        );
+        // Link order, not name order: the last linked zip wins
+        assert_eq!(zip_entries(&out)?["assets/test/a.txt"], b"first");
+        Ok(())
+    }
+
+    #[test]
+    fn provenance_extra_field_records_source_input() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        create_dir_all(a.join("assets/test"))?;
+        create_dir_all(b.join("assets/test"))?;
+        write(a.join("assets/test/only_a.txt"), "a")?;
+        write(a.join("assets/test/shared.txt"), "a")?;
+        write(b.join("assets/test/shared.txt"), "b")?;
+        write(b.join("assets/test/replaced.txt"), "b")?;
+        let opts = MergeOptions {
+            embed_provenance_extra: true,
+            extra_files: vec![("assets/test/replaced.txt".to_string(), b"x".to_vec())],
+            ..Default::default()
+        };
+        let out =
+            merge_packs_to_bytes_with_options(&[PackInput::Dir(a), PackInput::Dir(b)], &opts)?;
+
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let mut source_of = |name: &str| -> anyhow::Result<Option<u32>> {
+            let entry = archive.by_name(name)?;
+            let mut extra = entry.extra_data().unwrap_or_default();
+            while extra.len() >= 4 {
+                let id = u16::from_le_bytes([extra[0], extra[1]]);
+                let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+                if id == PROVENANCE_EXTRA_FIELD_ID {
+                    return Ok(Some(u32::from_le_bytes(extra[4..4 + len].try_into()?)));
+                }
+                extra = &extra[4 + len..];
+            }
+            Ok(None)
+        };
+        assert_eq!(source_of("assets/test/only_a.txt")?, Some(0));
+        assert_eq!(source_of("assets/test/shared.txt")?, Some(1));
+        assert_eq!(source_of("pack.mcmeta")?, None);
+        assert_eq!(source_of("assets/test/replaced.txt")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn preserve_input_compression_keeps_entry_methods() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file(
+            "assets/test/stored.txt",
+            fo.clone()
+                .compression_method(zip::CompressionMethod::Stored),
+        )?;
+        zw.write_all(&b"stored ".repeat(100))?;
+        zw.start_file(
+            "assets/test/deflated.txt",
+            fo.compression_method(zip::CompressionMethod::Deflated),
+        )?;
+        zw.write_all(&b"deflated ".repeat(100))?;
+        let input = zw.finish()?.into_inner();
+        let d = tempdir()?;
+        write(d.path().join("later.txt"), "x")?;
+        let packs = [PackInput::ZipBytes(input), PackInput::Dir(d.path().into())];
+
+        let opts = MergeOptions {
+            preserve_input_compression: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out.clone()))?;
+        let method = |a: &mut ZipArchive<_>, n: &str| a.by_name(n).map(|e| e.compression());
+        assert_eq!(
+            method(&mut archive, "assets/test/stored.txt")?,
+            zip::CompressionMethod::Stored
+        );
+        assert_eq!(
+            method(&mut archive, "assets/test/deflated.txt")?,
+            zip::CompressionMethod::Deflated
+        );
+        let entries = zip_entries(&out)?;
+        assert_eq!(entries["assets/test/stored.txt"], b"stored ".repeat(100));
+        assert_eq!(
+            entries["assets/test/deflated.txt"],
+            b"deflated ".repeat(100)
+        );
+        assert_eq!(entries["later.txt"], b"x");
+        Ok(())
+    }
+
+    #[test]
+    fn require_valid_pack_rejects_non_pack_zip() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("my_pack/pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":34}}"#)?;
+        zw.start_file("my_pack/assets/test/a.txt", fo)?;
+        zw.write_all(b"a")?;
+        let wrong_folder = zw.finish()?.into_inner();
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("assets/test/b.txt"), "b")?;
+        let wrong_path = d.path().join("wrong.zip");
+        write(&wrong_path, &wrong_folder)?;
+        let packs = [
+            PackInput::Dir(d.path().into()),
+            PackInput::ZipFile(wrong_path.clone()),
+        ];
+        let opts = MergeOptions {
+            require_valid_pack: true,
+            ..Default::default()
+        };
+
+        match merge_packs_to_bytes_with_options(&packs, &opts) {
+            Err(MergeError::InvalidInput(msg)) => {
+                let expected = format!("{}: not a resource or data pack", wrong_path.display());
+                assert!(msg.contains(&expected), "{}", msg)
+            }
+            other => panic!("expected InvalidInput, got {:?}", other.map(|_| ())),
+        }
+        // Off by default: the stray folder is merged as-is
+        assert!(merge_packs_to_bytes(&packs).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn remeta_rewrites_mcmeta_and_keeps_asset_bytes() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(
+            d.path().join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34}}"#,
+        )?;
+        write(d.path().join("assets/test/a.txt"), "a".repeat(500))?;
+        write(d.path().join("assets/test/b.bin"), [0u8, 1, 2, 3])?;
+        let cached = merge_packs_to_bytes(&[PackInput::Dir(d.path().into())])?;
+
+        let opts = MergeOptions {
+            pack_format_override: Some(46),
+            description_override: Some("Bumped".to_string()),
+            ..Default::default()
+        };
+        let bumped = remeta(&cached, &opts)?;
+
+        // Asset entries are copied as stored: same compressed bytes and CRC
+        let mut before = ZipArchive::new(Cursor::new(&cached))?;
+        let mut after = ZipArchive::new(Cursor::new(&bumped))?;
+        for name in ["assets/test/a.txt", "assets/test/b.bin"] {
+            let raw = |a: &mut ZipArchive<Cursor<&Vec<u8>>>| -> anyhow::Result<(u32, Vec<u8>)> {
+                let index = a.index_for_name(name).expect("entry exists");
+                let mut entry = a.by_index_raw(index)?;
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                Ok((entry.crc32(), bytes))
+            };
+            assert_eq!(raw(&mut before)?, raw(&mut after)?);
+        }
+        let mcmeta: serde_json::Value =
+            serde_json::from_slice(&zip_entries(&bumped)?["pack.mcmeta"])?;
+        assert_eq!(mcmeta["pack"]["pack_format"], 46);
+        assert_eq!(mcmeta["pack"]["description"], "Bumped");
+        Ok(())
+    }
+
+    #[test]
+    fn language_blocks_are_unioned_by_code() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        create_dir_all(&a)?;
+        create_dir_all(&b)?;
+        write(
+            a.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34},"language":{"tlh_aa":{"name":"Klingon","region":"Kronos","bidirectional":false}}}"#,
+        )?;
+        write(
+            b.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34},"language":{"qya_aa":{"name":"Quenya","region":"Valinor","bidirectional":false}}}"#,
+        )?;
+        let packs = [PackInput::Dir(a), PackInput::Dir(b)];
+        let opts = MergeOptions {
+            merge_language_block: true,
+            ..Default::default()
+        };
+
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let mcmeta: serde_json::Value = serde_json::from_slice(&zip_entries(&out)?["pack.mcmeta"])?;
+        assert_eq!(mcmeta["language"]["tlh_aa"]["name"], "Klingon");
+        assert_eq!(mcmeta["language"]["qya_aa"]["name"], "Quenya");
+        assert_eq!(
+            compute_merged_mcmeta(&packs, &opts)?["language"],
+            mcmeta["language"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extra_files_are_injected_after_inputs() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("credits.txt"), "from input")?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        let packs = [PackInput::Dir(d.path().into())];
+        let mut opts = MergeOptions {
+            extra_files: vec![
+                ("credits.txt".to_string(), b"injected".to_vec()),
+                (
+                    "build\\info.json".to_string(),
+                    br#"{"commit":"abc123"}"#.to_vec(),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(entries["build/info.json"], br#"{"commit":"abc123"}"#);
+        assert_eq!(entries["credits.txt"], b"injected");
+        assert_eq!(entries["assets/test/a.txt"], b"a");
+
+        opts.overwrite = OverwritePolicy::FirstWins;
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(entries["credits.txt"], b"from input");
+        Ok(())
+    }
+
+    #[test]
+    fn remove_drops_paths_from_the_merged_result() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        create_dir_all(d.path().join("debug/logs"))?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("debug/logs/run.txt"), "log")?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        let opts = MergeOptions {
+            extra_files: vec![("debug/injected.txt".to_string(), b"x".to_vec())],
+            remove: vec!["debug/**".to_string(), "pack.mcmeta".to_string()],
+            ..Default::default()
+        };
+
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(d.path().into())], &opts)?;
+        let entries = zip_entries(&out)?;
+        assert!(!entries.keys().any(|k| k.starts_with("debug/")));
+        assert_eq!(entries["assets/test/a.txt"], b"a");
+        assert!(entries.contains_key("pack.mcmeta"));
+        Ok(())
+    }
+
+    #[test]
+    fn logical_hash_ignores_order_of_non_conflicting_inputs() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        create_dir_all(a.join("assets/test"))?;
+        create_dir_all(b.join("assets/test"))?;
+        write(a.join("assets/test/a.txt"), "a")?;
+        write(b.join("assets/test/b.txt"), "b")?;
+        let opts = MergeOptions::default();
+
+        let forward = logical_hash(
+            &[PackInput::Dir(a.clone()), PackInput::Dir(b.clone())],
+            &opts,
+        )?;
+        let reversed = logical_hash(
+            &[PackInput::Dir(b.clone()), PackInput::Dir(a.clone())],
+            &opts,
+        )?;
+        assert_eq!(forward, reversed);
+        assert_eq!(forward.len(), 64);
+
+        write(b.join("assets/test/b.txt"), "changed")?;
+        let changed = logical_hash(&[PackInput::Dir(a), PackInput::Dir(b)], &opts)?;
+        assert_ne!(forward, changed);
+        Ok(())
+    }
+
+    #[test]
+    fn config_subdir_reads_only_that_folder() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let repo = d.path().join("repo");
+        let pack = repo.join("packs/mypack");
+        create_dir_all(pack.join("assets/test"))?;
+        create_dir_all(repo.join("src"))?;
+        write(pack.join("assets/test/a.txt"), "a")?;
+        write(repo.join("src/main.rs"), "fn main() {}")?;
+        write(repo.join("Cargo.toml"), "[package]")?;
+        let config = d.path().join("merge.json");
+        write(
+            &config,
+            r#"{ "inputs": [{ "path": "repo", "subdir": "packs/mypack" }] }"#,
+        )?;
+
+        let cfg = read_config_file(&config)?;
+        let packs = cfg.pack_inputs(Vec::new())?;
+        let entries = zip_entries(&merge_packs_to_bytes(&packs)?)?;
+        let mut keys: Vec<&str> = entries.keys().map(|k| k.as_str()).collect();
+        keys.retain(|k| !SYNTHESIZED_ROOT_FILES.contains(k));
+        assert_eq!(keys, ["assets/test/a.txt"]);
+
+        // A subdir must stay inside a directory input
+        write(d.path().join("pack.zip"), "")?;
+        let outside = d.path().join("outside");
+        for input in [
+            serde_json::json!({ "path": "repo", "subdir": "../outside" }),
+            serde_json::json!({ "path": "repo", "subdir": outside }),
+            serde_json::json!({ "path": "pack.zip", "subdir": "packs/mypack" }),
+        ] {
+            write(
+                &config,
+                serde_json::json!({ "inputs": [input] }).to_string(),
+            )?;
+            let err = read_config_file(&config)?
+                .pack_inputs(Vec::new())
+                .unwrap_err();
+            assert!(matches!(err, MergeError::InvalidInput(_)), "{}", err);
+            assert!(
+                err.to_string().contains(d.path().to_str().unwrap()),
+                "{}",
+                err
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn merge_fonts_concatenates_providers() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let shared = r#"{"type":"space","advances":{" ":4}}"#;
+        let mut packs = Vec::new();
+        for (name, file) in [("a", "a_icons.png"), ("b", "b_icons.png")] {
+            let dir = d.path().join(name);
+            create_dir_all(dir.join("assets/minecraft/font"))?;
+            write(
+                dir.join("assets/minecraft/font/default.json"),
+                format!(
+                    r#"{{"providers":[{},{{"type":"bitmap","file":"minecraft:font/{}","ascent":7,"chars":["\ue000"]}}]}}"#,
+                    shared, file
+                ),
+            )?;
+            packs.push(PackInput::Dir(dir));
+        }
+        let opts = MergeOptions {
+            merge_fonts: true,
+            ..Default::default()
+        };
+
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let font: serde_json::Value =
+            serde_json::from_slice(&zip_entries(&out)?["assets/minecraft/font/default.json"])?;
+        let providers = font["providers"].as_array().unwrap();
+        assert_eq!(providers.len(), 3);
+        assert_eq!(providers[0]["type"], "space");
+        assert_eq!(providers[1]["file"], "minecraft:font/a_icons.png");
+        assert_eq!(providers[2]["file"], "minecraft:font/b_icons.png");
+        Ok(())
+    }
+
+    #[test]
+    fn smart_json_merge_combines_langs_and_sounds() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let mut packs = Vec::new();
+        for (name, key, event) in [("a", "a.key", "a.hit"), ("b", "b.key", "b.hit")] {
+            let dir = d.path().join(name);
+            create_dir_all(dir.join("assets/minecraft/lang"))?;
+            write(
+                dir.join("assets/minecraft/lang/en_us.json"),
+                format!(r#"{{"{}":"{}","shared":"{}"}}"#, key, name, name),
+            )?;
+            write(
+                dir.join("assets/minecraft/sounds.json"),
+                format!(r#"{{"{}":{{"sounds":["minecraft:{}"]}}}}"#, event, name),
+            )?;
+            packs.push(PackInput::Dir(dir));
+        }
+        let opts = MergeOptions {
+            smart_json_merge: true,
+            ..Default::default()
+        };
+
+        let files = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        let lang: serde_json::Value =
+            serde_json::from_slice(&files["assets/minecraft/lang/en_us.json"])?;
+        assert_eq!(lang["a.key"], "a");
+        assert_eq!(lang["b.key"], "b");
+        assert_eq!(lang["shared"], "b");
+        let sounds: serde_json::Value =
+            serde_json::from_slice(&files["assets/minecraft/sounds.json"])?;
+        assert!(sounds.get("a.hit").is_some() && sounds.get("b.hit").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn list_namespaces_maps_namespaces_to_inputs() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        create_dir_all(a.join("assets/minecraft/textures"))?;
+        write(a.join("assets/minecraft/textures/a.png"), "a")?;
+        create_dir_all(b.join("assets/minecraft/models"))?;
+        write(b.join("assets/minecraft/models/b.json"), "{}")?;
+        create_dir_all(b.join("data/mymod/tags"))?;
+        write(b.join("data/mymod/tags/x.json"), "{}")?;
+        write(b.join("data/mymod/tags/y.json"), "{}")?;
+
+        let found = list_namespaces(&[PackInput::Dir(a), PackInput::Dir(b)], &Default::default())?;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["minecraft"], vec![0, 1]);
+        assert_eq!(found["mymod"], vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn run_with_settings_also_emits_zip_for_dir_output() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let src = d.path().join("src");
+        create_dir_all(src.join("assets/minecraft/textures"))?;
+        write(src.join("assets/minecraft/textures/a.png"), "a")?;
+        write(
+            d.path().join("merge.json"),
+            r#"{ "out": "out", "dir": true, "also_emit_zip": "out.zip" }"#,
+        )?;
+        let cfg = read_config_file(&d.path().join("merge.json"))?;
+        let settings = Settings {
+            inputs: vec![PackInput::Dir(src)],
+            out: cfg.out.unwrap().into(),
+            dir: cfg.dir.unwrap(),
+            also_emit_zip: cfg.also_emit_zip.map(PathBuf::from),
+            options: MergeOptions::default(),
+        };
+
+        run_with_settings(&settings)?;
+        let tree = d.path().join("out");
+        assert_eq!(
+            std::fs::read(tree.join("assets/minecraft/textures/a.png"))?,
+            b"a"
+        );
+        assert!(tree.join("pack.mcmeta").is_file());
+        let files = zip_entries(&std::fs::read(d.path().join("out.zip"))?)?;
+        assert_eq!(files["assets/minecraft/textures/a.png"], b"a");
+        assert_eq!(
+            files["pack.mcmeta"],
+            std::fs::read(tree.join("pack.mcmeta"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn json_bundle_round_trips_to_file_map() -> anyhow::Result<()> {
+        use base64::Engine;
+
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        write(d.path().join("assets/test/b.bin"), [0u8, 159, 146, 150])?;
+        let packs = [PackInput::Dir(d.path().into())];
+        let opts = MergeOptions::default();
+
+        let bundle = merge_packs_to_json(&packs, &opts)?;
+        let mut files = std::collections::BTreeMap::new();
+        for (path, b64) in bundle["files"].as_object().unwrap() {
+            let bytes = base64::engine::general_purpose::STANDARD.decode(b64.as_str().unwrap())?;
+            files.insert(path.clone(), bytes);
+        }
+        let zipped = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(files, zipped);
+        assert_eq!(
+            bundle["mcmeta"],
+            serde_json::from_slice::<serde_json::Value>(&files["pack.mcmeta"])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn zip_comment_is_written() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        let opts = MergeOptions {
+            zip_comment: Some("build 1.2.3 (abc123)".to_string()),
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(d.path().into())], &opts)?;
+        let archive = ZipArchive::new(Cursor::new(out))?;
+        assert_eq!(archive.comment(), b"build 1.2.3 (abc123)");
+        Ok(())
+    }
+
+    #[test]
+    fn mcmeta_first_entry_order() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        create_dir_all(d.path().join("data/test"))?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        write(d.path().join("data/test/b.json"), "{}")?;
+        write(d.path().join("LICENSE"), "mit")?;
+        let opts = MergeOptions {
+            entry_order: EntryOrder::McmetaFirst,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(d.path().into())], &opts)?;
+        let archive = ZipArchive::new(Cursor::new(out))?;
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(
+            names,
+            [
+                "pack.mcmeta",
+                "pack.png",
+                "assets/test/a.txt",
+                "LICENSE",
+                "README.md",
+                "data/test/b.json"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_merge_matches_sequential() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let mut packs = Vec::new();
+        for i in 0..6 {
+            let dir = d.path().join(format!("pack{}", i));
+            create_dir_all(dir.join("assets/test"))?;
+            create_dir_all(dir.join("data/test/tags/item"))?;
+            write(
+                dir.join("pack.mcmeta"),
+                format!(r#"{{"pack":{{"pack_format":{}}}}}"#, 15 + i),
+            )?;
+            write(dir.join("assets/test/shared.txt"), format!("from {}", i))?;
+            write(dir.join(format!("assets/test/only{}.txt", i)), "x")?;
+            write(
+                dir.join("data/test/tags/item/t.json"),
+                format!(r#"{{"values":["test:v{}"]}}"#, i),
+            )?;
+            packs.push(PackInput::Dir(dir));
+        }
+        let opts = MergeOptions {
+            merge_tags: true,
+            fixed_timestamps: true,
+            ..Default::default()
+        };
+        let sequential = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        for _ in 0..10 {
+            assert_eq!(
+                merge_packs_to_bytes_parallel_deterministic(&packs, &opts)?,
+                sequential
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn url_failure_policy_skips_transient_but_not_permanent_errors() -> anyhow::Result<()> {
+        // One mock server answers 404, the other accepts but never answers in time
+        let missing = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let slow = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let missing_url = format!("http://{}/typo.zip", missing.local_addr()?);
+        let slow_url = format!("http://{}/slow.zip", slow.local_addr()?);
+        let server = std::thread::spawn(move || -> std::io::Result<()> {
+            let (mut stream, _) = missing.accept()?;
+            let mut req = [0u8; 1024];
+            let _ = stream.read(&mut req)?;
+            stream.write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            let (mut stream, _) = slow.accept()?;
+            let _ = stream.read(&mut req)?;
+            std::thread::sleep(std::time::Duration::from_millis(800));
+            Ok(())
+        });
+
+        let d = tempdir()?;
+        create_dir_all(d.path().join("assets/test"))?;
+        write(d.path().join("assets/test/a.txt"), "a")?;
+        let opts = MergeOptions {
+            url_failure_policy: UrlFailurePolicy::SkipTransientErrorPermanent,
+            per_input_timeout: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let dir = PackInput::Dir(d.path().into());
+
+        let res =
+            merge_packs_to_bytes_with_options(&[dir.clone(), PackInput::Url(missing_url)], &opts);
+        assert!(matches!(
+            res,
+            Err(MergeError::HttpStatus { status: 404, .. })
+        ));
+        let out = merge_packs_to_bytes_with_options(&[dir, PackInput::Url(slow_url)], &opts)?;
+        assert_eq!(zip_entries(&out)?["assets/test/a.txt"], b"a");
+        server.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn ranged_url_reads_fetch_only_needed_entries() -> anyhow::Result<()> {
+        use std::sync::atomic::AtomicUsize;
+
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":34}}"#)?;
+        zw.start_file("assets/test/a.txt", fo.clone())?;
+        zw.write_all(b"wanted")?;
+        zw.start_file(
+            "assets/big/blob.bin",
+            fo.compression_method(zip::CompressionMethod::Stored),
+        )?;
+        let blob: Vec<u8> = (0..1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(7919) % 251) as u8)
+            .collect();
+        zw.write_all(&blob)?;
+        let body = Arc::new(zw.finish()?.into_inner());
+
+        // Mock server answering HEAD and `Range: bytes=a-b` requests, one per connection
+        let served = Arc::new(AtomicUsize::new(0));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/pack.zip", listener.local_addr()?);
+        let (server_body, server_served) = (body.clone(), served.clone());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut req = [0u8; 4096];
+                let n = stream.read(&mut req).unwrap_or(0);
+                let req = String::from_utf8_lossy(&req[..n]).to_ascii_lowercase();
+                let range = req
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| {
+                        let (a, b) = r.trim().split_once('-')?;
+                        Some((a.parse::<usize>().ok()?, b.parse::<usize>().ok()?))
+                    });
+                let len = server_body.len();
+                let _ = match (req.starts_with("head"), range) {
+                    (true, _) => write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        len
+                    ),
+                    (false, Some((a, b))) => {
+                        let part = &server_body[a..=b.min(len - 1)];
+                        server_served.fetch_add(part.len(), Ordering::SeqCst);
+                        write!(
+                            stream,
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            a,
+                            a + part.len() - 1,
+                            len,
+                            part.len()
+                        )
+                        .and_then(|_| stream.write_all(part))
+                    }
+                    (false, None) => {
+                        server_served.fetch_add(len, Ordering::SeqCst);
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            len
+                        )
+                        .and_then(|_| stream.write_all(&server_body))
+                    }
+                };
+            }
+        });
+
+        let opts = MergeOptions {
+            ranged_url_reads: true,
+            namespaces: Some(vec!["test".to_string()]),
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Url(url)], &opts)?;
+        let entries = zip_entries(&out)?;
+        assert_eq!(entries["assets/test/a.txt"], b"wanted");
+        assert!(!entries.contains_key("assets/big/blob.bin"));
+        let fetched = served.load(Ordering::SeqCst);
+        assert!(
+            fetched < body.len() / 4,
+            "fetched {} of {} bytes",
+            fetched,
+            body.len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ranged_url_reads_fall_back_when_ranges_fail() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        zw.start_file(
+            "assets/test/a.txt",
+            zip::write::SimpleFileOptions::default(),
+        )?;
+        zw.write_all(b"whole")?;
+        let body = Arc::new(zw.finish()?.into_inner());
+
+        // Every server advertises ranges on HEAD; `get_status` answers every GET, range or not
+        let serve = |get_status: &'static str| -> anyhow::Result<String> {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+            let url = format!("http://{}/pack.zip", listener.local_addr()?);
+            let body = body.clone();
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut req = [0u8; 4096];
+                    let n = stream.read(&mut req).unwrap_or(0);
+                    let head = req[..n].starts_with(b"HEAD");
+                    let _ = match (head, get_status) {
+                        (true, _) => write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        ),
+                        (false, "200 OK") => write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .and_then(|_| stream.write_all(&body)),
+                        (false, status) => write!(
+                            stream,
+                            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        ),
+                    };
+                }
+            });
+            Ok(url)
+        };
+
+        // A server ignoring the Range header gets downloaded whole
+        let opts = MergeOptions {
+            ranged_url_reads: true,
+            ..Default::default()
+        };
+        let url = serve("200 OK")?;
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Url(url)],
+            &opts,
+        )?)?;
+        assert_eq!(entries["assets/test/a.txt"], b"whole");
+
+        // A failing server is subject to the failure policy
+        let url = serve("503 Service Unavailable")?;
+        let err =
+            merge_packs_to_bytes_with_options(&[PackInput::Url(url.clone())], &opts).unwrap_err();
+        assert!(matches!(err, MergeError::HttpStatus { status: 503, .. }));
+        let opts = MergeOptions {
+            url_failure_policy: UrlFailurePolicy::SkipTransientErrorPermanent,
+            ..opts
+        };
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Url(url)],
+            &opts,
+        )?)?;
+        assert!(!entries.contains_key("assets/test/a.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn slow_url_times_out() -> anyhow::Result<()> {
+        // A mock server that accepts the request but doesn't answer in time
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/slow.zip", listener.local_addr()?);
+        let server = std::thread::spawn(move || -> std::io::Result<()> {
+            let (mut stream, _) = listener.accept()?;
+            let mut req = [0u8; 1024];
+            let _ = stream.read(&mut req)?;
+            std::thread::sleep(std::time::Duration::from_millis(1500));
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            Ok(())
+        });
+
+        let opts = MergeOptions {
+            per_input_timeout: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let res = merge_packs_to_bytes_with_options(&[PackInput::Url(url.clone())], &opts);
+        assert!(matches!(res, Err(MergeError::Timeout(u)) if u == url));
+        assert!(started.elapsed() < std::time::Duration::from_millis(1500));
+        server.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_merge_honours_per_input_timeout() -> anyhow::Result<()> {
+        register_reader(
+            "slowpack",
+            Box::new(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(1500));
+                Ok(HashMap::new())
+            }),
+        );
+        let d = tempdir()?;
+        let slow = d.path().join("input.slowpack");
+        write(&slow, "")?;
+        let opts = MergeOptions {
+            per_input_timeout: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        let started = std::time::Instant::now();
+        let res = merge_packs_to_bytes_parallel_deterministic(&[PackInput::ZipFile(slow)], &opts);
+        assert!(
+            matches!(res, Err(MergeError::Timeout(_))),
+            "{:?}",
+            res.map(|b| b.len())
+        );
+        assert!(started.elapsed() < std::time::Duration::from_millis(1500));
+        Ok(())
+    }
+
+    #[test]
+    fn diff_to_dir_writes_only_changes() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/same.txt"), "same")?;
+        write(pack.join("assets/test/changed.txt"), "old")?;
+        write(pack.join("assets/test/removed.txt"), "gone soon")?;
+        let packs = [PackInput::Dir(pack.clone())];
+        let prev = merge_packs_to_bytes(&packs)?;
+
+        write(pack.join("assets/test/changed.txt"), "new")?;
+        std::fs::remove_file(pack.join("assets/test/removed.txt"))?;
+        let out = d.path().join("delta");
+        diff_to_dir(&prev, &packs, &out, &MergeOptions::default())?;
+
+        let mut written: Vec<String> = WalkDir::new(&out)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                e.path()
+                    .strip_prefix(&out)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        written.sort();
+        assert_eq!(written, ["assets/test/changed.txt", "deleted.txt"]);
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/changed.txt"))?,
+            "new"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("deleted.txt"))?,
+            "assets/test/removed.txt\n"
+        );
+
+        // The listing's name is reserved rather than written twice
+        write(pack.join("deleted.txt"), "mine")?;
+        let err = diff_to_dir(
+            &prev,
+            &packs,
+            d.path().join("delta2"),
+            &MergeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, MergeError::InvalidInput(m) if m.contains("deleted.txt")));
+        assert!(!d.path().join("delta2").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn mcmeta_post_process_adds_custom_key() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        write(
+            d.path().join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34}}"#,
+        )?;
+        let opts = MergeOptions {
+            mcmeta_post_process: Some(McmetaPostProcess::new(|mut meta| {
+                meta["x_build"] = serde_json::json!({ "commit": "abc123" });
+                meta
+            })),
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(
+            &[PackInput::Dir(d.path().into())],
+            &opts,
+        )?)?;
+        let meta: serde_json::Value = serde_json::from_slice(&out["pack.mcmeta"])?;
+        assert_eq!(meta["x_build"]["commit"], "abc123");
+        assert_eq!(meta["pack"]["pack_format"], 34);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_zip_archives_reads_open_handles() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let make = |name: &str, body: &[u8]| -> anyhow::Result<PathBuf> {
+            let path = td.path().join(name);
+            let mut zw = ZipWriter::new(File::create(&path)?);
+            let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("pack.mcmeta", fo.clone())?;
+            zw.write_all(br#"{"pack":{"pack_format":15}}"#)?;
+            zw.start_file("assets/minecraft/a.txt", fo)?;
+            zw.write_all(body)?;
+            zw.finish()?;
+            Ok(path)
+        };
+        let mut first = ZipArchive::new(File::open(make("first.zip", b"first")?)?)?;
+        let mut second = ZipArchive::new(File::open(make("second.zip", b"second")?)?)?;
+
+        let out =
+            merge_zip_archives_to_bytes(&mut [&mut first, &mut second], &MergeOptions::default())?;
+        let entries = zip_entries(&out)?;
+        assert_eq!(entries["assets/minecraft/a.txt"], b"second");
+        let readme = String::from_utf8(entries["README.md"].clone())?;
+        assert!(readme.contains("ZipArchive: <open archive 1>"));
+        // The handles stay usable after the merge
+        assert_eq!(first.len(), 2);
+        assert!(first.by_name("assets/minecraft/a.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn strict_overlays_rejects_contradictory_ranges() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let mut packs = Vec::new();
+        for (name, formats) in [("a", "[10, 12]"), ("b", "[20, 22]")] {
+            let dir = d.path().join(name);
+            create_dir_all(&dir)?;
+            write(
+                dir.join("pack.mcmeta"),
+                format!(
+                    r#"{{"pack":{{"pack_format":15,"description":""}},"overlays":{{"entries":[{{"directory":"foo","formats":{}}}]}}}}"#,
+                    formats
+                ),
+            )?;
+            packs.push(PackInput::Dir(dir));
+        }
+
+        assert!(merge_packs_to_bytes_with_options(&packs, &MergeOptions::default()).is_ok());
+        let opts = MergeOptions {
+            strict_overlays: true,
+            ..Default::default()
+        };
+        match merge_packs_to_bytes_with_options(&packs, &opts) {
+            Err(MergeError::InvalidInput(msg)) => assert!(msg.contains("\"foo\""), "{}", msg),
+            other => panic!(
+                "expected an overlay conflict, got {:?}",
+                other.map(|b| b.len())
+            ),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn merge_overlays_keeps_extra_fields_of_earlier_entries() {
+        let earlier = serde_json::json!({ "entries": [
+            { "directory": "ov", "formats": [15, 18], "x_note": "keep me" }
+        ]});
+        let later = serde_json::json!({ "entries": [
+            { "directory": "ov", "formats": [18, 34] }
+        ]});
+        let merged = merge_overlays(&[earlier, later]).unwrap();
+        assert_eq!(
+            merged["entries"],
+            serde_json::json!([{ "directory": "ov", "formats": [18, 34], "x_note": "keep me" }])
+        );
+    }
+
+    #[test]
+    fn zip_input_formats_match_between_strategies() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let zip_path = td.path().join("a.zip");
+        let mut zw = ZipWriter::new(File::create(&zip_path)?);
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":22,"max_format":40}}"#)?;
+        zw.start_file("assets/minecraft/a.txt", fo)?;
+        zw.write_all(b"a")?;
+        zw.finish()?;
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("pack.mcmeta", fo)?;
+        zw.write_all(br#"{"pack":{"pack_format":15}}"#)?;
+        let zip_bytes = zw.finish()?.into_inner();
+        let packs = vec![PackInput::ZipFile(zip_path), PackInput::ZipBytes(zip_bytes)];
+
+        let in_memory = merge_packs_to_bytes(&packs)?;
+        let streamed_path = td.path().join("out.zip");
+        let streamed_opts = MergeOptions {
+            streaming_threshold_bytes: Some(0),
+            ..Default::default()
+        };
+        merge_packs_to_file_with_options(&packs, &streamed_path, &streamed_opts)?;
+        for out in [in_memory, std::fs::read(&streamed_path)?] {
+            let mcmeta: serde_json::Value =
+                serde_json::from_slice(&zip_entries(&out)?["pack.mcmeta"])?;
+            assert_eq!(mcmeta["pack"]["pack_format"], 22);
+            assert_eq!(mcmeta["pack"]["max_format"], 40);
+            assert_eq!(
+                mcmeta["pack"]["supported_formats"],
+                serde_json::json!([1, 22])
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pack_png_highest_resolution_picks_largest_icon() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let png = |size: u32| -> anyhow::Result<Vec<u8>> {
+            let mut out = Vec::new();
+            image::DynamicImage::new_rgba8(size, size)
+                .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)?;
+            Ok(out)
+        };
+        let (big, small) = (td.path().join("big"), td.path().join("small"));
+        create_dir_all(&big)?;
+        create_dir_all(&small)?;
+        let big_png = png(128)?;
+        write(big.join("pack.png"), &big_png)?;
+        write(small.join("pack.png"), png(16)?)?;
+
+        let opts = MergeOptions {
+            pack_png_policy: PackPngPolicy::HighestResolution,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[big.into(), small.into()], &opts)?;
+        assert_eq!(zip_entries(&out)?["pack.png"], big_png);
+        Ok(())
+    }
+
+    #[test]
+    fn inputs_below_format_floor_are_dropped() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (old, new) = (td.path().join("old"), td.path().join("new"));
+        create_dir_all(old.join("assets/minecraft"))?;
+        create_dir_all(new.join("assets/minecraft"))?;
+        write(old.join("pack.mcmeta"), r#"{"pack":{"pack_format":1}}"#)?;
+        write(old.join("assets/minecraft/old.txt"), "old")?;
+        write(new.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        write(new.join("assets/minecraft/new.txt"), "new")?;
+
+        let opts = MergeOptions {
+            drop_inputs_below_format: Some(6),
+            supported_formats_policy: SupportedFormatsPolicy::LowestToHighest,
+            ..Default::default()
+        };
+        let packs = [PackInput::Dir(old.clone()), PackInput::Dir(new.clone())];
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let entries = zip_entries(&out)?;
+        assert!(!entries.contains_key("assets/minecraft/old.txt"));
+        assert!(entries.contains_key("assets/minecraft/new.txt"));
+        // The README only lists inputs that made it into the merge, on every output path
+        let readme = String::from_utf8(entries["README.md"].clone())?;
+        assert!(!readme.contains(&old.display().to_string()));
+        assert!(readme.contains(&new.display().to_string()));
+        let file = td.path().join("out.zip");
+        merge_packs_to_file_with_options(&packs, &file, &opts)?;
+        let readme = zip_entries(&std::fs::read(&file)?)?["README.md"].clone();
+        assert!(!String::from_utf8(readme)?.contains(&old.display().to_string()));
+        let mcmeta: serde_json::Value = serde_json::from_slice(&entries["pack.mcmeta"])?;
+        assert_eq!(mcmeta["pack"]["min_format"], 15);
+        Ok(())
+    }
+
+    #[test]
+    fn identical_overlay_files_are_detected_and_coalesced() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        let tex = "assets/minecraft/textures/block";
+        for dir in ["", "overlay_a/", "overlay_b/"] {
+            create_dir_all(pack.join(format!("{}{}", dir, tex)))?;
+        }
+        write(
+            pack.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":34},"overlays":{"entries":[
+                {"directory":"overlay_a","formats":[34,40]},
+                {"directory":"overlay_b","formats":[41,50]}]}}"#,
+        )?;
+        write(pack.join(format!("{}/stone.png", tex)), "stone")?;
+        write(pack.join(format!("overlay_a/{}/stone.png", tex)), "stone")?;
+        write(pack.join(format!("overlay_b/{}/stone.png", tex)), "stone")?;
+        write(pack.join(format!("overlay_a/{}/dirt.png", tex)), "new dirt")?;
+        write(pack.join(format!("overlay_b/{}/dirt.png", tex)), "new dirt")?;
+
+        let packs = [PackInput::Dir(pack)];
+        let report = find_identical_overlay_files(&packs, &MergeOptions::default())?;
+        let dirs = vec!["overlay_a".to_string(), "overlay_b".to_string()];
+        assert_eq!(
+            report,
+            vec![
+                IdenticalOverlayFile {
+                    path: format!("{}/dirt.png", tex),
+                    directories: dirs.clone(),
+                    matches_base: false,
+                },
+                IdenticalOverlayFile {
+                    path: format!("{}/stone.png", tex),
+                    directories: dirs,
+                    matches_base: true,
+                },
+            ]
+        );
+
+        let opts = MergeOptions {
+            coalesce_identical_overlay_files: true,
+            ..Default::default()
+        };
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert!(entries.contains_key(&format!("{}/stone.png", tex)));
+        assert!(!entries.contains_key(&format!("overlay_a/{}/stone.png", tex)));
+        assert!(!entries.contains_key(&format!("overlay_b/{}/stone.png", tex)));
+        assert!(entries.contains_key(&format!("overlay_a/{}/dirt.png", tex)));
+        Ok(())
+    }
+
+    #[test]
+    fn description_can_omit_crate_version() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(&pack)?;
+        write(pack.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+
+        let opts = MergeOptions {
+            include_version_in_description: false,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[pack.into()], &opts)?;
+        let mcmeta: serde_json::Value = serde_json::from_slice(&zip_entries(&out)?["pack.mcmeta"])?;
+        let desc = mcmeta["pack"]["description"].as_str().unwrap();
+        assert_eq!(desc, "Made with Rust API: resource_merger");
+        assert!(!desc.contains(env!("CARGO_PKG_VERSION")));
+        Ok(())
+    }
+
+    #[test]
+    fn first_wins_and_skip_if_exists_keep_the_earlier_entry() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        for (dir, body) in [(&a, "a"), (&b, "b")] {
+            create_dir_all(dir.join("assets/minecraft/textures"))?;
+            write(dir.join("assets/minecraft/textures/shared.png"), body)?;
+        }
+        write(b.join("assets/minecraft/textures/only_b.png"), "b")?;
+        let packs = [PackInput::Dir(a), PackInput::Dir(b)];
+
+        for (policy, expected) in [
+            (OverwritePolicy::LastWins, b"b"),
+            (OverwritePolicy::FirstWins, b"a"),
+            (OverwritePolicy::SkipIfExists, b"a"),
+        ] {
+            let opts = MergeOptions {
+                overwrite: policy,
+                ..Default::default()
+            };
+            let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+            assert_eq!(
+                entries["assets/minecraft/textures/shared.png"], expected,
+                "{:?}",
+                policy
+            );
+            assert_eq!(entries["assets/minecraft/textures/only_b.png"], b"b");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn error_if_conflict_fails_on_any_duplicate_path() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        for dir in [&a, &b] {
+            create_dir_all(dir.join("assets/minecraft/textures"))?;
+            write(dir.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        }
+        write(a.join("assets/minecraft/textures/a.png"), "a")?;
+        write(b.join("assets/minecraft/textures/b.png"), "b")?;
+        let packs = [PackInput::Dir(a), PackInput::Dir(b.clone())];
+        let opts = MergeOptions {
+            overwrite: OverwritePolicy::ErrorIfConflict,
+            ..Default::default()
+        };
+        // Both inputs ship pack.mcmeta, which is generated and never conflicts
+        merge_packs_to_bytes_with_options(&packs, &opts)?;
+
+        write(b.join("assets/minecraft/textures/a.png"), "b")?;
+        match merge_packs_to_bytes_with_options(&packs, &opts) {
+            Err(MergeError::InvalidInput(msg)) => {
+                assert!(msg.contains("input 2"), "{}", msg);
+                assert!(msg.contains("assets/minecraft/textures/a.png"), "{}", msg);
+            }
+            other => panic!(
+                "expected a conflict error, got {:?}",
+                other.map(|b| b.len())
+            ),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn allowed_conflicts_are_exempt_from_conflict_errors() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        for (dir, body) in [(&a, "a"), (&b, "b")] {
+            create_dir_all(dir.join("assets/minecraft/lang"))?;
+            write(dir.join("assets/minecraft/lang/en_us.json"), body)?;
+        }
+        let opts = MergeOptions {
+            overwrite: OverwritePolicy::ErrorIfConflict,
+            allowed_conflicts: vec!["assets/*/lang/**".to_string()],
+            ..Default::default()
+        };
+        let packs = [PackInput::Dir(a.clone()), PackInput::Dir(b.clone())];
+        let out = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        assert_eq!(zip_entries(&out)?["assets/minecraft/lang/en_us.json"], b"b");
+
+        write(a.join("assets/minecraft/other.txt"), "a")?;
+        write(b.join("assets/minecraft/other.txt"), "b")?;
+        let err = merge_packs_to_bytes_with_options(&packs, &opts).unwrap_err();
+        assert!(err.to_string().contains("assets/minecraft/other.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn read_pack_returns_directory_entries() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft/textures"))?;
+        write(pack.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        write(pack.join("assets/minecraft/textures/a.png"), "a")?;
+
+        let files = read_pack(&PackInput::Dir(pack), &MergeOptions::default())?;
+        let mut keys: Vec<&str> = files.keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["assets/minecraft/textures/a.png", "pack.mcmeta"]);
+        assert_eq!(files["assets/minecraft/textures/a.png"], b"a");
+        Ok(())
+    }
+
+    #[test]
+    fn atomic_dir_output_is_fully_old_or_fully_new() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/a.txt"), "new")?;
+        let out = d.path().join("out");
+        create_dir_all(out.join("assets/test"))?;
+        write(out.join("assets/test/a.txt"), "old")?;
+        write(out.join("stale.txt"), "old")?;
+        // A user directory that happens to be named like a backup is left alone
+        create_dir_all(d.path().join("out.bak"))?;
+        write(d.path().join("out.bak/keep.txt"), "mine")?;
+        let tree = |dir: &Path| -> anyhow::Result<Vec<(String, String)>> {
+            let mut files = Vec::new();
+            for entry in WalkDir::new(dir).sort_by_file_name() {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    let rel = entry
+                        .path()
+                        .strip_prefix(dir)?
+                        .to_string_lossy()
+                        .replace('\\', "/");
+                    files.push((rel, std::fs::read_to_string(entry.path())?));
+                }
+            }
+            Ok(files)
+        };
+        let old_tree = tree(&out)?;
+        let packs = [PackInput::Dir(pack)];
+
+        // A failing merge leaves the old tree untouched
+        let failing = MergeOptions {
+            transforms: vec![FileTransform::new("README.md", |_, _| {
+                Err(MergeError::InvalidInput("boom".into()))
+            })],
+            ..Default::default()
+        };
+        assert!(merge_packs_to_dir(&packs, &out, &failing).is_err());
+        assert_eq!(tree(&out)?, old_tree);
+
+        // So does one that fails halfway through extracting: `a.txt` is written as a file, then
+        // `a.txt/b.txt` needs it to be a directory
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::SimpleFileOptions::default();
+        zw.start_file("assets/test/a.txt", fo)?;
+        zw.write_all(b"new")?;
+        zw.start_file("assets/test/a.txt/b.txt", fo)?;
+        zw.write_all(b"new")?;
+        let clashing = [PackInput::ZipBytes(zw.finish()?.into_inner())];
+        let failed = merge_packs_to_dir(&clashing, &out, &MergeOptions::default());
+        assert!(matches!(failed, Err(MergeError::Io(_))), "{:?}", failed);
+        assert_eq!(tree(&out)?, old_tree);
+
+        // A successful merge replaces it completely and leaves no backup behind
+        merge_packs_to_dir(&packs, &out, &MergeOptions::default())?;
+        assert!(!out.join("stale.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/a.txt"))?,
+            "new"
+        );
+        assert!(out.join("pack.mcmeta").is_file());
+        assert_eq!(
+            std::fs::read_to_string(d.path().join("out.bak/keep.txt"))?,
+            "mine"
+        );
+        let leftovers: Vec<_> = std::fs::read_dir(d.path())?
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(".resource_merger-")
+            })
+            .collect();
+        assert!(leftovers.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_input_pack_png_carries_icon_unchanged() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        create_dir_all(&a)?;
+        create_dir_all(&b)?;
+        write(a.join("pack.png"), b"\x89PNG\r\n\x1a\nfirst icon")?;
+        write(b.join("pack.png"), b"\x89PNG\r\n\x1a\nwinning icon")?;
+
+        let opts = MergeOptions {
+            keep_input_pack_png: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[a.into(), b.into()], &opts)?;
+        assert_eq!(
+            zip_entries(&out)?["pack.png"],
+            b"\x89PNG\r\n\x1a\nwinning icon"
+        );
+
+        let out = merge_packs_to_bytes_with_options(&[], &opts)?;
+        assert_eq!(zip_entries(&out)?["pack.png"], default_pack_png_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn validate_all_mcmeta_reports_broken_sidecar() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        let tex = pack.join("assets/minecraft/textures/block");
+        create_dir_all(&tex)?;
+        write(pack.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        write(
+            tex.join("water.png.mcmeta"),
+            r#"{"animation":{"frametime":2}}"#,
+        )?;
+        write(
+            tex.join("lava.png.mcmeta"),
+            r#"{"animation":{"frametime":2,}"#,
+        )?;
+
+        let packs = [PackInput::Dir(pack)];
+        assert!(merge_packs_to_bytes(&packs).is_ok());
+        let opts = MergeOptions {
+            validate_all_mcmeta: true,
+            ..Default::default()
+        };
+        let err = merge_packs_to_bytes_with_options(&packs, &opts)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("assets/minecraft/textures/block/lava.png.mcmeta"));
+        assert!(!err.contains("water.png.mcmeta"));
+        Ok(())
+    }
+
+    #[test]
+    fn strip_vanilla_identical_drops_unchanged_files() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (vanilla, pack) = (td.path().join("vanilla"), td.path().join("pack"));
+        for dir in [&vanilla, &pack] {
+            create_dir_all(dir.join("assets/minecraft/textures/block"))?;
+        }
+        write(
+            vanilla.join("assets/minecraft/textures/block/stone.png"),
+            "stone",
+        )?;
+        write(
+            vanilla.join("assets/minecraft/textures/block/dirt.png"),
+            "dirt",
+        )?;
+        write(
+            pack.join("assets/minecraft/textures/block/stone.png"),
+            "stone",
+        )?;
+        write(
+            pack.join("assets/minecraft/textures/block/dirt.png"),
+            "better dirt",
+        )?;
+
+        let opts = MergeOptions {
+            strip_vanilla_identical: Some(vanilla),
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[pack.clone().into()], &opts)?;
+        let entries = zip_entries(&out)?;
+        assert!(!entries.contains_key("assets/minecraft/textures/block/stone.png"));
+        assert_eq!(
+            entries["assets/minecraft/textures/block/dirt.png"],
+            b"better dirt"
+        );
+
+        // A zip reference is only read at merged paths, so its unrelated (here unreadable
+        // encrypted) entries don't matter
+        let reference = td.path().join("vanilla.zip");
+        let mut zw = ZipWriter::new(File::create(&reference)?);
+        zw.start_file(
+            "assets/minecraft/textures/block/stone.png",
+            zip::write::SimpleFileOptions::default(),
+        )?;
+        zw.write_all(b"stone")?;
+        zw.start_file(
+            "assets/minecraft/sounds/secret.ogg",
+            zip::write::SimpleFileOptions::default()
+                .with_aes_encryption(zip::AesMode::Aes256, "hunter2"),
+        )?;
+        zw.write_all(b"ogg")?;
+        zw.finish()?;
+        let opts = MergeOptions {
+            strip_vanilla_identical: Some(reference),
+            ..Default::default()
+        };
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&[pack.into()], &opts)?)?;
+        assert!(!entries.contains_key("assets/minecraft/textures/block/stone.png"));
+        assert!(entries.contains_key("assets/minecraft/textures/block/dirt.png"));
+        Ok(())
+    }
+
+    #[test]
+    fn file_output_matches_buffered_output() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft/textures"))?;
+        write(pack.join("pack.mcmeta"), r#"{"pack":{"pack_format":15}}"#)?;
+        for i in 0..20 {
+            write(
+                pack.join(format!("assets/minecraft/textures/{}.txt", i)),
+                i.to_string(),
+            )?;
+        }
+        let packs = [PackInput::Dir(pack)];
+        let opts = MergeOptions {
+            fixed_timestamps: true,
+            ..Default::default()
+        };
+        let buffered = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        let out = td.path().join("out.zip");
+        merge_packs_to_file_with_options(&packs, &out, &opts)?;
+        assert_eq!(std::fs::read(&out)?, buffered);
+        Ok(())
+    }
+
+    #[test]
+    fn preview_merge_reports_mcmeta_and_entries() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        for (dir, fmt) in [(&a, 15), (&b, 22)] {
+            create_dir_all(dir.join("assets/minecraft"))?;
+            write(
+                dir.join("pack.mcmeta"),
+                format!(r#"{{"pack":{{"pack_format":{}}}}}"#, fmt),
+            )?;
+            write(dir.join("assets/minecraft/shared.txt"), "shared")?;
+        }
+        write(a.join("assets/minecraft/only_a.txt"), "abc")?;
+
+        let preview = preview_merge(&[a.into(), b.into()], &MergeOptions::default())?;
+        assert_eq!(preview.mcmeta["pack"]["pack_format"], 22);
+        let paths: Vec<&str> = preview.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "README.md",
+                "assets/minecraft/only_a.txt",
+                "assets/minecraft/shared.txt",
+                "pack.mcmeta",
+                "pack.png"
+            ]
+        );
+        assert_eq!(preview.entries[1].size, 3);
+        assert_eq!(preview.warnings.len(), 1);
+        assert!(preview.warnings[0].contains("assets/minecraft/shared.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_input_crc_detects_corrupted_entry() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        zw.start_file("assets/minecraft/a.txt", fo)?;
+        zw.write_all(b"original contents")?;
+        let mut bytes = zw.finish()?.into_inner();
+        let pos = bytes
+            .windows(8)
+            .position(|w| w == b"original")
+            .expect("stored data present");
+        bytes[pos] = b'O';
+
+        let opts = MergeOptions {
+            verify_input_crc: true,
+            ..Default::default()
+        };
+        let err = merge_packs_to_bytes_with_options(&[PackInput::ZipBytes(bytes)], &opts)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("ZipBytes: <in-memory>"), "{}", err);
+        assert!(err.contains("assets/minecraft/a.txt"), "{}", err);
+        assert!(err.contains("CRC-32"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_assertion_reports_differences() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft"))?;
+        write(pack.join("assets/minecraft/a.txt"), "a")?;
+        write(pack.join("assets/minecraft/b.txt"), "b")?;
+        let packs = [PackInput::Dir(pack.clone())];
+        let opts = MergeOptions::default();
+        let manifest = zip_manifest(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        let manifest_path = td.path().join("manifest.json");
+        write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+
+        assert_against_manifest(&packs, &opts, &manifest_path)?;
+
+        write(pack.join("assets/minecraft/a.txt"), "changed")?;
+        std::fs::remove_file(pack.join("assets/minecraft/b.txt"))?;
+        write(pack.join("assets/minecraft/c.txt"), "c")?;
+        match assert_against_manifest(&packs, &opts, &manifest_path) {
+            Err(MergeError::ManifestMismatch(diff)) => {
+                let lines: Vec<&str> = diff.lines().collect();
+                assert_eq!(lines.len(), 3, "{}", diff);
+                assert!(lines[0].starts_with("changed: assets/minecraft/a.txt"));
+                assert_eq!(lines[1], "extra: assets/minecraft/c.txt");
+                assert_eq!(lines[2], "missing: assets/minecraft/b.txt");
+            }
+            other => panic!("expected a manifest mismatch, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn overwrite_rules_apply_per_glob() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let (a, b) = (td.path().join("a"), td.path().join("b"));
+        for (dir, body) in [(&a, "a"), (&b, "b")] {
+            create_dir_all(dir.join("assets/minecraft/textures"))?;
+            create_dir_all(dir.join("assets/minecraft/models"))?;
+            write(dir.join("assets/minecraft/textures/stone.png"), body)?;
+            write(dir.join("assets/minecraft/models/stone.json"), body)?;
+        }
+        let packs = [PackInput::Dir(a.clone()), PackInput::Dir(b.clone())];
+        let opts = MergeOptions {
+            overwrite_rules: vec![
+                (
+                    "assets/*/lang/**".to_string(),
+                    OverwritePolicy::ErrorIfConflict,
+                ),
+                ("assets/*/models/**".to_string(), OverwritePolicy::FirstWins),
+            ],
+            ..Default::default()
+        };
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(entries["assets/minecraft/textures/stone.png"], b"b");
+        assert_eq!(entries["assets/minecraft/models/stone.json"], b"a");
+
+        for dir in [&a, &b] {
+            create_dir_all(dir.join("assets/minecraft/lang"))?;
+            write(dir.join("assets/minecraft/lang/en_us.json"), "{}")?;
+        }
+        let err = merge_packs_to_bytes_with_options(&packs, &opts).unwrap_err();
+        assert!(err.to_string().contains("assets/minecraft/lang/en_us.json"));
+        Ok(())
+    }
+
+    #[test]
+    fn read_mcmeta_returns_raw_text() -> anyhow::Result<()> {
+        let mcmeta = r#"{ "pack": { "pack_format": 15, "description": "raw" } }"#;
+        let make_zip = |with_mcmeta: bool| -> anyhow::Result<Vec<u8>> {
+            let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+            let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            if with_mcmeta {
+                zw.start_file("pack.mcmeta", fo.clone())?;
+                zw.write_all(mcmeta.as_bytes())?;
+            }
+            zw.start_file("assets/minecraft/a.txt", fo)?;
+            zw.write_all(b"a")?;
+            Ok(zw.finish()?.into_inner())
+        };
+        assert_eq!(
+            read_mcmeta(&PackInput::ZipBytes(make_zip(true)?))?.as_deref(),
+            Some(mcmeta)
+        );
+        assert_eq!(read_mcmeta(&PackInput::ZipBytes(make_zip(false)?))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn atomic_writes_use_configured_temp_dir() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft"))?;
+        write(pack.join("assets/minecraft/a.txt"), "a")?;
+        let (temps, out_dir) = (td.path().join("temps"), td.path().join("out"));
+        create_dir_all(&temps)?;
+        create_dir_all(&out_dir)?;
+
+        // Look into the temp dir while the output is being written
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (seen_in_transform, temps_in_transform) = (seen.clone(), temps.clone());
+        let opts = MergeOptions {
+            temp_dir: Some(temps.clone()),
+            transforms: vec![FileTransform::new("README.md", move |_, bytes| {
+                for entry in std::fs::read_dir(&temps_in_transform)? {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    seen_in_transform.lock().unwrap().push(name);
+                }
+                Ok(bytes)
+            })],
+            ..Default::default()
+        };
+        merge_packs_to_file_with_options(&[pack.into()], out_dir.join("out.zip"), &opts)?;
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert!(seen[0].starts_with(".resource_merger-"));
+        assert!(out_dir.join("out.zip").is_file());
+        assert_eq!(std::fs::read_dir(&temps)?.count(), 0);
+        assert_eq!(std::fs::read_dir(&out_dir)?.count(), 1);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn scratch_files_use_configured_temp_dir() -> anyhow::Result<()> {
+        let td = tempdir()?;
+        let pack = td.path().join("pack");
+        create_dir_all(pack.join("assets/minecraft"))?;
+        write(pack.join("assets/minecraft/a.txt"), "a")?;
+        let temps = td.path().join("temps");
+        create_dir_all(&temps)?;
+
+        // The scratch file is unlinked, so look for it among the open descriptors while the
+        // merged zip is being written into it
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (hits_in_transform, temps_in_transform) = (hits.clone(), temps.clone());
+        let opts = MergeOptions {
+            temp_dir: Some(temps.clone()),
+            split_output_bytes: Some(1 << 20),
+            transforms: vec![FileTransform::new("README.md", move |_, bytes| {
+                for fd in std::fs::read_dir("/proc/self/fd")? {
+                    if let Ok(target) = std::fs::read_link(fd?.path()) {
+                        if target.starts_with(&temps_in_transform) {
+                            hits_in_transform.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+                Ok(bytes)
+            })],
+            ..Default::default()
+        };
+        merge_packs_to_file_with_options(&[pack.clone().into()], td.path().join("out.zip"), &opts)?;
+        assert!(hits.swap(0, Ordering::Relaxed) > 0);
+
+        let opts = MergeOptions {
+            split_output_bytes: None,
+            streaming_threshold_bytes: Some(0),
+            ..opts
+        };
+        merge_packs_to_dir(&[pack.into()], td.path().join("out"), &opts)?;
+        assert!(hits.load(Ordering::Relaxed) > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn single_zip_normalization_preserves_entries() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":22}}"#)?;
+        let mut expected = std::collections::BTreeMap::new();
+        for i in 0..10 {
+            let name = format!("assets/minecraft/textures/block/{}.png", i);
+            let body = format!("texture {}", i).repeat(50).into_bytes();
+            zw.start_file(name.as_str(), fo.clone())?;
+            zw.write_all(&body)?;
+            expected.insert(name, body);
+        }
+        // A nested path with a backslash is normalized even on the fast path
+        zw.start_file("assets\\minecraft\\lang\\en_us.json", fo)?;
+        zw.write_all(b"{}")?;
+        expected.insert(
+            "assets/minecraft/lang/en_us.json".to_string(),
+            b"{}".to_vec(),
+        );
+        let bytes = zw.finish()?.into_inner();
+
+        let out = merge_packs_to_bytes(&[PackInput::ZipBytes(bytes)])?;
+        let mut entries = zip_entries(&out)?;
+        let mcmeta: serde_json::Value = serde_json::from_slice(&entries["pack.mcmeta"])?;
+        assert_eq!(mcmeta["pack"]["pack_format"], 22);
+        for root in SYNTHESIZED_ROOT_FILES {
+            assert!(entries.remove(root).is_some(), "missing {}", root);
+        }
+        assert_eq!(entries, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn file_output_uses_single_zip_pass_through() -> anyhow::Result<()> {
+        // A stored text entry would be deflated by a regular merge; the fast path keeps it as is
+        let text = "the same line again\n".repeat(200);
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        zw.start_file("assets/minecraft/lang/notes.txt", stored)?;
+        zw.write_all(text.as_bytes())?;
+        let bytes = zw.finish()?.into_inner();
+        let d = tempdir()?;
+        let (zip_out, dir_out) = (d.path().join("out.zip"), d.path().join("out"));
+
+        let packs = [PackInput::ZipBytes(bytes)];
+        merge_packs_to_file_with_options(&packs, &zip_out, &MergeOptions::default())?;
+        let mut archive = ZipArchive::new(File::open(&zip_out)?)?;
+        let entry = archive.by_name("assets/minecraft/lang/notes.txt")?;
+        assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
+        drop(entry);
+        let regular = merge_packs_to_bytes_with_options(
+            &packs,
+            &MergeOptions {
+                fixed_timestamps: true,
+                ..Default::default()
+            },
+        )?;
+        let mut archive = ZipArchive::new(Cursor::new(regular))?;
+        let entry = archive.by_name("assets/minecraft/lang/notes.txt")?;
+        assert_eq!(entry.compression(), zip::CompressionMethod::Deflated);
+        drop(entry);
+
+        // A per-input timeout needs the watchdog of the regular plan
+        let bounded = merge_packs_to_bytes_with_options(
+            &packs,
+            &MergeOptions {
+                per_input_timeout: Some(std::time::Duration::from_secs(30)),
+                ..Default::default()
+            },
+        )?;
+        let mut archive = ZipArchive::new(Cursor::new(bounded))?;
+        let entry = archive.by_name("assets/minecraft/lang/notes.txt")?;
+        assert_eq!(entry.compression(), zip::CompressionMethod::Deflated);
+        drop(entry);
+
+        // Methods Minecraft can't read are recompressed even on the fast path
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let bzip2 = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Bzip2);
+        zw.start_file("assets/minecraft/lang/other.txt", bzip2)?;
+        zw.write_all(text.as_bytes())?;
+        let bzipped = [PackInput::ZipBytes(zw.finish()?.into_inner())];
+        let out = merge_packs_to_bytes_with_options(&bzipped, &MergeOptions::default())?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let mut entry = archive.by_name("assets/minecraft/lang/other.txt")?;
+        assert_eq!(entry.compression(), zip::CompressionMethod::Deflated);
+        let mut body = String::new();
+        entry.read_to_string(&mut body)?;
+        assert_eq!(body, text);
+        drop(entry);
+
+        merge_packs_to_dir(&packs, &dir_out, &MergeOptions::default())?;
+        assert_eq!(
+            std::fs::read_to_string(dir_out.join("assets/minecraft/lang/notes.txt"))?,
+            text
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn flatten_overlays_selects_matching_variant() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        for (dir, body) in [("", "base"), ("ov_old/", "old"), ("ov_new/", "new")] {
+            create_dir_all(pack.join(format!("{}assets/test", dir)))?;
+            write(pack.join(format!("{}assets/test/a.txt", dir)), body)?;
+        }
+        write(
+            pack.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":15},"overlays":{"entries":[
+                {"formats":[10,20],"directory":"ov_old"},
+                {"formats":{"min_inclusive":30,"max_inclusive":40},"directory":"ov_new"}]}}"#,
+        )?;
+
+        let read = |target: u32| -> anyhow::Result<(String, zip::ZipArchive<Cursor<Vec<u8>>>)> {
+            let opts = MergeOptions {
+                flatten_overlays_to: Some(target),
+                ..Default::default()
+            };
+            let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack.clone())], &opts)?;
+            let mut archive = ZipArchive::new(Cursor::new(out))?;
+            let mut s = String::new();
+            archive
+                .by_name("assets/test/a.txt")?
+                .read_to_string(&mut s)?;
+            Ok((s, archive))
+        };
+
+        let (s, mut archive) = read(35)?;
+        assert_eq!(s, "new");
+        assert!(archive.file_names().all(|n| !n.starts_with("ov_")));
+        let meta: serde_json::Value = serde_json::from_reader(archive.by_name("pack.mcmeta")?)?;
+        assert!(meta.get("overlays").is_none());
+
+        assert_eq!(read(15)?.0, "old");
+        assert_eq!(read(25)?.0, "base");
+
+        // Injected files are applied after flattening, so no overlay replaces them
+        let opts = MergeOptions {
+            flatten_overlays_to: Some(35),
+            extra_files: vec![("assets/test/a.txt".to_string(), b"injected".to_vec())],
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack.clone())], &opts)?;
+        assert_eq!(zip_entries(&out)?["assets/test/a.txt"], b"injected");
+
+        // Overlapping overlays apply in declaration order, not directory name order
+        for (dir, body) in [("z_first/", "first"), ("a_second/", "second")] {
+            create_dir_all(pack.join(format!("{}assets/test", dir)))?;
+            write(pack.join(format!("{}assets/test/a.txt", dir)), body)?;
+        }
+        write(
+            pack.join("pack.mcmeta"),
+            r#"{"pack":{"pack_format":15},"overlays":{"entries":[
+                {"formats":[30,40],"directory":"z_first"},
+                {"formats":[30,40],"directory":"a_second"}]}}"#,
+        )?;
+        assert_eq!(read(35)?.0, "second");
+        let out =
+            merge_packs_to_bytes_with_options(&[PackInput::Dir(pack)], &MergeOptions::default())?;
+        let meta: serde_json::Value = serde_json::from_slice(&zip_entries(&out)?["pack.mcmeta"])?;
+        assert_eq!(meta["overlays"]["entries"][0]["directory"], "z_first");
+        assert_eq!(meta["overlays"]["entries"][1]["directory"], "a_second");
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_merge_aborts() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/a.txt"), b"a")?;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let opts = MergeOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let packs = vec![PackInput::Dir(pack)];
+        assert!(merge_packs_to_bytes_with_options(&packs, &opts).is_ok());
+
+        cancel.store(true, Ordering::Relaxed);
+        let res = merge_packs_to_bytes_with_options(&packs, &opts);
+        assert!(matches!(res, Err(MergeError::Cancelled)));
+        let out = d.path().join("out");
+        let res = merge_packs_to_dir(&packs, &out, &opts);
+        assert!(matches!(res, Err(MergeError::Cancelled)));
+        Ok(())
+    }
+
+    #[test]
+    fn merge_with_controls_reports_progress() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        for (dir, name) in [(&a, "a.txt"), (&b, "b.txt")] {
+            create_dir_all(dir.join("assets/test"))?;
+            write(dir.join("assets/test").join(name), name)?;
+        }
+        let out = d.path().join("out.zip");
+        let packs = vec![PackInput::Dir(a), PackInput::Dir(b)];
+
+        // An Rc makes the callback !Send, as GUI state usually is
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let report = merge_with_controls(
+            &packs,
+            &out,
+            &MergeOptions::default(),
+            move |e| sink.borrow_mut().push(e),
+            &AtomicBool::new(false),
+        )?;
+
+        let events = events.borrow();
+        assert_eq!(
+            events[..2],
+            [
+                ProgressEvent::InputStarted { index: 0, total: 2 },
+                ProgressEvent::InputStarted { index: 1, total: 2 },
+            ]
+        );
+        let written: Vec<&str> = events[2..]
+            .iter()
+            .map(|e| match e {
+                ProgressEvent::EntryWritten { path, total, .. } => {
+                    assert_eq!(*total, 5);
+                    path.as_str()
+                }
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert!(written.contains(&"assets/test/a.txt") && written.contains(&"pack.mcmeta"));
+        assert_eq!(
+            report,
+            MergeReport {
+                inputs: 2,
+                entries: 5,
+                bytes_written: std::fs::metadata(&out)?.len(),
+            }
+        );
+
+        let res = merge_with_controls(
+            &packs,
+            d.path().join("cancelled.zip"),
+            &MergeOptions::default(),
+            |_| {},
+            &AtomicBool::new(true),
+        );
+        assert!(matches!(res, Err(MergeError::Cancelled)));
+        Ok(())
+    }
+
+    #[test]
+    fn checksum_sidecars_match_output() -> anyhow::Result<()> {
+        use sha2::{Digest, Sha256};
+        let hex = |bytes: &[u8]| -> String {
+            Sha256::digest(bytes)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect()
+        };
+
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/a.txt"), b"a")?;
+        let packs = vec![PackInput::Dir(pack)];
+        let opts = MergeOptions {
+            write_checksum_sidecar: true,
+            ..Default::default()
+        };
+
+        let out = d.path().join("merged.zip");
+        merge_packs_to_file_with_options(&packs, &out, &opts)?;
+        let sidecar = std::fs::read_to_string(d.path().join("merged.zip.sha256"))?;
+        assert_eq!(
+            sidecar,
+            format!("{}  merged.zip\n", hex(&std::fs::read(&out)?))
+        );
+
+        let out_dir = d.path().join("merged");
+        merge_packs_to_dir(&packs, &out_dir, &opts)?;
+        let sums = std::fs::read_to_string(out_dir.join("SHA256SUMS"))?;
+        let listed: Vec<&str> = sums.lines().map(|l| &l[66..]).collect();
+        assert_eq!(
+            listed,
+            ["README.md", "assets/test/a.txt", "pack.mcmeta", "pack.png"]
+        );
+        for line in sums.lines() {
+            let (hash, path) = line.split_once("  ").unwrap();
+            assert_eq!(hash, hex(&std::fs::read(out_dir.join(path))?), "{}", path);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wide_format_span_warns_or_fails_when_strict() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let mut packs = Vec::new();
+        for format in [6, 48] {
+            let dir = d.path().join(format!("f{}", format));
+            create_dir_all(&dir)?;
+            write(
+                dir.join("pack.mcmeta"),
+                format!(r#"{{"pack":{{"pack_format":{}}}}}"#, format),
+            )?;
+            packs.push(PackInput::Dir(dir));
+        }
+        let mut opts = MergeOptions {
+            supported_formats_policy: SupportedFormatsPolicy::LowestToHighest,
+            warn_on_wide_format_span: Some(10),
+            ..Default::default()
+        };
+        let preview = preview_merge(&packs, &opts)?;
+        assert_eq!(
+            preview.warnings,
+            ["inputs span pack_format 6 to 48, more than 10 apart; they are likely incompatible"]
+        );
+
+        opts.warn_on_wide_format_span = Some(42);
+        assert!(preview_merge(&packs, &opts)?.warnings.is_empty());
+
+        opts.warn_on_wide_format_span = Some(10);
+        opts.strict_format_span = true;
+        let res = merge_packs_to_bytes_with_options(&packs, &opts);
+        assert!(matches!(res, Err(MergeError::InvalidInput(m)) if m.contains("6 to 48")));
+        Ok(())
+    }
+
+    #[test]
+    fn pretty_mcmeta_is_indented() -> anyhow::Result<()> {
+        let compact = zip_entries(&merge_packs_to_bytes(&[])?)?;
+        assert!(!compact["pack.mcmeta"].contains(&b'\n'));
+
+        let opts = MergeOptions {
+            pretty_mcmeta: true,
+            ..Default::default()
+        };
+        let pretty = zip_entries(&merge_packs_to_bytes_with_options(&[], &opts)?)?;
+        let text = String::from_utf8(pretty["pack.mcmeta"].clone())?;
+        assert!(text.starts_with("{\n  \"pack\": {\n    "));
+        let reparsed: serde_json::Value = serde_json::from_str(&text)?;
+        let original: serde_json::Value = serde_json::from_slice(&compact["pack.mcmeta"])?;
+        assert_eq!(reparsed, original);
+        Ok(())
+    }
+
+    #[test]
+    fn dot_prefixed_pack_mcmeta_feeds_format_detection() -> anyhow::Result<()> {
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("./pack.mcmeta", fo.clone())?;
+        zw.write_all(br#"{"pack":{"pack_format":48}}"#)?;
+        zw.start_file("./assets/test/a.txt", fo)?;
+        zw.write_all(b"a")?;
+        let input = PackInput::ZipBytes(zw.finish()?.into_inner());
+
+        assert!(read_mcmeta(&input)?.is_some_and(|m| m.contains("48")));
+        let entries = zip_entries(&merge_packs_to_bytes(&[input])?)?;
+        assert_eq!(
+            entries.keys().map(|k| k.as_str()).collect::<Vec<_>>(),
+            ["README.md", "assets/test/a.txt", "pack.mcmeta", "pack.png"]
+        );
+        let meta: serde_json::Value = serde_json::from_slice(&entries["pack.mcmeta"])?;
+        assert_eq!(meta["pack"]["pack_format"], 48);
+        Ok(())
+    }
+
+    #[test]
+    fn analyze_collects_every_check() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (a, b) = (d.path().join("a"), d.path().join("b"));
+        for (dir, format) in [(&a, 6), (&b, 48)] {
+            create_dir_all(dir.join("assets/test"))?;
+            write(dir.join("assets/test/shared.txt"), format.to_string())?;
+            write(
+                dir.join("pack.mcmeta"),
+                format!(r#"{{"pack":{{"pack_format":{}}}}}"#, format),
+            )?;
+        }
+        write(a.join("assets/test/Bad.txt"), "x")?;
+        create_dir_all(b.join("assets/test/models/item"))?;
+        create_dir_all(b.join("assets/test/textures/item"))?;
+        write(
+            b.join("assets/test/models/item/wand.json"),
+            r##"{"textures":{"layer0":"test:item/wand","layer1":"test:item/gone",
+                "layer2":"minecraft:item/stick","layer3":"block/stone","particle":"#layer0"}}"##,
+        )?;
+        write(b.join("assets/test/textures/item/wand.png"), "png")?;
+        write(b.join("assets/test/textures/item/copy.png"), "png")?;
+        let opts = MergeOptions {
+            warn_on_wide_format_span: Some(10),
+            strict_format_span: true,
+            overwrite: OverwritePolicy::ErrorIfConflict,
+            ..Default::default()
+        };
+
+        let analysis = analyze(&[PackInput::Dir(a.clone()), PackInput::Dir(b)], &opts)?;
+        assert_eq!(analysis.conflicts, ["assets/test/shared.txt"]);
+        assert!(analysis
+            .format_span
+            .as_deref()
+            .is_some_and(|m| m.contains("6 to 48")));
+        assert_eq!(
+            analysis.path_lints,
+            [PathLint {
+                path: "assets/test/Bad.txt".to_string(),
+                reason: PathLintReason::Uppercase,
+            }]
+        );
+        assert_eq!(
+            analysis.missing_textures,
+            [MissingTexture {
+                model: "assets/test/models/item/wand.json".to_string(),
+                texture: "assets/test/textures/item/gone.png".to_string(),
+            }]
+        );
+        assert_eq!(
+            analysis.duplicate_contents,
+            [DuplicateContent {
+                paths: vec![
+                    "assets/test/textures/item/copy.png".to_string(),
+                    "assets/test/textures/item/wand.png".to_string(),
+                ],
+                size: 3,
+            }]
+        );
+        assert!(analysis.identical_overlay_files.is_empty());
+        assert!(analysis.has_errors());
+
+        // An uppercase path alone is only a warning, an illegal character is an error
+        let analysis = analyze(&[PackInput::Dir(a.clone())], &opts)?;
+        assert_eq!(analysis.path_lints.len(), 1);
+        assert!(!analysis.has_errors());
+        write(a.join("assets/test/a:b.txt"), "x")?;
+        assert!(analyze(&[PackInput::Dir(a)], &opts)?.has_errors());
+        Ok(())
+    }
+
+    #[test]
+    fn non_png_icon_is_kept_only_without_validation() -> anyhow::Result<()> {
+        let mut opts = MergeOptions {
+            extra_files: vec![("pack.png".to_string(), b"GIF89a odd icon".to_vec())],
+            keep_input_pack_png: true,
+            ..Default::default()
+        };
+        let out = merge_packs_to_bytes_with_options(&[], &opts)?;
+        assert_eq!(zip_entries(&out)?["pack.png"], default_pack_png_bytes());
+
+        opts.validate_pack_png = false;
+        let out = merge_packs_to_bytes_with_options(&[], &opts)?;
+        assert_eq!(zip_entries(&out)?["pack.png"], b"GIF89a odd icon");
+        Ok(())
+    }
+
+    #[test]
+    fn deny_namespaces_drops_listed_namespaces() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        for path in [
+            "assets/minecraft/a.txt",
+            "assets/somemod/b.txt",
+            "data/somemod/c.json",
+            "data/other/d.json",
+            "overlay_1/assets/somemod/e.txt",
+        ] {
+            let path = pack.join(path);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, "x")?;
+        }
+        let packs = vec![PackInput::Dir(pack)];
+        let mut opts = MergeOptions {
+            deny_namespaces: vec!["somemod".to_string()],
+            ..Default::default()
+        };
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        let kept: Vec<&str> = entries
+            .keys()
+            .map(|k| k.as_str())
+            .filter(|k| k.contains('/'))
+            .collect();
+        assert_eq!(kept, ["assets/minecraft/a.txt", "data/other/d.json"]);
+
+        // The allowlist applies first, then the deny-list
+        opts.namespaces = Some(vec!["minecraft".to_string(), "somemod".to_string()]);
+        let entries = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert!(entries.contains_key("assets/minecraft/a.txt"));
+        assert!(!entries
+            .keys()
+            .any(|k| k.contains("somemod") || k.contains("other")));
+        Ok(())
+    }
 
-    for overlay_val in overlays_list {
-        if let Some(entries_arr) = overlay_val.get("entries").and_then(|v| v.as_array()) {
-            for entry in entries_arr {
-                if let Some(dir) = entry.get("directory").and_then(|v| v.as_str()) {
-                    merged_entries.insert(dir.to_string(), entry.clone());
-                }
+    #[test]
+    fn merge_and_split_separates_assets_and_data() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let (mixed, datapack) = (d.path().join("mixed"), d.path().join("datapack"));
+        for (dir, format, files) in [
+            (&mixed, 34, &["assets/test/a.txt", "data/test/b.json"][..]),
+            (&datapack, 48, &["data/other/c.json"][..]),
+        ] {
+            for file in files {
+                create_dir_all(dir.join(file).parent().unwrap())?;
+                write(dir.join(file), "x")?;
             }
+            write(
+                dir.join("pack.mcmeta"),
+                format!(r#"{{"pack":{{"pack_format":{}}}}}"#, format),
+            )?;
         }
-    }
+        write(mixed.join("LICENSE"), "MIT")?;
 
-    if merged_entries.is_empty() {
-        return None;
+        let (resource, data) = merge_and_split(
+            &[PackInput::Dir(mixed), PackInput::Dir(datapack)],
+            &MergeOptions::default(),
+        )?;
+        let (resource, data) = (zip_entries(&resource)?, zip_entries(&data)?);
+        let names = |entries: &BTreeMap<String, Vec<u8>>| -> Vec<String> {
+            entries.keys().cloned().collect()
+        };
+        assert_eq!(
+            names(&resource),
+            [
+                "LICENSE",
+                "README.md",
+                "assets/test/a.txt",
+                "pack.mcmeta",
+                "pack.png"
+            ]
+        );
+        assert_eq!(
+            names(&data),
+            [
+                "LICENSE",
+                "README.md",
+                "data/other/c.json",
+                "data/test/b.json",
+                "pack.mcmeta",
+                "pack.png"
+            ]
+        );
+        let format = |entries: &BTreeMap<String, Vec<u8>>| -> anyhow::Result<serde_json::Value> {
+            let meta: serde_json::Value = serde_json::from_slice(&entries["pack.mcmeta"])?;
+            Ok(meta["pack"]["pack_format"].clone())
+        };
+        assert_eq!(format(&resource)?, 34);
+        assert_eq!(format(&data)?, 48);
+        assert_eq!(content_folder("overlay_1/data/x/y.json"), Some("data"));
+        assert_eq!(content_folder("assets/data"), Some("assets"));
+        assert_eq!(content_folder("overlay_1/assets"), None);
+        Ok(())
     }
 
-    // Convert back to array, sorted by directory name for determinism
-    let mut sorted_entries: Vec<_> = merged_entries.into_iter().collect();
-    sorted_entries.sort_by(|a, b| a.0.cmp(&b.0));
-    let entries_array: Vec<serde_json::Value> =
-        sorted_entries.into_iter().map(|(_, v)| v).collect();
+    #[test]
+    fn filename_encoding_decodes_shift_jis_names() -> anyhow::Result<()> {
+        // A zip whose entry name is Shift-JIS bytes without the UTF-8 flag, as old Japanese
+        // zip tools write them
+        let (name, _, _) = encoding_rs::SHIFT_JIS.encode("assets/test/テクスチャ.txt");
+        let mut zw = ZipWriter::new(Cursor::new(Vec::new()));
+        let fo = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+        zw.start_file("assets/test/XXXXXXXXXX.txt", fo)?;
+        zw.write_all(b"x")?;
+        let mut bytes = zw.finish()?.into_inner();
+        // Swap the ASCII placeholder for the Shift-JIS name in the local and central headers;
+        // both are 14 bytes long so no offsets move
+        let placeholder = b"XXXXXXXXXX.txt";
+        let replacement = &name[b"assets/test/".len()..];
+        assert_eq!(placeholder.len(), replacement.len());
+        for start in (0..bytes.len() - placeholder.len()).rev() {
+            if &bytes[start..start + placeholder.len()] == placeholder {
+                bytes[start..start + placeholder.len()].copy_from_slice(replacement);
+            }
+        }
+        let input = [PackInput::ZipBytes(bytes)];
 
-    Some(serde_json::json!({
-        "entries": entries_array
-    }))
-}
+        let garbled = zip_entries(&merge_packs_to_bytes(&input)?)?;
+        assert!(!garbled.contains_key("assets/test/テクスチャ.txt"));
 
-/// Try to extract pack_format and max_format from a pack.mcmeta JSON string.
-/// Returns (pack_format, max_format) where max_format might be higher than pack_format.
-fn extract_pack_format_from_mcmeta(s: &str) -> std::result::Result<(u32, Option<u32>), ()> {
-    // Quick and tolerant parser: look for "pack_format", "max_format", and "supported_formats".
-    // Accept both the common shape { "pack": { ... } } and rare top-level fields.
-    if let Ok(v) = serde_json::from_str::<serde_json::Value>(s) {
-        // helper to extract numeric from a Value
-        let try_from_value = |val: &serde_json::Value| -> Option<u32> {
-            if let Some(n) = val.as_u64() {
-                return Some(n as u32);
-            }
-            if let Some(s) = val.as_str() {
-                if let Ok(n) = s.parse::<u32>() {
-                    return Some(n);
-                }
-            }
-            None
+        let opts = MergeOptions {
+            filename_encoding: Some(encoding_rs::SHIFT_JIS),
+            ..Default::default()
         };
+        let decoded = zip_entries(&merge_packs_to_bytes_with_options(&input, &opts)?)?;
+        assert_eq!(decoded["assets/test/テクスチャ.txt"], b"x");
+        // Listed (not read) entries are decoded too
+        let listed = MergeOptions {
+            preserve_input_compression: true,
+            ..opts.clone()
+        };
+        let decoded = zip_entries(&merge_packs_to_bytes_with_options(&input, &listed)?)?;
+        assert_eq!(decoded["assets/test/テクスチャ.txt"], b"x");
 
-        let mut pack_format = None;
-        let mut max_format = None;
+        let json = serde_json::to_value(&opts)?;
+        assert_eq!(json["filename_encoding"], "Shift_JIS");
+        let back: MergeOptions = serde_json::from_value(json)?;
+        assert_eq!(back.filename_encoding, Some(encoding_rs::SHIFT_JIS));
+        Ok(())
+    }
 
-        // Check common shape: { "pack": { ... } }
-        if let Some(pack) = v.get("pack") {
-            if let Some(fmt) = pack.get("pack_format") {
-                pack_format = try_from_value(fmt);
-            }
+    #[test]
+    fn sync_to_dir_updates_in_place() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test/nested"))?;
+        write(pack.join("assets/test/keep.txt"), "keep")?;
+        write(pack.join("assets/test/change.txt"), "old")?;
+        write(pack.join("assets/test/nested/gone.txt"), "gone")?;
+        let out = d.path().join("server_pack");
+        let packs = vec![PackInput::Dir(pack.clone())];
+        let opts = MergeOptions::default();
 
-            // Look for explicit max_format
-            if let Some(mf) = pack.get("max_format") {
-                max_format = try_from_value(mf);
-            }
+        sync_to_dir(&packs, &out, &opts)?;
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/change.txt"))?,
+            "old"
+        );
+        assert!(out.join("assets/test/nested/gone.txt").is_file());
+        let kept_mtime = std::fs::metadata(out.join("assets/test/keep.txt"))?.modified()?;
 
-            // Also check supported_formats for max_inclusive
-            if let Some(sf) = pack.get("supported_formats") {
-                if let Some(obj) = sf.as_object() {
-                    if let Some(max_inc) = obj.get("max_inclusive") {
-                        if let Some(n) = try_from_value(max_inc) {
-                            max_format = Some(n);
-                        }
-                    }
-                }
-            }
-        }
+        // Files the sync didn't write are never deleted
+        write(out.join("server.properties"), "motd=hi")?;
+        write(out.join("assets/test/nested/mine.txt"), "mine")?;
+        std::fs::remove_dir_all(pack.join("assets/test/nested"))?;
+        write(pack.join("assets/test/change.txt"), "new")?;
+        write(pack.join("assets/test/added.txt"), "added")?;
+        sync_to_dir(&packs, &out, &opts)?;
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/change.txt"))?,
+            "new"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/added.txt"))?,
+            "added"
+        );
+        assert!(!out.join("assets/test/nested/gone.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.join("assets/test/nested/mine.txt"))?,
+            "mine"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("server.properties"))?,
+            "motd=hi"
+        );
 
-        // Fallback: check top-level pack_format and max_format
-        if pack_format.is_none() {
-            if let Some(fmt) = v.get("pack_format") {
-                pack_format = try_from_value(fmt);
-            }
-        }
-        if max_format.is_none() {
-            if let Some(mf) = v.get("max_format") {
-                max_format = try_from_value(mf);
-            }
-        }
+        // Once the last produced file in it is gone, the directory goes too
+        std::fs::remove_file(out.join("assets/test/nested/mine.txt"))?;
+        create_dir_all(pack.join("assets/test/nested"))?;
+        write(pack.join("assets/test/nested/gone.txt"), "back")?;
+        sync_to_dir(&packs, &out, &opts)?;
+        std::fs::remove_dir_all(pack.join("assets/test/nested"))?;
+        sync_to_dir(&packs, &out, &opts)?;
+        assert!(!out.join("assets/test/nested").exists());
+        assert_eq!(
+            std::fs::metadata(out.join("assets/test/keep.txt"))?.modified()?,
+            kept_mtime
+        );
+        assert!(out.join("pack.mcmeta").is_file());
 
-        if let Some(pf) = pack_format {
-            return Ok((pf, max_format));
-        }
+        write(pack.join(".resource_merger-sync"), "assets/test/keep.txt\n")?;
+        let err = sync_to_dir(&packs, &out, &opts).unwrap_err();
+        assert!(matches!(err, MergeError::InvalidInput(m) if m.contains(".resource_merger-sync")));
+        assert!(out.join("assets/test/keep.txt").is_file());
+        Ok(())
     }
-    Err(())
-}
 
-fn make_pack_mcmeta(
-    pack_format: u32,
-    supported_formats: &[u32],
-    description: Option<&str>,
-    max_format: u32,
-    overlays: Option<&serde_json::Value>,
-) -> String {
-    let desc = description.map(|s| s.to_string()).unwrap_or_else(|| {
-        format!(
-            "Made with Rust API: resource_merger:{}",
-            env!("CARGO_PKG_VERSION")
-        )
-    });
+    #[test]
+    fn registered_reader_loads_custom_format() -> anyhow::Result<()> {
+        // A toy format: one `path=contents` line per file
+        register_reader(
+            "mypack",
+            Box::new(|path: &Path| {
+                let text = std::fs::read_to_string(path)?;
+                Ok(text
+                    .lines()
+                    .filter_map(|l| l.split_once('='))
+                    .map(|(k, v)| (k.to_string(), v.as_bytes().to_vec()))
+                    .collect())
+            }),
+        );
+        let d = tempdir()?;
+        let custom = d.path().join("a.MyPack");
+        write(
+            &custom,
+            "pack.mcmeta={\"pack\":{\"pack_format\":15}}\nassets/test/a.txt=custom\n../evil.txt=x\n",
+        )?;
 
-    // Threshold for backwards compatibility: resource pack format < 65 requires old format
-    const OLD_FORMAT_THRESHOLD: u32 = 65;
+        let packs = [PackInput::ZipFile(custom)];
+        let entries = zip_entries(&merge_packs_to_bytes(&packs)?)?;
+        assert_eq!(entries["assets/test/a.txt"], b"custom");
+        assert!(!entries.keys().any(|k| k.contains("evil")));
+        let meta: serde_json::Value = serde_json::from_slice(&entries["pack.mcmeta"])?;
+        assert_eq!(meta["pack"]["pack_format"], 15);
 
-    // Determine min from supported_formats array
-    let min_format = supported_formats.first().copied().unwrap_or(pack_format);
+        // The read-only helpers open it through the same reader
+        assert_eq!(classify_input(&packs[0])?, PackClassification::ResourcePack);
+        assert_eq!(
+            read_mcmeta(&packs[0])?.as_deref(),
+            Some(r#"{"pack":{"pack_format":15}}"#)
+        );
+        let merged = compute_merged_mcmeta(&packs, &MergeOptions::default())?;
+        assert_eq!(merged["pack"]["pack_format"], 15);
+        assert_eq!(scan_formats(&packs)[0].pack_format, Some(15));
+        Ok(())
+    }
 
-    // Check if we need backwards compatibility fields (if min_format < 65)
-    let needs_old_format = min_format < OLD_FORMAT_THRESHOLD;
+    #[test]
+    fn minify_text_compacts_json_and_trims_lines() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test/models"))?;
+        let pretty = "{\n  \"parent\": \"block/cube_all\",\n  \"textures\": {\n    \"all\": \"test:a\"\n  }\n}\n";
+        write(pack.join("assets/test/models/a.json"), pretty)?;
+        write(pack.join("assets/test/broken.json"), "{ not json ")?;
+        write(
+            pack.join("assets/test/en_us.lang"),
+            "a.b=Hello   \r\nc.d=World\t\n",
+        )?;
+        write(pack.join("assets/test/image.png"), b"\x89PNG  \n  ")?;
+        let packs = [PackInput::Dir(pack)];
 
-    let mut meta = if needs_old_format {
-        // Old format: include pack_format and supported_formats for backwards compatibility
-        serde_json::json!({
-            "pack": {
-                "pack_format": pack_format,
-                "min_format": min_format,
-                "max_format": max_format,
-                "description": desc,
-                "supported_formats": supported_formats
-            }
-        })
-    } else {
-        // New format (1.21.9+): use min_format and max_format only
-        serde_json::json!({
-            "pack": {
-                "min_format": min_format,
-                "max_format": max_format,
-                "description": desc
-            }
-        })
-    };
+        let opts = MergeOptions {
+            minify_text: true,
+            ..Default::default()
+        };
+        let out = zip_entries(&merge_packs_to_bytes_with_options(&packs, &opts)?)?;
+        assert_eq!(
+            out["assets/test/models/a.json"],
+            br#"{"parent":"block/cube_all","textures":{"all":"test:a"}}"#
+        );
+        assert_eq!(out["assets/test/broken.json"], b"{ not json ");
+        assert_eq!(out["assets/test/en_us.lang"], b"a.b=Hello\nc.d=World\n");
+        assert_eq!(out["assets/test/image.png"], b"\x89PNG  \n  ");
 
-    // Add overlays if present
-    if let Some(overlays_val) = overlays {
-        if let Some(obj) = meta.as_object_mut() {
-            obj.insert("overlays".to_string(), overlays_val.clone());
+        let plain = zip_entries(&merge_packs_to_bytes(&packs)?)?;
+        assert_eq!(plain["assets/test/models/a.json"], pretty.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_writes_leave_no_temp_artifacts() -> anyhow::Result<()> {
+        assert_eq!(MergeError::Cancelled.to_string(), "merge was cancelled");
+        let bytes = merge_packs_to_bytes(&[])?;
+        let opts = MergeOptions {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+
+        let d = tempdir()?;
+        let res = write_output_file_with(&d.path().join("out.zip"), &opts, |f| {
+            Ok(f.write_all(&bytes)?)
+        });
+        assert!(matches!(res, Err(MergeError::Cancelled)));
+        let res = write_output_dir(&d.path().join("out_dir"), Cursor::new(bytes), &opts);
+        assert!(matches!(res, Err(MergeError::Cancelled)));
+        assert_eq!(std::fs::read_dir(d.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_tar_bytes_input() -> anyhow::Result<()> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, body) in [
+            ("pack.mcmeta", &br#"{"pack":{"pack_format":22}}"#[..]),
+            ("assets/test/from_tar.txt", &b"tar"[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, body)?;
         }
+        let tar_bytes = builder.into_inner()?;
+
+        let packs = vec![PackInput::TarBytes(tar_bytes)];
+        assert_eq!(scan_formats(&packs)[0].pack_format, Some(22));
+        let out = merge_packs_to_bytes(&packs)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        let mut s = String::new();
+        archive
+            .by_name("assets/test/from_tar.txt")?
+            .read_to_string(&mut s)?;
+        assert_eq!(s, "tar");
+        let meta: serde_json::Value = serde_json::from_reader(archive.by_name("pack.mcmeta")?)?;
+        assert_eq!(meta["pack"]["pack_format"], 22);
+        Ok(())
     }
 
-    // Use compact JSON (single-line) for smaller file size - Minecraft supports this
-    serde_json::to_string(&meta).unwrap_or_else(|_| {
-        "{\"pack\":{\"min_format\":1,\"max_format\":1,\"description\":\"resource_merger\"}}"
-            .to_string()
-    })
-}
+    #[test]
+    fn settings_serialize_to_json() -> anyhow::Result<()> {
+        let settings = Settings {
+            inputs: vec![
+                PackInput::Url("https://example.com/p.zip".to_string()),
+                PackInput::ZipBytes(b"PK".to_vec()),
+            ],
+            out: PathBuf::from("merged.zip"),
+            dir: false,
+            also_emit_zip: None,
+            options: MergeOptions {
+                overwrite: OverwritePolicy::FirstWins,
+                ..Default::default()
+            },
+        };
+        let v = serde_json::to_value(&settings)?;
+        assert_eq!(
+            v["inputs"],
+            serde_json::json!([
+                { "type": "url", "value": "https://example.com/p.zip" },
+                { "type": "zip-bytes", "value": "UEs=" },
+            ])
+        );
+        assert_eq!(v["options"]["overwrite"], "first");
+        assert_eq!(v["options"]["supported_formats_policy"], "one-to-highest");
+        assert!(v["options"].get("cancel").is_none());
+        Ok(())
+    }
 
-fn default_pack_png_bytes() -> Vec<u8> {
-    // Include the default 64x64 pack image binary at compile time. This uses the
-    // provided PNG file `assets/default-pack-64.png` and embeds its bytes into
-    // the binary so we can always write `pack.png` when inputs don't provide one.
-    const BYTES: &[u8] = include_bytes!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/assets/default-pack-64.png"
-    ));
-    BYTES.to_vec()
-}
+    #[test]
+    fn merge_options_round_trip() -> anyhow::Result<()> {
+        let mut opts = MergeOptions {
+            overwrite: OverwritePolicy::ErrorIfConflict,
+            dry_run: true,
+            buffer_size: 4096,
+            pack_format_override: Some(34),
+            supported_formats_policy: SupportedFormatsPolicy::LowestToHighest,
+            description_override: Some("desc".to_string()),
+            namespaces: Some(vec!["minecraft".to_string()]),
+            flatten_overlays_to: Some(46),
+            ..Default::default()
+        };
+        opts.zip_passwords.insert(2, "pw".to_string());
 
-fn make_readme(packs: &[PackInput]) -> String {
-    let mut out = String::new();
-    out.push_str("This resource pack was generated by resource_merger.\n\n");
-    out.push_str("Inputs used (in order, first -> last):\n");
-    for p in packs {
-        match p {
-            PackInput::Dir(pb) => {
-                out.push_str(&format!("- Dir: {}\n", pb.display()));
-            }
-            PackInput::ZipFile(pb) => {
-                out.push_str(&format!("- ZipFile: {}\n", pb.display()));
-            }
-            PackInput::ZipBytes(_) => {
-                out.push_str("- ZipBytes: <in-memory>\n");
-            }
-            PackInput::Url(u) => {
-                out.push_str(&format!("- Url: {}\n", u));
-            }
+        let json = serde_json::to_string(&opts)?;
+        let back: MergeOptions = serde_json::from_str(&json)?;
+        assert_eq!(serde_json::to_string(&back)?, json);
+        assert_eq!(back.overwrite, OverwritePolicy::ErrorIfConflict);
+        assert_eq!(back.zip_passwords.get(&2).map(|s| s.as_str()), Some("pw"));
+
+        let settings: Settings = serde_json::from_str(
+            r#"{ "inputs": [{ "type": "tar-bytes", "value": "UEs=" }], "out": "o.zip" }"#,
+        )?;
+        assert!(matches!(&settings.inputs[0], PackInput::TarBytes(b) if b == b"PK"));
+        assert_eq!(settings.options.buffer_size, 32 * 1024);
+        Ok(())
+    }
+
+    #[test]
+    fn policy_display_round_trips() {
+        for p in [
+            OverwritePolicy::LastWins,
+            OverwritePolicy::FirstWins,
+            OverwritePolicy::ErrorIfConflict,
+            OverwritePolicy::SkipIfExists,
+        ] {
+            assert_eq!(p.to_string().parse::<OverwritePolicy>(), Ok(p));
+            assert_eq!(serde_json::to_value(p).unwrap(), p.to_string());
+        }
+        for p in [
+            SupportedFormatsPolicy::OneToHighest,
+            SupportedFormatsPolicy::LowestToHighest,
+            SupportedFormatsPolicy::OneToLatest,
+        ] {
+            assert_eq!(p.to_string().parse::<SupportedFormatsPolicy>(), Ok(p));
+            assert_eq!(serde_json::to_value(p).unwrap(), p.to_string());
         }
     }
-    out.push_str(&format!(
-        "\nGenerated with resource_merger {}",
-        env!("CARGO_PKG_VERSION")
-    ));
-    out
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::{create_dir_all, write};
-    use tempfile::tempdir;
+    #[test]
+    fn per_extension_compression_stores_png() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let pack = d.path().join("pack");
+        create_dir_all(pack.join("assets/test"))?;
+        write(pack.join("assets/test/tex.PNG"), default_pack_png_bytes())?;
+        write(pack.join("assets/test/model.json"), "{}".repeat(100))?;
+
+        let mut opts = MergeOptions::default();
+        opts.per_extension_compression
+            .insert("png".to_string(), CompressionChoice::Stored);
+        opts.per_extension_compression
+            .insert("json".to_string(), CompressionChoice::Deflated(Some(9)));
+        let out = merge_packs_to_bytes_with_options(&[PackInput::Dir(pack)], &opts)?;
+        let mut archive = ZipArchive::new(Cursor::new(out))?;
+        assert_eq!(
+            archive.by_name("assets/test/tex.PNG")?.compression(),
+            zip::CompressionMethod::Stored
+        );
+        assert_eq!(
+            archive.by_name("pack.png")?.compression(),
+            zip::CompressionMethod::Stored
+        );
+        assert_eq!(
+            archive.by_name("assets/test/model.json")?.compression(),
+            zip::CompressionMethod::Deflated
+        );
+        Ok(())
+    }
 
     #[test]
-    fn merge_dirs_and_zipbytes() -> anyhow::Result<()> {
-        let d1 = tempdir()?;
-        let base = d1.path().join("base");
+    fn streaming_and_in_memory_paths_match() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let base = d.path().join("base");
         create_dir_all(base.join("assets/test"))?;
-        write(base.join("assets/test/a.txt"), b"hello")?;
-        write(base.join("assets/test/only_in_base.txt"), b"base")?;
-
-        let d2 = tempdir()?;
-        let override_dir = d2.path().join("over");
-        create_dir_all(override_dir.join("assets/test"))?;
-        write(override_dir.join("assets/test/a.txt"), b"world")?;
+        create_dir_all(base.join("data/test/tags/function"))?;
+        write(base.join("pack.mcmeta"), r#"{"pack":{"pack_format":34}}"#)?;
+        write(base.join("assets/test/a.txt"), b"base")?;
+        write(base.join("assets/test/b.txt"), b"only base")?;
+        write(
+            base.join("data/test/tags/function/load.json"),
+            r#"{"values":["test:a"]}"#,
+        )?;
 
-        // create an in-memory zip with another file
-        let mut cursor = Cursor::new(Vec::new());
+        let zip_path = d.path().join("over.zip");
         {
-            let mut zw = ZipWriter::new(&mut cursor);
-            zw.start_file(
-                "assets/test/b.txt",
-                zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default(),
-            )?;
-            zw.write_all(b"fromzip")?;
+            let mut zw = ZipWriter::new(File::create(&zip_path)?);
+            let o = zip::write::FileOptions::<zip::write::ExtendedFileOptions>::default();
+            zw.start_file("pack.mcmeta", o.clone())?;
+            zw.write_all(br#"{"pack":{"pack_format":46}}"#)?;
+            zw.start_file("assets/test/a.txt", o.clone())?;
+            zw.write_all(b"over")?;
+            zw.start_file("data/test/tags/function/load.json", o)?;
+            zw.write_all(br#"{"values":["test:b"]}"#)?;
             zw.finish()?;
         }
+        let packs = vec![PackInput::Dir(base), PackInput::ZipFile(zip_path)];
 
-        let bytes = cursor.into_inner();
+        let mut outputs = Vec::new();
+        for threshold in [None, Some(0)] {
+            let opts = MergeOptions {
+                merge_tags: true,
+                streaming_threshold_bytes: threshold,
+                ..Default::default()
+            };
+            assert_eq!(use_streaming(&packs, &opts), threshold.is_some());
+            let out = d.path().join(format!("out_{}.zip", threshold.is_some()));
+            merge_packs_to_file_with_options(&packs, &out, &opts)?;
+            outputs.push(zip_entries(&std::fs::read(out)?)?);
+        }
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0]["assets/test/a.txt"], b"over");
+        Ok(())
+    }
 
-        let packs = vec![
-            PackInput::Dir(base),
-            PackInput::Dir(override_dir),
-            PackInput::ZipBytes(bytes),
-        ];
+    #[test]
+    fn recursive_folder_finds_nested_packs() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let root = d.path();
+        let mcmeta = br#"{"pack":{"pack_format":34,"description":""}}"#;
+        for (pack, file) in [
+            ("a/one", "assets/test/one.txt"),
+            ("b/c/two", "assets/test/two.txt"),
+        ] {
+            let dir = root.join(pack);
+            create_dir_all(dir.join("assets/test"))?;
+            write(dir.join("pack.mcmeta"), mcmeta)?;
+            write(dir.join(file), pack)?;
+        }
+        write(root.join("b/notes.txt"), b"not a pack")?;
 
-        let out = merge_packs_to_bytes(&packs)?;
-        // open output and assert contents
-        let mut archive = ZipArchive::new(Cursor::new(out))?;
-        {
-            let mut a = archive.by_name("assets/test/a.txt")?;
-            let mut s = String::new();
-            a.read_to_string(&mut s)?;
-            assert_eq!(s, "world"); // overridden by second pack
+        let mut packs = Vec::new();
+        collect_packs_in_folder(root, true, &mut packs)?;
+        let dirs: Vec<PathBuf> = packs
+            .iter()
+            .map(|p| match p {
+                PackInput::Dir(p) => p.clone(),
+                other => panic!("unexpected input {:?}", other),
+            })
+            .collect();
+        assert_eq!(dirs, vec![root.join("a/one"), root.join("b/c/two")]);
+
+        let out = merge_packs_in_folder(root, true)?;
+        let archive = ZipArchive::new(Cursor::new(out))?;
+        assert!(archive.index_for_name("assets/test/one.txt").is_some());
+        assert!(archive.index_for_name("assets/test/two.txt").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn force_top_input_beats_later_input() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        for (name, body) in [("branding", "branding"), ("normal", "normal")] {
+            create_dir_all(d.path().join(name).join("assets/test"))?;
+            write(d.path().join(name).join("assets/test/logo.txt"), body)?;
         }
+        let cfg_path = d.path().join("merge.json");
+        write(
+            &cfg_path,
+            r#"{ "inputs": [{ "path": "branding", "force_top": true }, "normal"] }"#,
+        )?;
 
-        {
-            let mut b = archive.by_name("assets/test/b.txt")?;
-            let mut s2 = String::new();
-            b.read_to_string(&mut s2)?;
-            assert_eq!(s2, "fromzip");
+        let packs = read_config_file(&cfg_path)?.pack_inputs(Vec::new())?;
+        let out = merge_packs_to_bytes(&packs)?;
+        assert_eq!(zip_entries(&out)?["assets/test/logo.txt"], b"branding");
+        Ok(())
+    }
+
+    #[test]
+    fn for_formats_excludes_out_of_range_input() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        for name in ["base", "legacy"] {
+            create_dir_all(d.path().join(name).join("assets/test"))?;
+            write(d.path().join(name).join("assets/test/logo.txt"), name)?;
         }
+        let cfg_path = d.path().join("merge.json");
+        write(
+            &cfg_path,
+            r#"{ "inputs": ["base", { "path": "legacy", "for_formats": [15, 18] }],
+                 "target_format": 34 }"#,
+        )?;
+
+        let mut cfg = read_config_file(&cfg_path)?;
+        let packs = cfg.pack_inputs(Vec::new())?;
+        assert_eq!(packs.len(), 1);
+        let out = merge_packs_to_bytes(&packs)?;
+        assert_eq!(zip_entries(&out)?["assets/test/logo.txt"], b"base");
+
+        cfg.target_format = Some(15);
+        assert_eq!(cfg.pack_inputs(Vec::new())?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn example_config_parses_back() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        let path = d.path().join("merge.json");
+        let json = Config::example_json();
+        assert!(json.contains("\"// overwrite\""));
+        write(&path, json)?;
+        let cfg = read_config_file(&path)?;
+        assert_eq!(cfg.inputs.map(|i| i.len()), Some(3));
+        assert_eq!(cfg.overwrite.as_deref(), Some("last"));
+        assert_eq!(cfg.buffer_size, Some(32 * 1024));
+        Ok(())
+    }
+
+    #[test]
+    fn config_into_options_resolves_fields() -> anyhow::Result<()> {
+        let d = tempdir()?;
+        write(d.path().join("credits.txt"), "thanks")?;
+        write(
+            d.path().join("merge.json"),
+            r#"{
+                "overwrite": "first",
+                "atomic": false,
+                "supported_formats": "lowest-to-highest",
+                "compression": "stored",
+                "namespaces": ["mymod"],
+                "smart_json_merge": true,
+                "add_files": ["credits.txt:credits.txt"],
+                "out": "merged.zip",
+                "dir": true
+            }"#,
+        )?;
+        let cfg = read_config_file(&d.path().join("merge.json"))?;
+
+        let opts = cfg.into_options()?;
+        assert_eq!(opts.overwrite, OverwritePolicy::FirstWins);
+        assert!(!opts.atomic);
+        assert_eq!(opts.buffer_size, 32 * 1024);
+        assert_eq!(
+            opts.supported_formats_policy,
+            SupportedFormatsPolicy::LowestToHighest
+        );
+        assert_eq!(opts.compression, Some(CompressionChoice::Stored));
+        assert_eq!(opts.namespaces, Some(vec!["mymod".to_string()]));
+        assert!(opts.smart_json_merge);
+        assert_eq!(
+            opts.extra_files,
+            vec![("credits.txt".to_string(), b"thanks".to_vec())]
+        );
 
+        let settings = read_config_file(&d.path().join("merge.json"))?.into_settings()?;
+        assert_eq!(settings.out, d.path().join("merged.zip"));
+        assert!(settings.dir && settings.inputs.is_empty());
+
+        let bad = Config {
+            overwrite: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            bad.into_options(),
+            Err(MergeError::InvalidInput(_))
+        ));
+        assert!(Config::default().into_settings().is_err());
         Ok(())
     }
 }
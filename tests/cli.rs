@@ -0,0 +1,178 @@
+use std::fs::{create_dir_all, write};
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn dry_run_fail_on_conflict_exits_with_code_3() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let (a, b) = (d.path().join("a"), d.path().join("b"));
+    for dir in [&a, &b] {
+        create_dir_all(dir.join("assets/minecraft/lang"))?;
+        write(dir.join("assets/minecraft/lang/en_us.json"), "{}")?;
+    }
+    let out = d.path().join("out.zip");
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_merge"))
+            .args(["--dry-run", "--fail-on-conflict", "--out"])
+            .arg(&out)
+            .args(extra)
+            .arg(&a)
+            .arg(&b)
+            .output()
+    };
+
+    let conflicting = run(&[])?;
+    assert_eq!(conflicting.status.code(), Some(3));
+    let stderr = String::from_utf8(conflicting.stderr)?;
+    assert!(stderr.contains("assets/minecraft/lang/en_us.json"));
+
+    let allowed = run(&["--allow-conflict", "assets/*/lang/**"])?;
+    assert_eq!(allowed.status.code(), Some(0));
+    assert!(!out.exists());
+    Ok(())
+}
+
+#[test]
+fn compression_comes_from_config_unless_flag_given() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let config = d.path().join("merge.json");
+    write(&config, r#"{ "compression": "stored", "out": "out.zip" }"#)?;
+    let print = |extra: &[&str]| -> anyhow::Result<serde_json::Value> {
+        let output = Command::new(env!("CARGO_BIN_EXE_merge"))
+            .args(["--print-config", "--config"])
+            .arg(&config)
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    let settings = print(&[])?;
+    assert_eq!(settings["options"]["compression"], "stored");
+
+    let settings = print(&["--compression", "best"])?;
+    assert_eq!(settings["options"]["compression"]["deflated"], 9);
+    Ok(())
+}
+
+#[test]
+fn env_vars_are_lowest_precedence_defaults() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let print = |extra: &[&str]| -> anyhow::Result<serde_json::Value> {
+        let output = Command::new(env!("CARGO_BIN_EXE_merge"))
+            .env("RESOURCE_MERGER_DESCRIPTION", "from env")
+            .env("RESOURCE_MERGER_PACK_FORMAT", "34")
+            .args(["--print-config", "--out"])
+            .arg(d.path().join("out.zip"))
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    let settings = print(&[])?;
+    assert_eq!(settings["options"]["description_override"], "from env");
+    assert_eq!(settings["options"]["pack_format_override"], 34);
+
+    let settings = print(&["--description", "from flag", "--pack-format", "15"])?;
+    assert_eq!(settings["options"]["description_override"], "from flag");
+    assert_eq!(settings["options"]["pack_format_override"], 15);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn post_merge_command_receives_output_path() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let pack = d.path().join("pack");
+    create_dir_all(pack.join("assets/minecraft"))?;
+    write(pack.join("assets/minecraft/a.txt"), "a")?;
+    let (record, record_env) = (d.path().join("hook.txt"), d.path().join("hook_env.txt"));
+    let config = d.path().join("merge.json");
+    // Spaces, `;`, `$(...)` and quotes in the path must reach the command as one argument
+    let name = "merged pack; $(touch injected) 'x'.zip";
+    write(
+        &config,
+        serde_json::json!({
+            "out": name,
+            "post_merge_command": format!(
+                "printf %s {{out}} > '{}'; printf %s \"$RESOURCE_MERGER_OUT\" > '{}'",
+                record.display(),
+                record_env.display()
+            ),
+        })
+        .to_string(),
+    )?;
+
+    let status = Command::new(env!("CARGO_BIN_EXE_merge"))
+        .current_dir(d.path())
+        .arg("--config")
+        .arg(&config)
+        .arg(&pack)
+        .status()?;
+    assert!(status.success());
+    let out = d.path().join(name);
+    assert!(out.is_file());
+    assert_eq!(std::fs::read_to_string(&record)?, out.display().to_string());
+    assert_eq!(
+        std::fs::read_to_string(&record_env)?,
+        out.display().to_string()
+    );
+    assert!(!d.path().join("injected").exists());
+    Ok(())
+}
+
+#[test]
+fn analyze_reports_problems_and_sets_exit_code() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let (a, b) = (d.path().join("a"), d.path().join("b"));
+    for dir in [&a, &b] {
+        create_dir_all(dir.join("assets/minecraft/lang"))?;
+        write(dir.join("assets/minecraft/lang/en_us.json"), "{}")?;
+    }
+    create_dir_all(b.join("assets/test/models/item"))?;
+    write(
+        b.join("assets/test/models/item/wand.json"),
+        r#"{"textures":{"layer0":"test:item/wand"}}"#,
+    )?;
+    let analyze = |inputs: &[&std::path::Path]| {
+        Command::new(env!("CARGO_BIN_EXE_merge"))
+            .arg("analyze")
+            .args(inputs)
+            .output()
+    };
+
+    let found = analyze(&[&a, &b])?;
+    assert_eq!(found.status.code(), Some(3));
+    let stdout = String::from_utf8(found.stdout)?;
+    assert!(stdout.contains("assets/minecraft/lang/en_us.json"));
+    assert!(stdout.contains("assets/test/textures/item/wand.png"));
+    assert!(!d.path().join("out.zip").exists());
+
+    let clean = analyze(&[&a])?;
+    assert_eq!(clean.status.code(), Some(0));
+    assert!(String::from_utf8(clean.stdout)?.contains("no problems found"));
+    Ok(())
+}
+
+#[test]
+fn config_paths_resolve_from_any_working_directory() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let (packs, elsewhere) = (d.path().join("packs"), d.path().join("elsewhere"));
+    create_dir_all(packs.join("base/assets/minecraft"))?;
+    create_dir_all(&elsewhere)?;
+    write(packs.join("base/assets/minecraft/a.txt"), "a")?;
+    let config = packs.join("merge.json");
+    write(&config, r#"{ "inputs": ["./base"], "out": "merged.zip" }"#)?;
+
+    let status = Command::new(env!("CARGO_BIN_EXE_merge"))
+        .current_dir(&elsewhere)
+        .arg("--config")
+        .arg(&config)
+        .status()?;
+    assert!(status.success());
+    assert!(!elsewhere.join("merged.zip").exists());
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(packs.join("merged.zip"))?)?;
+    assert!(archive.by_name("assets/minecraft/a.txt").is_ok());
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use resource_merger::{merge_packs_to_bytes_with_options, MergeOptions, PackInput};
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_pack(root: &Path, files: &[(&str, &str)]) -> anyhow::Result<()> {
+    for (path, body) in files {
+        let path = root.join(path);
+        create_dir_all(path.parent().unwrap())?;
+        write(path, body)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn repeated_merges_are_byte_identical() -> anyhow::Result<()> {
+    let d = tempdir()?;
+    let (a, b) = (d.path().join("a"), d.path().join("b"));
+    write_pack(
+        &a,
+        &[
+            (
+                "pack.mcmeta",
+                r#"{"pack":{"pack_format":15,"description":"a"}}"#,
+            ),
+            (
+                "assets/minecraft/lang/en_us.json",
+                r#"{"z.key":"A","a.key":"A"}"#,
+            ),
+            ("assets/minecraft/textures/block/stone.png", "stone-a"),
+            (
+                "assets/alpha/models/item/wand.json",
+                r#"{"parent":"item/generated"}"#,
+            ),
+            (
+                "data/minecraft/tags/blocks/logs.json",
+                r#"{"values":["a:log"]}"#,
+            ),
+            ("ov/assets/minecraft/textures/block/dirt.png", "dirt-a"),
+        ],
+    )?;
+    write_pack(
+        &b,
+        &[
+            (
+                "pack.mcmeta",
+                r#"{"pack":{"pack_format":18,"description":"b"}}"#,
+            ),
+            ("assets/minecraft/lang/en_us.json", r#"{"m.key":"B"}"#),
+            ("assets/minecraft/textures/block/stone.png", "stone-b"),
+            (
+                "assets/beta/sounds.json",
+                r#"{"hit":{"sounds":["beta:hit"]}}"#,
+            ),
+            (
+                "data/minecraft/tags/blocks/logs.json",
+                r#"{"values":["b:log"]}"#,
+            ),
+        ],
+    )?;
+    let packs = [PackInput::Dir(a), PackInput::Dir(b)];
+    let opts = MergeOptions {
+        fixed_timestamps: true,
+        extra_files: vec![
+            ("credits.txt".to_string(), b"thanks".to_vec()),
+            ("LICENSE".to_string(), b"MIT".to_vec()),
+        ],
+        ..MergeOptions::default()
+    };
+
+    let first = merge_packs_to_bytes_with_options(&packs, &opts)?;
+    for run in 1..50 {
+        let again = merge_packs_to_bytes_with_options(&packs, &opts)?;
+        assert!(again == first, "merge {run} differs from the first");
+    }
+    Ok(())
+}